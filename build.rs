@@ -1,7 +1,62 @@
+use std::{env, fmt::Write, fs, path::{Path, PathBuf}};
+
+#[allow(dead_code)]
+#[path = "src/engine/shader_manifest.rs"]
+mod shader_manifest;
+#[path = "scripts/shader_compiler.rs"]
+mod shader_compiler;
+
+use shader_manifest::{ShaderManifest, SHADER_MANIFEST_FILE};
+
 fn main() {
   #[cfg(target_os="macos")]
   println!("cargo:rustc-link-arg=-Wl,-rpath,@loader_path");
 
   #[cfg(target_os="linux")]
   println!("cargo:rustc-link-arg=-Wl,-rpath,$ORIGIN");
+
+  embed_shaders();
+}
+
+/// Compiles every variant in the shader manifest into OUT_DIR and writes a table that `ShaderLibrary` includes, so
+/// the binary carries its shaders. Without glslc the precompiled variants in shader/ are embedded instead.
+fn embed_shaders() {
+  let shader_directory = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("shader");
+  let out_directory = PathBuf::from(env::var("OUT_DIR").unwrap());
+  println!("cargo:rerun-if-changed={}", shader_directory.display());
+  println!("cargo:rerun-if-changed=scripts/shader_compiler.rs");
+
+  let manifest = ShaderManifest::load(&shader_directory).expect("Failed to load shader manifest");
+  let use_glslc = shader_compiler::glslc_available();
+  if !use_glslc {
+    println!("cargo:warning=glslc was not found, embedding the precompiled shaders from shader/ instead");
+  }
+
+  let mut table = String::new();
+  writeln!(table, "pub const EMBEDDED_MANIFEST: &str = include_str!({:?});", shader_directory.join(SHADER_MANIFEST_FILE).display().to_string()).unwrap();
+  writeln!(table, "pub const EMBEDDED_SHADERS: &[(&str, &[u8])] = &[").unwrap();
+  for permutation in manifest.permutations.iter() {
+    let output_path = out_directory.join("shader").join(permutation.output_path());
+    if use_glslc {
+      shader_compiler::compile_permutation(&shader_directory, permutation, &output_path)
+        .unwrap_or_else(|error| panic!("Failed to compile shader {}: {}", permutation.key, error));
+    } else {
+      let precompiled_path = shader_directory.join(permutation.output_path());
+      // a missing variant would only surface as a runtime error, so it fails the build instead
+      if !precompiled_path.exists() {
+        panic!(
+          "Shader variant {} is not precompiled, install glslc or run `cargo run --bin build_shaders` where it is available",
+          permutation.key
+        );
+      }
+      fs::create_dir_all(output_path.parent().unwrap()).unwrap();
+      fs::copy(&precompiled_path, &output_path).unwrap();
+    }
+
+    let name = permutation.output_path().to_string_lossy().replace('\\', "/");
+    writeln!(table, "    ({:?}, include_bytes!({:?})),", name, output_path.display().to_string()).unwrap();
+  }
+  writeln!(table, "];").unwrap();
+
+  fs::write(out_directory.join("embedded_shaders.rs"), table).unwrap();
 }
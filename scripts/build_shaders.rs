@@ -1,24 +1,26 @@
+#[allow(dead_code)]
+#[path = "../src/engine/shader_manifest.rs"]
+mod shader_manifest;
+#[allow(dead_code)]
+#[path = "shader_compiler.rs"]
+mod shader_compiler;
+#[cfg(test)]
+#[path = "../src/engine/test_directory.rs"]
+mod test_directory;
+
+use shader_compiler::compile_permutation;
+use shader_manifest::ShaderManifest;
+
+/// Refreshes the precompiled variants in shader/, builds without glslc embed those instead of compiling
 pub fn main() {
     let project_base_path = std::env::var("CARGO_MANIFEST_DIR").unwrap();
-    let project_base_path = std::path::Path::new(&project_base_path);
-
-    let relative_shader_files = [
-        ("shader/texture/texture.vert", "shader/texture/vert.spv"),
-        ("shader/texture/texture.frag", "shader/texture/frag.spv"),
-    ];
+    let shader_directory = std::path::Path::new(&project_base_path).join("shader");
 
-    let absolute_shader_files = relative_shader_files
-        .iter()
-        .map(|(src, dst)| (project_base_path.join(src), project_base_path.join(dst)))
-        .collect::<Vec<_>>();
+    let manifest = ShaderManifest::load(&shader_directory).expect("Failed to load shader manifest");
 
-    for (src, dst) in absolute_shader_files.iter() {
-        println!("cargo:rerun-if-changed={}", src.to_str().unwrap());
-        std::process::Command::new("glslc")
-            .arg(src)
-            .arg("-o")
-            .arg(dst)
-            .status()
-            .expect("Failed to compile shader");
+    for permutation in manifest.permutations.iter() {
+        let output_path = shader_directory.join(permutation.output_path());
+        compile_permutation(&shader_directory, permutation, &output_path).expect("Failed to compile shader");
+        println!("compiled {} -> {}", permutation.key, output_path.display());
     }
 }
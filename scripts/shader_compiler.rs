@@ -0,0 +1,154 @@
+use std::{collections::HashSet, error::Error, fs, io::Write, path::{Path, PathBuf}, process::{Command, Stdio}};
+
+use crate::shader_manifest::ShaderPermutation;
+
+// Shared between build.rs and scripts/build_shaders.rs, both declare `shader_manifest` next to this module
+
+/// Quoted for a `#line` directive, forward slashes so paths read the same on every platform
+fn line_file_name(path: &Path) -> String {
+    format!("{:?}", path.to_string_lossy().replace('\\', "/"))
+}
+
+/// Inlines `#include "path"` directives, paths are relative to the shader directory, each file is included once and
+/// include cycles are errors. `#line` directives mark every include boundary so glslc reports errors against the
+/// original file and line.
+pub fn preprocess_includes(shader_directory: &Path, source: &Path, included: &mut HashSet<PathBuf>, include_stack: &mut Vec<PathBuf>) -> Result<String, Box<dyn Error>> {
+    include_stack.push(source.to_path_buf());
+
+    let text = fs::read_to_string(shader_directory.join(source))
+        .map_err(|error| format!("Unable to read shader {}: {}", source.display(), error))?;
+    let mut output = String::with_capacity(text.len());
+
+    for (line_index, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if let Some(directive) = trimmed.strip_prefix("#include") {
+            let include_path = directive
+                .trim()
+                .strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+                .ok_or_else(|| format!("{}:{}: malformed #include, expected #include \"path\"", source.display(), line_index + 1))?;
+            let include_path = PathBuf::from(include_path);
+
+            // checked before the include once set, which would otherwise quietly drop the file that closes the cycle
+            if include_stack.contains(&include_path) {
+                return Err(format!("{}:{}: include cycle through {}", source.display(), line_index + 1, include_path.display()).into());
+            }
+            if included.insert(include_path.clone()) {
+                output.push_str(&format!("#line 1 {}\n", line_file_name(&include_path)));
+                output.push_str(&preprocess_includes(shader_directory, &include_path, included, include_stack)?);
+                output.push_str(&format!("#line {} {}\n", line_index + 2, line_file_name(source)));
+            } else {
+                // keeps the following lines numbered as in the file
+                output.push('\n');
+            }
+            continue;
+        }
+
+        output.push_str(line);
+        output.push('\n');
+
+        // named #line directives need the extension, which can only follow #version
+        if trimmed.starts_with("#version") {
+            output.push_str("#extension GL_GOOGLE_include_directive : require\n");
+            output.push_str(&format!("#line {} {}\n", line_index + 2, line_file_name(source)));
+        }
+    }
+
+    include_stack.pop();
+    Ok(output)
+}
+
+/// Whether glslc from the Vulkan SDK is on the path
+pub fn glslc_available() -> bool {
+    Command::new("glslc").arg("--version").stdout(Stdio::null()).stderr(Stdio::null()).status().is_ok()
+}
+
+pub fn compile_permutation(shader_directory: &Path, permutation: &ShaderPermutation, output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let source_path = PathBuf::from(&permutation.key.source);
+    let source = preprocess_includes(shader_directory, &source_path, &mut HashSet::new(), &mut Vec::new())?;
+    if let Some(output_directory) = output_path.parent() {
+        fs::create_dir_all(output_directory)?;
+    }
+
+    let mut command = Command::new("glslc");
+    command
+        .arg(format!("-fshader-stage={}", permutation.stage()))
+        .args(permutation.defines.iter().map(|define| format!("-D{}", define)))
+        .arg("-")
+        .arg("-o")
+        .arg(output_path)
+        .stdin(Stdio::piped());
+
+    let mut child = command.spawn().map_err(|error| format!("Failed to run glslc: {}", error))?;
+    child.stdin.take().unwrap().write_all(source.as_bytes())?;
+    let status = child.wait()?;
+
+    if !status.success() {
+        return Err(format!("glslc failed for {}", permutation.key).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_directory::TestDirectory;
+
+    fn preprocess(directory: &TestDirectory, source: &str) -> Result<String, Box<dyn Error>> {
+        preprocess_includes(directory.path(), Path::new(source), &mut HashSet::new(), &mut Vec::new())
+    }
+
+    #[test]
+    fn includes_are_inlined_with_line_directives() {
+        let directory = TestDirectory::with_files("shader-inline", &[
+            ("main.frag", "#version 450\n#include \"common/a.glsl\"\nvoid main() {}\n"),
+            ("common/a.glsl", "float a() { return 1.0; }\n"),
+        ]);
+        let output = preprocess(&directory, "main.frag").unwrap();
+        assert_eq!(
+            output,
+            "#version 450\n\
+             #extension GL_GOOGLE_include_directive : require\n\
+             #line 2 \"main.frag\"\n\
+             #line 1 \"common/a.glsl\"\n\
+             float a() { return 1.0; }\n\
+             #line 3 \"main.frag\"\n\
+             void main() {}\n"
+        );
+    }
+
+    #[test]
+    fn files_are_included_once() {
+        let directory = TestDirectory::with_files("shader-once", &[
+            ("main.frag", "#version 450\n#include \"a.glsl\"\n#include \"b.glsl\"\n#include \"a.glsl\"\nvoid main() {}\n"),
+            ("a.glsl", "float a() { return 1.0; }\n"),
+            ("b.glsl", "#include \"a.glsl\"\nfloat b() { return a(); }\n"),
+        ]);
+        let output = preprocess(&directory, "main.frag").unwrap();
+        assert_eq!(output.matches("float a()").count(), 1);
+        assert!(output.contains("float b()"));
+        // the skipped include leaves a blank line so main stays on line 5
+        assert!(output.ends_with("#line 4 \"main.frag\"\n\nvoid main() {}\n"));
+    }
+
+    #[test]
+    fn include_cycles_are_rejected() {
+        let directory = TestDirectory::with_files("shader-cycle", &[
+            ("main.frag", "#version 450\n#include \"a.glsl\"\n"),
+            ("a.glsl", "#include \"b.glsl\"\n"),
+            ("b.glsl", "float b() { return 1.0; }\n#include \"a.glsl\"\n"),
+        ]);
+        let error = preprocess(&directory, "main.frag").unwrap_err();
+        assert_eq!(error.to_string(), "b.glsl:2: include cycle through a.glsl");
+
+        let directory = TestDirectory::with_files("shader-self", &[("main.frag", "#version 450\n#include \"main.frag\"\n")]);
+        assert!(preprocess(&directory, "main.frag").unwrap_err().to_string().contains("include cycle"));
+    }
+
+    #[test]
+    fn malformed_includes_report_file_and_line() {
+        let directory = TestDirectory::with_files("shader-malformed", &[("main.frag", "#version 450\n#include <a.glsl>\n")]);
+        let error = preprocess(&directory, "main.frag").unwrap_err();
+        assert!(error.to_string().starts_with("main.frag:2: malformed #include"));
+    }
+}
@@ -0,0 +1,51 @@
+float luminance(vec3 color) {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+vec3 grayscale(vec3 color) {
+    return vec3(luminance(color));
+}
+
+vec3 tint(vec3 color, vec3 tint_color) {
+    return color * tint_color;
+}
+
+vec3 linear_to_srgb(vec3 color) {
+    color = clamp(color, 0.0, 1.0);
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+vec3 srgb_to_linear(vec3 color) {
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+    return mix(high, low, lessThanEqual(color, vec3(0.04045)));
+}
+
+vec3 rec709_to_rec2020(vec3 color) {
+    const mat3 conversion = mat3(
+        0.6274, 0.0691, 0.0164,
+        0.3293, 0.9195, 0.0880,
+        0.0433, 0.0114, 0.8956
+    );
+    return conversion * color;
+}
+
+// extended reinhard on luminance, values up to `white` map to 1.0
+vec3 tonemap_reinhard(vec3 color, float white) {
+    float lum = luminance(color);
+    float mapped = lum * (1.0 + lum / (white * white)) / (1.0 + lum);
+    return color * (mapped / max(lum, 1e-5));
+}
+
+// SMPTE ST 2084, `nits` is absolute luminance and 10000 nits maps to 1.0
+vec3 pq_encode(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
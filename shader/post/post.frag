@@ -0,0 +1,90 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_ARB_shading_language_420pack : enable
+
+#include "common/color.glsl"
+
+layout (binding = 0) uniform sampler2D inputColor;
+layout (binding = 1) uniform sampler2D colorLut;
+
+layout (push_constant) uniform PostProcessParameters {
+    vec2 resolution;
+    float time;
+    float intensity;
+} parameters;
+
+layout (location = 0) in vec2 o_uv;
+layout (location = 0) out vec4 uFragColor;
+
+#ifdef COLOR_LUT
+// 2D strip lut, LUT_SIZE slices of LUT_SIZE x LUT_SIZE laid out horizontally, blue selects the slice
+vec3 apply_lut(vec3 color) {
+    float blue = clamp(color.b, 0.0, 1.0) * (LUT_SIZE - 1.0);
+    float slice_low = floor(blue);
+    float slice_high = min(slice_low + 1.0, LUT_SIZE - 1.0);
+    vec2 texel = vec2(clamp(color.r, 0.0, 1.0), clamp(color.g, 0.0, 1.0)) * (LUT_SIZE - 1.0) + 0.5;
+    vec2 lut_resolution = vec2(LUT_SIZE * LUT_SIZE, LUT_SIZE);
+    vec3 low = texture(colorLut, vec2(slice_low * LUT_SIZE + texel.x, texel.y) / lut_resolution).rgb;
+    vec3 high = texture(colorLut, vec2(slice_high * LUT_SIZE + texel.x, texel.y) / lut_resolution).rgb;
+    return mix(low, high, blue - slice_low);
+}
+#endif
+
+#ifdef CRT
+vec2 barrel(vec2 uv) {
+    vec2 centered = uv * 2.0 - 1.0;
+    centered *= 1.0 + dot(centered, centered) * 0.08 * parameters.intensity;
+    return centered * 0.5 + 0.5;
+}
+#endif
+
+void main() {
+    vec4 color = texture(inputColor, o_uv);
+
+#if defined(GRAYSCALE)
+    color.rgb = mix(color.rgb, grayscale(color.rgb), parameters.intensity);
+#elif defined(VIGNETTE)
+    vec2 centered = o_uv - 0.5;
+    float vignette = smoothstep(0.8, 0.25, length(centered));
+    color.rgb *= mix(1.0, vignette, parameters.intensity);
+#elif defined(COLOR_LUT)
+    color.rgb = mix(color.rgb, apply_lut(color.rgb), parameters.intensity);
+#elif defined(BLUR)
+    vec2 texel = parameters.intensity / parameters.resolution;
+    vec4 sum = vec4(0.0);
+    float weights[3] = float[](0.25, 0.5, 0.25);
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            sum += texture(inputColor, o_uv + vec2(x, y) * texel) * weights[x + 1] * weights[y + 1];
+        }
+    }
+    color = sum;
+#elif defined(CRT)
+    vec2 uv = barrel(o_uv);
+    if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+        color = vec4(0.0, 0.0, 0.0, 1.0);
+    } else {
+        vec2 shift = vec2(0.5 / parameters.resolution.x, 0.0);
+        color = vec4(
+            texture(inputColor, uv + shift).r,
+            texture(inputColor, uv).g,
+            texture(inputColor, uv - shift).b,
+            texture(inputColor, uv).a
+        );
+        float scanline = 0.85 + 0.15 * sin(uv.y * parameters.resolution.y * 3.14159 + parameters.time * 10.0);
+        color.rgb *= mix(1.0, scanline, parameters.intensity);
+    }
+#elif defined(ENCODE_SRGB)
+    color.rgb = linear_to_srgb(color.rgb);
+#elif defined(HDR10)
+    // intensity carries the paper white level in nits
+    vec3 nits = rec709_to_rec2020(max(color.rgb, 0.0)) * parameters.intensity;
+    nits = tonemap_reinhard(nits / HDR_PEAK_NITS, 1.0) * HDR_PEAK_NITS;
+    color.rgb = pq_encode(nits);
+#elif defined(EXTENDED_SRGB)
+    vec3 nits = tonemap_reinhard(max(color.rgb, 0.0) * parameters.intensity / HDR_PEAK_NITS, 1.0) * HDR_PEAK_NITS;
+    color.rgb = nits / 80.0;
+#endif
+
+    uFragColor = color;
+}
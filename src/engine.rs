@@ -17,7 +17,6 @@ pub mod vulkan_logical_device;
 pub mod vulkan_surface;
 pub mod vulkan_commands;
 pub mod vulkan_swapchain;
-pub mod vulkan_fences;
 pub mod vulkan_semaphores;
 pub mod vulkan_attachments;
 pub mod vulkan_render_pass;
@@ -29,3 +28,26 @@ pub mod vulkan_shaders;
 pub mod vulkan_pipeline;
 pub mod image_manager;
 pub mod input_state;
+pub mod shader_manifest;
+pub mod shader_library;
+pub mod vulkan_pipeline_cache;
+pub mod vulkan_offscreen_target;
+pub mod post_processing;
+pub mod clip_stack;
+pub mod presentation;
+pub mod gpu_timeline;
+pub mod retirement_queue;
+pub mod device_context;
+pub mod parallel_recording;
+pub mod vulkan_base;
+pub mod app;
+pub mod frame_clock;
+pub mod profiler;
+pub mod logging;
+pub mod engine_config;
+pub mod keyboard;
+pub mod mouse;
+pub mod input_actions;
+pub mod input_recording;
+#[cfg(test)]
+pub mod test_directory;
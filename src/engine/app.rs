@@ -0,0 +1,447 @@
+use std::{error::Error, sync::Arc};
+
+use ash::{vk, Device};
+use winit::{
+    event::{DeviceEvent, ElementState, Event, KeyEvent, WindowEvent},
+    event_loop::ControlFlow,
+    keyboard::{Key as LogicalKey, NamedKey, PhysicalKey},
+    platform::run_on_demand::EventLoopExtRunOnDemand,
+};
+
+use super::{
+    commandbuffer::record_submit_timeline_commandbuffer,
+    engine_config::{print_help, EngineConfig},
+    frame_clock::{FrameClock, DEFAULT_FIXED_UPDATE_RATE},
+    gpu_timeline::{GpuTicket, LastUse},
+    input_actions::InputActions,
+    input_recording::{InputRecorder, InputReplay},
+    input_state::{InputState, InputStateEvent},
+    keyboard::Key,
+    logging::{init_logging, DEFAULT_LOG_LEVEL},
+    parallel_recording::SecondaryInheritance,
+    profiler::Profiler,
+    vulkan_base::VulkanBase,
+    winit_window::{get_window_resolution, make_winit_window},
+};
+
+/// Debug label colors as they show up in captures
+const SCENE_LABEL_COLOR: [f32; 4] = [0.2, 0.6, 1.0, 1.0];
+const POST_PROCESSING_LABEL_COLOR: [f32; 4] = [1.0, 0.6, 0.2, 1.0];
+
+/// Everything the runner owns that an app can reach from its hooks
+pub struct AppContext {
+    /// Declared before `base` so its query pools are destroyed before the device
+    pub profiler: Arc<Profiler>,
+    pub base: VulkanBase,
+    pub input: InputState,
+    /// Bind defaults in `App::init`, the player's bindings file is applied over them afterwards
+    pub actions: InputActions,
+    pub clock: FrameClock,
+    /// Settings the engine was started with, changing them afterwards has no effect
+    pub config: EngineConfig,
+    exit_requested: bool,
+}
+
+impl AppContext {
+    /// Stops the event loop after the current frame, `App::shutdown` still runs
+    pub fn exit(&mut self) {
+        self.exit_requested = true;
+    }
+}
+
+/// One acquired swapchain image. The app records its scene into secondaries that inherit the scene render
+/// pass, the runner executes them from the primary, then records post processing, submits and presents.
+pub struct Frame<'a> {
+    pub base: &'a VulkanBase,
+    pub profiler: &'a Profiler,
+    /// Which of the frames in flight this is, apps with more than one in flight index per frame buffers with it
+    pub frame_slot: usize,
+    pub present_index: usize,
+    /// Fraction of a fixed step since the last `App::fixed_update`, for interpolating fixed rate state
+    pub alpha: f32,
+    pub clear_color: [f32; 4],
+    secondary_command_buffers: Option<Vec<vk::CommandBuffer>>,
+    used: Vec<LastUse>,
+}
+
+impl Frame<'_> {
+    /// App resources the frame records, stamped with the frame's ticket once it is submitted so dropping them waits for it
+    pub fn mark_used(&mut self, last_use: &LastUse) {
+        self.used.push(last_use.clone());
+    }
+
+    pub fn scene_inheritance(&self) -> SecondaryInheritance {
+        SecondaryInheritance {
+            render_pass: self.base.scene_render_pass(),
+            subpass: 0,
+            framebuffer: self.base.scene_framebuffer(self.frame_slot, self.present_index),
+        }
+    }
+
+    /// Splits `jobs` across the recording threads. Secondaries don't inherit bound state so `record` has to bind
+    /// everything it uses. Can only be called once per frame, the worker pools are reset by every call.
+    pub fn record_scene<Job: Sync, RecordFunction>(&mut self, jobs: &[Job], record: RecordFunction)
+    where
+        RecordFunction: Fn(&Device, vk::CommandBuffer, &[Job]) + Sync,
+    {
+        assert!(self.secondary_command_buffers.is_none(), "The scene was already recorded this frame");
+        self.secondary_command_buffers = Some(self.base.parallel_recorder(self.frame_slot).record_secondaries(self.scene_inheritance(), jobs, record));
+    }
+}
+
+/// Game code plugs into the runner through these hooks, everything but `init` and `render` is optional
+pub trait App: Sized {
+    /// Called once the window and `VulkanBase` exist, creates the app
+    fn init(context: &mut AppContext) -> Result<Self, Box<dyn Error>>;
+
+    /// Called once per frame before rendering with the seconds since the previous frame
+    fn update(&mut self, _context: &mut AppContext, _dt: f32) {}
+
+    /// Called zero or more times per frame at the clock's fixed rate, `DEFAULT_FIXED_UPDATE_RATE` unless changed
+    fn fixed_update(&mut self, _context: &mut AppContext, _dt: f32) {}
+
+    fn render(&mut self, frame: &mut Frame<'_>);
+
+    /// Called after the swapchain was rebuilt with a new resolution
+    fn resize(&mut self, _context: &mut AppContext, _resolution: vk::Extent2D) {}
+
+    /// Called for every input event before it is applied to `AppContext::input`
+    fn input(&mut self, _context: &mut AppContext, _event: &InputStateEvent) {}
+
+    /// Called after the GPU went idle, before the app and `VulkanBase` are dropped
+    fn shutdown(&mut self, _context: &mut AppContext) {}
+}
+
+/// Loads the `EngineConfig` from the command line, creates the window and `VulkanBase`, then drives `A` until the
+/// window is closed or the app exits
+pub fn run_app<A: App>(app_name: &str) -> Result<(), Box<dyn Error>> {
+    // installed first so loading the config can warn, the configured level applies once it is loaded
+    init_logging(DEFAULT_LOG_LEVEL);
+    let Some(config) = EngineConfig::load(std::env::args().skip(1))? else {
+        print_help();
+        return Ok(());
+    };
+    log::set_max_level(config.log_level);
+    let (event_loop, window) = make_winit_window(app_name, &config.window);
+    let base = VulkanBase::new(window, &config)?;
+
+    let mut context = AppContext {
+        profiler: Arc::new(Profiler::new(base.shared_device(), config.frames_in_flight)),
+        base,
+        input: InputState::new(),
+        actions: InputActions::new(),
+        clock: FrameClock::new(DEFAULT_FIXED_UPDATE_RATE, config.fps_limit),
+        config,
+        exit_requested: false,
+    };
+    let event_sender = context.input.sender_clone();
+    let mut app = A::init(&mut context)?;
+    let bindings_path = context.config.input_bindings.clone();
+    if bindings_path.exists() {
+        if let Err(error) = context.actions.apply_file(&bindings_path) {
+            log::warn!("Keeping the default bindings: {}", error);
+        }
+    }
+
+    let mut recorder = context.config.input_record.as_deref().map(InputRecorder::create).transpose()?;
+    let mut replay = context.config.input_replay.as_deref().map(InputReplay::open).transpose()?;
+    if let Some(replay) = replay.as_ref() {
+        log::info!("Replaying {} recorded frames, window input is ignored until the replay ends", replay.frame_count());
+    }
+    // a replay owns the input, window events mixed in would make it diverge from the recording
+    let replaying = replay.is_some();
+    let send_window_input = |event: InputStateEvent| {
+        if !replaying {
+            event_sender.send(event).unwrap();
+        }
+    };
+
+    let result = event_loop.borrow_mut().run_on_demand(|event, elwp| {
+        elwp.set_control_flow(ControlFlow::Poll);
+        match event {
+            Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => {
+                elwp.exit();
+            }
+            Event::WindowEvent { event: WindowEvent::KeyboardInput { event: KeyEvent { physical_key, logical_key, state, .. }, .. }, .. } => {
+                let key = match physical_key {
+                    PhysicalKey::Code(code) => Key::from_key_code(code),
+                    PhysicalKey::Unidentified(_) => None,
+                };
+                match (key, state) {
+                    (Some(key), ElementState::Pressed) => {
+                        send_window_input(InputStateEvent::KeyPressed(key));
+                    }
+                    (Some(key), ElementState::Released) => {
+                        send_window_input(InputStateEvent::KeyReleased(key));
+                    }
+                    (None, _) => {}
+                }
+
+                // forwarded first so the input state and any recording still see the press that quit
+                if state == ElementState::Pressed && logical_key == LogicalKey::Named(NamedKey::Escape) {
+                    elwp.exit();
+                }
+            }
+            Event::WindowEvent { event: WindowEvent::ModifiersChanged(modifiers), .. } => {
+                send_window_input(InputStateEvent::ModifiersChanged(modifiers.state().into()));
+            }
+            Event::WindowEvent { event: WindowEvent::Focused(false), .. } => {
+                send_window_input(InputStateEvent::FocusLost);
+            }
+            Event::WindowEvent { event: WindowEvent::CursorMoved { position, .. }, .. } => {
+                send_window_input(InputStateEvent::CursorMoved((position.x, position.y)));
+            }
+            Event::WindowEvent { event: WindowEvent::MouseInput { state, button, .. }, .. } => {
+                match state {
+                    ElementState::Pressed => {
+                        send_window_input(InputStateEvent::MouseButtonPressed(button.into()));
+                    }
+                    ElementState::Released => {
+                        send_window_input(InputStateEvent::MouseButtonReleased(button.into()));
+                    }
+                }
+            }
+            Event::WindowEvent { event: WindowEvent::MouseWheel { delta, .. }, .. } => {
+                send_window_input(InputStateEvent::Scrolled(delta.into()));
+            }
+            Event::WindowEvent { event: WindowEvent::CursorEntered { .. }, .. } => {
+                send_window_input(InputStateEvent::CursorEntered);
+            }
+            Event::WindowEvent { event: WindowEvent::CursorLeft { .. }, .. } => {
+                send_window_input(InputStateEvent::CursorLeft);
+            }
+            Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } => {
+                send_window_input(InputStateEvent::RawMouseMotion(delta));
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Resized(_),
+                ..
+            } => {
+                context.base.request_swapchain_recreation();
+            }
+            Event::AboutToWait => {
+                let dt = match replay.as_mut() {
+                    Some(replay) => match replay.next_frame() {
+                        Some(recorded) => {
+                            for event in recorded.events.iter() {
+                                event_sender.send(event.clone()).unwrap();
+                            }
+                            context.clock.tick_replayed(recorded.delta)
+                        }
+                        None => {
+                            log::info!("Input replay finished");
+                            elwp.exit();
+                            return;
+                        }
+                    },
+                    None => context.clock.tick(),
+                };
+                let profiler = context.profiler.clone();
+                profiler.begin_frame(*context.base.frame.borrow(), context.base.graphics_timeline());
+
+                context.input.begin_frame();
+                let input_events = context.input.take_channel_events();
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.record_frame(context.clock.frame_count(), context.clock.total(), dt, &input_events);
+                }
+                for input_event in input_events {
+                    app.input(&mut context, &input_event);
+                    context.input.consume(input_event);
+                }
+                context.actions.update(&context.input);
+
+                {
+                    let _scope = profiler.cpu_scope("update");
+                    app.update(&mut context, dt);
+                    while context.clock.consume_fixed_step() {
+                        let fixed_dt = context.clock.fixed_timestep();
+                        app.fixed_update(&mut context, fixed_dt);
+                    }
+                }
+
+                let previous_resolution = context.base.surface_resolution;
+                // minimized windows have a zero extent, skip frames until there is something to present to
+                let mut submitted_ticket = None;
+                if context.base.ensure_swapchain(get_window_resolution(context.base.window.clone())) {
+                    if context.base.surface_resolution != previous_resolution {
+                        let resolution = context.base.surface_resolution;
+                        app.resize(&mut context, resolution);
+                    }
+                    submitted_ticket = unsafe { render_frame(&mut app, &mut context) };
+                }
+                profiler.end_frame(submitted_ticket);
+
+                if context.exit_requested {
+                    elwp.exit();
+                }
+            }
+            _ => (),
+        }
+    });
+
+    context.base.shared_device().wait_idle();
+    context.profiler.collect(context.base.graphics_timeline());
+    if let Some(trace_path) = context.config.profile_trace.as_ref() {
+        match context.profiler.write_chrome_trace(trace_path) {
+            Ok(()) => log::info!("Wrote profile trace to {}", trace_path.display()),
+            Err(error) => log::warn!("Failed to write profile trace to {}: {}", trace_path.display(), error),
+        }
+    }
+    app.shutdown(&mut context);
+    // app resources hold the device, they have to go before VulkanBase destroys it
+    drop(app);
+    drop(context);
+
+    result.map_err(|error| error.into())
+}
+
+/// Returns the ticket of the frame's submission, `None` if the frame was skipped
+unsafe fn render_frame<A: App>(app: &mut A, context: &mut AppContext) -> Option<GpuTicket> {
+    let profiler = context.profiler.clone();
+    let base = &mut context.base;
+    let frame_index = base.increment_frame();
+    let frame_slot = base.frame_slot(frame_index);
+    let current_swapchain_image = base.get_next_swapchain_image_index();
+
+    // the frame that last used this slot may still be reading buffers the app is about to write
+    {
+        let _scope = profiler.cpu_scope("wait for previous frame");
+        base.graphics_timeline().wait(base.draw_command_buffers[frame_slot].last_ticket.get());
+        base.collect_retired();
+    }
+
+    let acquire_scope = profiler.cpu_scope("acquire");
+    let acquisition_result = base
+        .swapchain_device
+        .acquire_next_image(
+            base.swapchain,
+            u64::MAX,
+            base.present_complete_semaphores[current_swapchain_image],
+            vk::Fence::null(),
+        );
+    let present_index = match acquisition_result {
+        Ok((present_index, suboptimal)) => {
+            // still usable, the acquire semaphore will be signaled so this frame must be submitted
+            if suboptimal {
+                base.request_swapchain_recreation();
+            }
+            present_index
+        }
+        Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+            base.request_swapchain_recreation();
+            return None;
+        }
+        Err(e) => {
+            panic!("Failed to acquire next image: {:?}", e);
+        }
+    };
+    drop(acquire_scope);
+
+    let base = &context.base;
+    let time = context.clock.total();
+    let mut frame = Frame {
+        base,
+        profiler: &profiler,
+        frame_slot,
+        present_index: present_index as usize,
+        alpha: context.clock.alpha(),
+        clear_color: [0.0, 0.0, 0.0, 0.0],
+        secondary_command_buffers: None,
+        used: Vec::new(),
+    };
+    {
+        let _scope = profiler.cpu_scope("render");
+        app.render(&mut frame);
+    }
+    let clear_color = frame.clear_color;
+    let secondary_command_buffers = frame.secondary_command_buffers.unwrap_or_default();
+    let used = frame.used;
+
+    let clear_values = [
+        vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: clear_color,
+            },
+        },
+        vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        },
+    ];
+    let render_pass_begin_info = vk::RenderPassBeginInfo::default()
+        .render_pass(base.scene_render_pass())
+        .framebuffer(base.scene_framebuffer(frame_slot, present_index as usize))
+        .render_area(base.surface_resolution.into())
+        .clear_values(&clear_values);
+
+    let submit_scope = profiler.cpu_scope("submit");
+    let ticket = record_submit_timeline_commandbuffer(
+        &base.shared_device(),
+        &base.draw_command_buffers[frame_slot],
+        base.graphics_timeline(),
+        base.device.graphics_queue(),
+        &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
+        &[base.present_complete_semaphores[current_swapchain_image]],
+        &[],
+        &[base.rendering_complete_semaphores[current_swapchain_image]],
+        |device, draw_command_buffer| {
+            profiler.reset_gpu_queries(device, draw_command_buffer);
+            base.device.begin_label(draw_command_buffer, "scene pass", SCENE_LABEL_COLOR);
+            profiler.gpu_scope(device, draw_command_buffer, "scene pass", || {
+                device.cmd_begin_render_pass(
+                    draw_command_buffer,
+                    &render_pass_begin_info,
+                    vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+                );
+                if !secondary_command_buffers.is_empty() {
+                    device.cmd_execute_commands(draw_command_buffer, &secondary_command_buffers);
+                }
+                device.cmd_end_render_pass(draw_command_buffer);
+            });
+            base.device.end_label(draw_command_buffer);
+
+            if let Some(post_processing) = base.post_processing.as_ref() {
+                base.device.label_scope(draw_command_buffer, "post processing", POST_PROCESSING_LABEL_COLOR, || {
+                    profiler.gpu_scope(device, draw_command_buffer, "post processing", || {
+                        post_processing.record(device, draw_command_buffer, frame_slot, present_index as usize, time as f32);
+                    });
+                });
+            }
+        },
+    );
+    drop(submit_scope);
+    base.mark_frame_used(frame_slot, ticket);
+    for last_use in used {
+        last_use.mark(ticket);
+    }
+    if let Some(debugger) = base.debugger.as_ref() {
+        debugger.check_validation_errors();
+    }
+    let present_info = vk::PresentInfoKHR {
+        wait_semaphore_count: 1,
+        p_wait_semaphores: &base.rendering_complete_semaphores[current_swapchain_image],
+        swapchain_count: 1,
+        p_swapchains: &base.swapchain,
+        p_image_indices: &present_index,
+        ..Default::default()
+    };
+    let presentation_result = {
+        let _scope = profiler.cpu_scope("present");
+        let present_queue = base.device.present_queue();
+        let _queue_guard = present_queue.lock();
+        base.swapchain_device.queue_present(present_queue.queue, &present_info)
+    };
+    match presentation_result {
+        Ok(false) => {}
+        Ok(true) | Err(vk::Result::SUBOPTIMAL_KHR) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+            context.base.request_swapchain_recreation();
+        }
+        Err(e) => {
+            panic!("Failed to present: {:?}", e);
+        }
+    }
+
+    Some(ticket)
+}
@@ -0,0 +1,317 @@
+use std::ops::Range;
+
+use ash::{vk, Device};
+
+use super::vulkan_pipeline::VulkanPipeline;
+
+const INDICES_PER_QUAD: u32 = 6;
+
+pub fn intersect_rects(a: vk::Rect2D, b: vk::Rect2D) -> vk::Rect2D {
+    let left = a.offset.x.max(b.offset.x);
+    let top = a.offset.y.max(b.offset.y);
+    let right = (a.offset.x + a.extent.width as i32).min(b.offset.x + b.extent.width as i32);
+    let bottom = (a.offset.y + a.extent.height as i32).min(b.offset.y + b.extent.height as i32);
+
+    vk::Rect2D {
+        offset: vk::Offset2D { x: left, y: top },
+        extent: vk::Extent2D {
+            width: (right - left).max(0) as u32,
+            height: (bottom - top).max(0) as u32,
+        },
+    }
+}
+
+/// A recorded step of a clipped draw, quad ranges index into the `CoherentQuads` being drawn
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipCommand {
+    Draw { quads: Range<u32>, scissor: vk::Rect2D, stencil_reference: u32 },
+    /// Raises the stencil from `stencil_reference` to `stencil_reference + 1` wherever the mask quads cover
+    PushMask { quads: Range<u32>, scissor: vk::Rect2D, stencil_reference: u32 },
+    /// Lowers the stencil from `stencil_reference` back down once the mask is no longer needed
+    PopMask { quads: Range<u32>, scissor: vk::Rect2D, stencil_reference: u32 },
+}
+
+struct ClipEntry {
+    scissor: vk::Rect2D,
+    stencil_depth: u32,
+    mask_quads: Option<Range<u32>>,
+}
+
+/// Splits a quad draw into sub ranges clipped by a stack of rectangles, rectangles only narrow the
+/// scissor while arbitrary shapes are masked with the stencil buffer
+pub struct ClipStack {
+    viewport: vk::Rect2D,
+    stencil_available: bool,
+    entries: Vec<ClipEntry>,
+    commands: Vec<ClipCommand>,
+}
+
+impl ClipStack {
+    /// Without stencil support masks degrade to clipping by their bounding rectangle
+    pub fn new(resolution: vk::Extent2D, stencil_available: bool) -> Self {
+        Self {
+            viewport: resolution.into(),
+            stencil_available,
+            entries: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn current_scissor(&self) -> vk::Rect2D {
+        self.entries.last().map(|entry| entry.scissor).unwrap_or(self.viewport)
+    }
+
+    pub fn stencil_depth(&self) -> u32 {
+        self.entries.last().map(|entry| entry.stencil_depth).unwrap_or(0)
+    }
+
+    pub fn push_rect(&mut self, rect: vk::Rect2D) {
+        self.entries.push(ClipEntry {
+            scissor: intersect_rects(self.current_scissor(), rect),
+            stencil_depth: self.stencil_depth(),
+            mask_quads: None,
+        });
+    }
+
+    /// Clips to the shape covered by `mask_quads`, `bounds` must contain the whole shape
+    pub fn push_mask(&mut self, bounds: vk::Rect2D, mask_quads: Range<u32>) {
+        let scissor = intersect_rects(self.current_scissor(), bounds);
+        let stencil_depth = self.stencil_depth();
+
+        if !self.stencil_available {
+            self.entries.push(ClipEntry { scissor, stencil_depth, mask_quads: None });
+            return;
+        }
+
+        self.commands.push(ClipCommand::PushMask {
+            quads: mask_quads.clone(),
+            scissor,
+            stencil_reference: stencil_depth,
+        });
+        self.entries.push(ClipEntry {
+            scissor,
+            stencil_depth: stencil_depth + 1,
+            mask_quads: Some(mask_quads),
+        });
+    }
+
+    pub fn pop(&mut self) {
+        let entry = self.entries.pop().expect("Clip stack popped more than it was pushed");
+
+        if let Some(mask_quads) = entry.mask_quads {
+            self.commands.push(ClipCommand::PopMask {
+                quads: mask_quads,
+                scissor: entry.scissor,
+                stencil_reference: entry.stencil_depth,
+            });
+        }
+    }
+
+    /// Draws `quads` with the current clip, adjacent draws sharing a clip are merged
+    pub fn draw(&mut self, quads: Range<u32>) {
+        self.push_draw(quads, true);
+    }
+
+    /// Draws `quads` with the current clip as up to `batch_count` separate draws that are never merged, so
+    /// `Frame::record_scene` can hand them to different recording threads
+    pub fn draw_batches(&mut self, quads: Range<u32>, batch_count: u32) {
+        let batch_size = quads.len().div_ceil(batch_count.max(1) as usize).max(1) as u32;
+        let mut start = quads.start;
+        while start < quads.end {
+            let end = (start + batch_size).min(quads.end);
+            self.push_draw(start..end, false);
+            start = end;
+        }
+    }
+
+    fn push_draw(&mut self, quads: Range<u32>, merge: bool) {
+        if quads.is_empty() {
+            return;
+        }
+
+        let scissor = self.current_scissor();
+        if scissor.extent.width == 0 || scissor.extent.height == 0 {
+            return;
+        }
+
+        let stencil_reference = self.stencil_depth();
+        if let Some(ClipCommand::Draw { quads: previous, scissor: previous_scissor, stencil_reference: previous_reference }) = self.commands.last_mut() {
+            if merge && previous.end == quads.start && *previous_scissor == scissor && *previous_reference == stencil_reference {
+                previous.end = quads.end;
+                return;
+            }
+        }
+
+        self.commands.push(ClipCommand::Draw { quads, scissor, stencil_reference });
+    }
+
+    /// Unwinds anything still pushed so the stencil is left clean for the next frame
+    pub fn finish(mut self) -> Vec<ClipCommand> {
+        while !self.entries.is_empty() {
+            self.pop();
+        }
+
+        self.commands
+    }
+}
+
+/// Raw pipeline handles used while recording, all must share a pipeline layout and the content pipeline
+/// must take a dynamic stencil reference whenever the mask pipelines are present
+#[derive(Debug, Clone, Copy)]
+pub struct ClipPipelines {
+    pub content: vk::Pipeline,
+    pub stencil: Option<(vk::Pipeline, vk::Pipeline)>,
+}
+
+/// Pipelines that write the stencil for non rectangular clips, only built when the depth format has stencil
+pub struct StencilClipPipelines {
+    pub push_mask: VulkanPipeline,
+    pub pop_mask: VulkanPipeline,
+}
+
+/// Expects the quads' buffers and descriptor sets to already be bound and the viewport set
+///
+/// # Safety
+///
+/// `command_buffer` must be recording inside a render pass compatible with `pipelines`, and every quad range in
+/// `commands` must lie within the bound index buffer
+pub unsafe fn record_clip_commands(device: &Device, command_buffer: vk::CommandBuffer, commands: &[ClipCommand], pipelines: &ClipPipelines) {
+    for command in commands {
+        let (pipeline, quads, scissor, stencil_reference) = match (command, pipelines.stencil) {
+            (ClipCommand::Draw { quads, scissor, stencil_reference }, _) => (pipelines.content, quads, scissor, *stencil_reference),
+            (ClipCommand::PushMask { quads, scissor, stencil_reference }, Some((push_mask, _))) => (push_mask, quads, scissor, *stencil_reference),
+            (ClipCommand::PopMask { quads, scissor, stencil_reference }, Some((_, pop_mask))) => (pop_mask, quads, scissor, *stencil_reference),
+            _ => continue,
+        };
+
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+        device.cmd_set_scissor(command_buffer, 0, &[*scissor]);
+        if pipelines.stencil.is_some() {
+            device.cmd_set_stencil_reference(command_buffer, vk::StencilFaceFlags::FRONT_AND_BACK, stencil_reference);
+        }
+        device.cmd_draw_indexed(
+            command_buffer,
+            quads.len() as u32 * INDICES_PER_QUAD,
+            1,
+            quads.start * INDICES_PER_QUAD,
+            0,
+            0,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> vk::Rect2D {
+        vk::Rect2D {
+            offset: vk::Offset2D { x, y },
+            extent: vk::Extent2D { width, height },
+        }
+    }
+
+    fn new_stack(stencil_available: bool) -> ClipStack {
+        ClipStack::new(vk::Extent2D { width: 100, height: 100 }, stencil_available)
+    }
+
+    #[test]
+    fn rects_intersect_to_their_overlap() {
+        assert_eq!(intersect_rects(rect(0, 0, 50, 50), rect(25, 10, 50, 50)), rect(25, 10, 25, 40));
+        assert_eq!(intersect_rects(rect(10, 10, 20, 20), rect(0, 0, 100, 100)), rect(10, 10, 20, 20));
+        // disjoint rects collapse to an empty extent instead of wrapping around
+        assert_eq!(intersect_rects(rect(0, 0, 10, 10), rect(20, 20, 10, 10)).extent, vk::Extent2D { width: 0, height: 0 });
+        assert_eq!(intersect_rects(rect(-10, -10, 20, 20), rect(0, 0, 100, 100)), rect(0, 0, 10, 10));
+    }
+
+    #[test]
+    fn pushes_nest_scissors_and_stencil_references() {
+        let mut stack = new_stack(true);
+        assert_eq!((stack.current_scissor(), stack.stencil_depth()), (rect(0, 0, 100, 100), 0));
+
+        stack.push_rect(rect(10, 10, 50, 50));
+        assert_eq!((stack.current_scissor(), stack.stencil_depth()), (rect(10, 10, 50, 50), 0));
+
+        stack.push_mask(rect(20, 20, 80, 80), 0..1);
+        assert_eq!((stack.current_scissor(), stack.stencil_depth()), (rect(20, 20, 40, 40), 1));
+
+        stack.push_mask(rect(30, 30, 10, 10), 1..2);
+        assert_eq!((stack.current_scissor(), stack.stencil_depth()), (rect(30, 30, 10, 10), 2));
+        stack.draw(2..4);
+
+        stack.pop();
+        assert_eq!((stack.current_scissor(), stack.stencil_depth()), (rect(20, 20, 40, 40), 1));
+        stack.pop();
+        stack.pop();
+        assert_eq!((stack.current_scissor(), stack.stencil_depth()), (rect(0, 0, 100, 100), 0));
+
+        assert_eq!(stack.finish(), vec![
+            ClipCommand::PushMask { quads: 0..1, scissor: rect(20, 20, 40, 40), stencil_reference: 0 },
+            ClipCommand::PushMask { quads: 1..2, scissor: rect(30, 30, 10, 10), stencil_reference: 1 },
+            ClipCommand::Draw { quads: 2..4, scissor: rect(30, 30, 10, 10), stencil_reference: 2 },
+            ClipCommand::PopMask { quads: 1..2, scissor: rect(30, 30, 10, 10), stencil_reference: 2 },
+            ClipCommand::PopMask { quads: 0..1, scissor: rect(20, 20, 40, 40), stencil_reference: 1 },
+        ]);
+    }
+
+    #[test]
+    fn masks_fall_back_to_their_bounds_without_stencil() {
+        let mut stack = new_stack(false);
+        stack.push_mask(rect(20, 20, 10, 10), 0..1);
+        assert_eq!(stack.stencil_depth(), 0);
+        stack.draw(1..3);
+
+        assert_eq!(stack.finish(), vec![ClipCommand::Draw { quads: 1..3, scissor: rect(20, 20, 10, 10), stencil_reference: 0 }]);
+    }
+
+    #[test]
+    fn finish_unwinds_masks_left_pushed() {
+        let mut stack = new_stack(true);
+        stack.push_mask(rect(0, 0, 10, 10), 0..1);
+
+        assert_eq!(stack.finish().last(), Some(&ClipCommand::PopMask { quads: 0..1, scissor: rect(0, 0, 10, 10), stencil_reference: 1 }));
+    }
+
+    #[test]
+    #[should_panic(expected = "Clip stack popped more than it was pushed")]
+    fn unbalanced_pop_panics() {
+        let mut stack = new_stack(true);
+        stack.push_rect(rect(0, 0, 10, 10));
+        stack.pop();
+        stack.pop();
+    }
+
+    #[test]
+    fn adjacent_draws_merge_and_empty_clips_draw_nothing() {
+        let mut stack = new_stack(true);
+        stack.draw(0..2);
+        stack.draw(2..5);
+        stack.push_rect(rect(200, 200, 10, 10));
+        stack.draw(5..6);
+        stack.pop();
+        stack.draw(6..7);
+
+        assert_eq!(stack.finish(), vec![
+            ClipCommand::Draw { quads: 0..5, scissor: rect(0, 0, 100, 100), stencil_reference: 0 },
+            ClipCommand::Draw { quads: 6..7, scissor: rect(0, 0, 100, 100), stencil_reference: 0 },
+        ]);
+    }
+
+    #[test]
+    fn draw_batches_split_evenly_and_never_merge() {
+        let mut stack = new_stack(true);
+        stack.draw_batches(0..10, 3);
+        stack.draw_batches(10..12, 4);
+        stack.draw_batches(12..12, 2);
+
+        let quads: Vec<Range<u32>> = stack
+            .finish()
+            .into_iter()
+            .map(|command| match command {
+                ClipCommand::Draw { quads, .. } => quads,
+                command => panic!("unexpected {:?}", command),
+            })
+            .collect();
+        assert_eq!(quads, vec![0..4, 4..8, 8..10, 10..11, 11..12]);
+    }
+}
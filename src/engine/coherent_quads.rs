@@ -1,8 +1,8 @@
-use std::{cell::RefCell, fmt::{Debug, Formatter}, mem::{self, size_of_val}, os::raw::c_void, sync::{Arc, Mutex}};
+use std::{cell::RefCell, fmt::{Debug, Formatter}, mem::{self, size_of_val}, os::raw::c_void, sync::Arc};
 
-use ash::{util::Align, vk, Device};
+use ash::{util::Align, vk};
 
-use super::{memory::find_memorytype_index, vertex::Vertex};
+use super::{device_context::DeviceContext, gpu_timeline::LastUse, memory::find_memorytype_index, vertex::Vertex};
 
 pub struct CoherentQuads {
     pub local_index_buffer_data: Vec<u32>,
@@ -12,7 +12,9 @@ pub struct CoherentQuads {
     pub current_max_quad_quantity: u32,
     pub index_buffer_memory: vk::DeviceMemory,
     pub vertex_input_buffer_memory: vk::DeviceMemory,
-    pub device: Arc<Mutex<Device>>,
+    pub device: Arc<DeviceContext>,
+    /// Hand to `Frame::mark_used` in every frame that draws the quads
+    pub last_use: LastUse,
 }
 
 impl Debug for CoherentQuads {
@@ -28,7 +30,7 @@ impl Debug for CoherentQuads {
 }
 
 impl CoherentQuads {
-     pub unsafe fn new(max_quad_quantity: u32, device: Arc<Mutex<Device>>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
+     pub unsafe fn new(max_quad_quantity: u32, device: Arc<DeviceContext>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
         let local_vertex_buffer_data: RefCell<Vec<Vertex>> = RefCell::new(Vec::with_capacity(max_quad_quantity as usize * 4));
         let local_index_buffer_data: Vec<u32> = Vec::with_capacity(max_quad_quantity as usize * 6);
 
@@ -50,13 +52,10 @@ impl CoherentQuads {
             ..Default::default()
         };
 
-        let locked_device = device.clone();
-        let locked_device = locked_device.lock().unwrap();
+        let device_index_buffer = device.create_buffer(&index_buffer_info, None).unwrap();
+        let device_vertex_buffer = device.create_buffer(&vertex_input_buffer_info, None).unwrap();
 
-        let device_index_buffer = locked_device.create_buffer(&index_buffer_info, None).unwrap();
-        let device_vertex_buffer = locked_device.create_buffer(&vertex_input_buffer_info, None).unwrap();
-
-        let index_buffer_memory_req = locked_device.get_buffer_memory_requirements(device_index_buffer);
+        let index_buffer_memory_req = device.get_buffer_memory_requirements(device_index_buffer);
         let index_buffer_memory_index = find_memorytype_index(
             &index_buffer_memory_req,
             &device_memory_properties,
@@ -68,11 +67,11 @@ impl CoherentQuads {
             memory_type_index: index_buffer_memory_index,
             ..Default::default()
         };
-        let index_buffer_memory = locked_device
+        let index_buffer_memory = device
             .allocate_memory(&index_allocate_info, None)
             .unwrap();
 
-        let vertex_input_buffer_memory_req = locked_device
+        let vertex_input_buffer_memory_req = device
             .get_buffer_memory_requirements(device_vertex_buffer);
         let vertex_input_buffer_memory_index = find_memorytype_index(
             &vertex_input_buffer_memory_req,
@@ -86,15 +85,15 @@ impl CoherentQuads {
             memory_type_index: vertex_input_buffer_memory_index,
             ..Default::default()
         };
-        let vertex_input_buffer_memory = locked_device
+        let vertex_input_buffer_memory = device
             .allocate_memory(&vertex_buffer_allocate_info, None)
             .unwrap();
 
-        locked_device
+        device
             .bind_buffer_memory(device_vertex_buffer, vertex_input_buffer_memory, 0)
             .unwrap();
 
-        locked_device
+        device
             .bind_buffer_memory(device_index_buffer, index_buffer_memory, 0)
             .unwrap();
 
@@ -107,13 +106,10 @@ impl CoherentQuads {
             current_max_quad_quantity: max_quad_quantity,
             index_buffer_memory,
             vertex_input_buffer_memory,
+            last_use: LastUse::default(),
         }
     }
 
-    pub fn index_quantity(&self) -> usize {
-        self.local_index_buffer_data.len()
-    }
-
     pub fn quad_quantity(&self) -> usize {
         self.local_vertex_buffer_data.borrow().len() / self.vertex_buffer_instance_node_quantity()
     }
@@ -148,81 +144,57 @@ impl CoherentQuads {
             .copy_from_slice(&vertices);
     }
 
-    pub fn get_quad(&self, index: usize) -> [Vertex; 4] {
-        let index_offset = index as u32 * 4;
-        let borrow_data = self.local_vertex_buffer_data.borrow();
-        [
-            borrow_data[index_offset as usize],
-            borrow_data[(index_offset + 1) as usize],
-            borrow_data[(index_offset + 2) as usize],
-            borrow_data[(index_offset + 3) as usize],
-        ]
-    }
-
     pub fn remap_data(&self) {
         let device = self.device.as_ref();
-        match device.lock() {
-            Ok(device) => {
-                unsafe {
-                    let index_buffer_memory_req = device.get_buffer_memory_requirements(self.device_index_buffer);
-                    let index_ptr: *mut c_void = device
-                        .map_memory(
-                            self.index_buffer_memory,
-                            0,
-                            index_buffer_memory_req.size,
-                            vk::MemoryMapFlags::empty(),
-                        )
-                        .unwrap();
-                    let mut index_slice = Align::new(
-                        index_ptr,
-                        mem::align_of::<u32>() as u64,
-                        index_buffer_memory_req.size,
-                    );
-                    index_slice.copy_from_slice(&self.local_index_buffer_data);
-                    device.unmap_memory(self.index_buffer_memory);
-
-                    let vertex_input_buffer_memory_req = device
-                        .get_buffer_memory_requirements(self.device_vertex_buffer);
-                    let vert_ptr = device
-                        .map_memory(
-                            self.vertex_input_buffer_memory,
-                            0,
-                            vertex_input_buffer_memory_req.size,
-                            vk::MemoryMapFlags::empty(),
-                        )
-                        .unwrap();
-                    let mut slice = Align::new(
-                        vert_ptr,
-                        mem::align_of::<Vertex>() as u64,
-                        vertex_input_buffer_memory_req.size,
-                    );
-                    slice.copy_from_slice(&self.local_vertex_buffer_data.borrow());
-                    device.unmap_memory(self.vertex_input_buffer_memory);
-                }
-            }
-            Err(_) => {
-                panic!("Failed to lock device mutex");
-            }
+        unsafe {
+            let index_buffer_memory_req = device.get_buffer_memory_requirements(self.device_index_buffer);
+            let index_ptr: *mut c_void = device
+                .map_memory(
+                    self.index_buffer_memory,
+                    0,
+                    index_buffer_memory_req.size,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .unwrap();
+            let mut index_slice = Align::new(
+                index_ptr,
+                mem::align_of::<u32>() as u64,
+                index_buffer_memory_req.size,
+            );
+            index_slice.copy_from_slice(&self.local_index_buffer_data);
+            device.unmap_memory(self.index_buffer_memory);
+
+            let vertex_input_buffer_memory_req = device
+                .get_buffer_memory_requirements(self.device_vertex_buffer);
+            let vert_ptr = device
+                .map_memory(
+                    self.vertex_input_buffer_memory,
+                    0,
+                    vertex_input_buffer_memory_req.size,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .unwrap();
+            let mut slice = Align::new(
+                vert_ptr,
+                mem::align_of::<Vertex>() as u64,
+                vertex_input_buffer_memory_req.size,
+            );
+            slice.copy_from_slice(&self.local_vertex_buffer_data.borrow());
+            device.unmap_memory(self.vertex_input_buffer_memory);
         }
     }
 }
 
 impl Drop for CoherentQuads {
     fn drop(&mut self) {
-        let device = self.device.as_ref();
-        match device.lock() {
-            Ok(device) => {
-                unsafe {
-                    device.destroy_buffer(self.device_index_buffer, None);
-                    device.destroy_buffer(self.device_vertex_buffer, None);
-
-                    device.free_memory(self.index_buffer_memory, None);
-                    device.free_memory(self.vertex_input_buffer_memory, None);
-                }
-            }
-            Err(_) => {
-                panic!("Failed to lock device mutex");
-            }
-        }
+        let (index_buffer, vertex_buffer) = (self.device_index_buffer, self.device_vertex_buffer);
+        let (index_buffer_memory, vertex_input_buffer_memory) = (self.index_buffer_memory, self.vertex_input_buffer_memory);
+        self.device.retire(&self.last_use, move |device| unsafe {
+            device.destroy_buffer(index_buffer, None);
+            device.destroy_buffer(vertex_buffer, None);
+
+            device.free_memory(index_buffer_memory, None);
+            device.free_memory(vertex_input_buffer_memory, None);
+        });
     }
 }
@@ -6,37 +6,32 @@
     unused_qualifications
 )]
 
-use std::{default::Default, sync::{Arc, Mutex}};
+use std::{default::Default, sync::Arc};
 use ash::{vk, Device};
 
-use super::{vulkan_depth_image::VulkanDepthImage, vulkan_image::VulkanImage, vulkan_texture::VulkanTexture};
+use super::{device_context::{DeviceContext, VulkanQueue}, gpu_timeline::{GpuTicket, GpuTimeline, TimelineCommandBuffer}, vulkan_depth_image::VulkanDepthImage, vulkan_image::VulkanImage, vulkan_texture::VulkanTexture};
 
-/// Helper function for submitting command buffers. Immediately waits for the fence before the command buffer
-/// is executed. That way we can delay the waiting for the fences by 1 frame which is good for performance.
-/// Make sure to create the fence in a signaled state on the first use.
+/// Records `func` into `command_buffer` and submits it, waiting for the command buffer's previous ticket before reuse
+/// and signaling the next ticket of `timeline`. `timeline_waits` holds tickets of other queues' timelines this submission
+/// depends on. Returns the ticket that completes with this submission.
 #[allow(clippy::too_many_arguments)]
-pub fn record_submit_commandbuffer<FunctionPointerType: FnOnce(&Device, vk::CommandBuffer)>(
+pub fn record_submit_timeline_commandbuffer<FunctionPointerType: FnOnce(&Device, vk::CommandBuffer)>(
     device: &Device,
-    command_buffer: vk::CommandBuffer,
-    command_buffer_reuse_fence: vk::Fence,
-    submit_queue: vk::Queue,
+    command_buffer: &TimelineCommandBuffer,
+    timeline: &GpuTimeline,
+    submit_queue: &VulkanQueue,
     wait_mask: &[vk::PipelineStageFlags],
     wait_semaphores: &[vk::Semaphore],
+    timeline_waits: &[(&GpuTimeline, GpuTicket, vk::PipelineStageFlags)],
     signal_semaphores: &[vk::Semaphore],
     func: FunctionPointerType,
-) {
-    unsafe {
-        device
-            .wait_for_fences(&[command_buffer_reuse_fence], true, u64::MAX)
-            .expect("Wait for fence failed.");
-
-        device
-            .reset_fences(&[command_buffer_reuse_fence])
-            .expect("Reset fences failed.");
+) -> GpuTicket {
+    timeline.wait(command_buffer.last_ticket.get());
 
+    unsafe {
         device
             .reset_command_buffer(
-                command_buffer,
+                command_buffer.command_buffer,
                 vk::CommandBufferResetFlags::RELEASE_RESOURCES,
             )
             .expect("Reset command buffer failed.");
@@ -45,39 +40,66 @@ pub fn record_submit_commandbuffer<FunctionPointerType: FnOnce(&Device, vk::Comm
             .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
 
         device
-            .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+            .begin_command_buffer(command_buffer.command_buffer, &command_buffer_begin_info)
             .expect("Begin commandbuffer");
-        func(device, command_buffer);
+        func(device, command_buffer.command_buffer);
         device
-            .end_command_buffer(command_buffer)
+            .end_command_buffer(command_buffer.command_buffer)
             .expect("End commandbuffer");
+    }
+
+    // tickets have to be signaled in order, so reserving one and submitting it happen under the queue lock
+    let _queue_guard = submit_queue.lock();
+    let ticket = timeline.next_ticket();
 
-        let command_buffers = vec![command_buffer];
+    // binary semaphores ignore their value but every semaphore needs an entry
+    let all_wait_semaphores: Vec<vk::Semaphore> = wait_semaphores.iter().cloned()
+        .chain(timeline_waits.iter().map(|(wait_timeline, _, _)| wait_timeline.semaphore))
+        .collect();
+    let all_wait_values: Vec<u64> = wait_semaphores.iter().map(|_| 0)
+        .chain(timeline_waits.iter().map(|(_, wait_ticket, _)| *wait_ticket))
+        .collect();
+    let all_wait_mask: Vec<vk::PipelineStageFlags> = wait_mask.iter().cloned()
+        .chain(timeline_waits.iter().map(|(_, _, stage)| *stage))
+        .collect();
+    let all_signal_semaphores: Vec<vk::Semaphore> = signal_semaphores.iter().cloned()
+        .chain([timeline.semaphore])
+        .collect();
+    let all_signal_values: Vec<u64> = signal_semaphores.iter().map(|_| 0)
+        .chain([ticket])
+        .collect();
 
-        let submit_info = vk::SubmitInfo::default()
-            .wait_semaphores(wait_semaphores)
-            .wait_dst_stage_mask(wait_mask)
-            .command_buffers(&command_buffers)
-            .signal_semaphores(signal_semaphores);
+    let command_buffers = [command_buffer.command_buffer];
+    let mut timeline_submit_info = vk::TimelineSemaphoreSubmitInfo::default()
+        .wait_semaphore_values(&all_wait_values)
+        .signal_semaphore_values(&all_signal_values);
+    let submit_info = vk::SubmitInfo::default()
+        .wait_semaphores(&all_wait_semaphores)
+        .wait_dst_stage_mask(&all_wait_mask)
+        .command_buffers(&command_buffers)
+        .signal_semaphores(&all_signal_semaphores)
+        .push_next(&mut timeline_submit_info);
 
+    unsafe {
         device
-            .queue_submit(submit_queue, &[submit_info], command_buffer_reuse_fence)
+            .queue_submit(submit_queue.queue, &[submit_info], vk::Fence::null())
             .expect("queue submit failed.");
     }
-}
 
-pub fn submit_commandbuffer_to_ensure_depth_image_format(device: Arc<Mutex<ash::Device>>, setup_command_buffer: vk::CommandBuffer, setup_commands_reuse_fence: vk::Fence, setup_command_buffer_submit_queue: vk::Queue, depth_image: &VulkanDepthImage) {
-    let locked_device = device.clone();
-    let locked_device = locked_device.lock().unwrap();
+    command_buffer.last_ticket.set(ticket);
+    ticket
+}
 
-    record_submit_commandbuffer(
-        &locked_device,
+pub fn submit_commandbuffer_to_ensure_depth_image_format(device: Arc<DeviceContext>, setup_command_buffer: &TimelineCommandBuffer, timeline: &GpuTimeline, setup_command_buffer_submit_queue: &VulkanQueue, depth_image: &VulkanDepthImage) -> GpuTicket {
+    let ticket = record_submit_timeline_commandbuffer(
+        &device,
         setup_command_buffer,
-        setup_commands_reuse_fence,
+        timeline,
         setup_command_buffer_submit_queue,
         &[],
         &[],
         &[],
+        &[],
         |device, setup_command_buffer| {
             let layout_transition_barriers = vk::ImageMemoryBarrier::default()
                 .image(depth_image.depth_image)
@@ -89,7 +111,7 @@ pub fn submit_commandbuffer_to_ensure_depth_image_format(device: Arc<Mutex<ash::
                 .old_layout(vk::ImageLayout::UNDEFINED)
                 .subresource_range(
                     vk::ImageSubresourceRange::default()
-                        .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                        .aspect_mask(depth_image.aspect_mask())
                         .layer_count(1)
                         .level_count(1),
                 );
@@ -105,23 +127,61 @@ pub fn submit_commandbuffer_to_ensure_depth_image_format(device: Arc<Mutex<ash::
             ) };
         },
     );
+    depth_image.last_use.mark(ticket);
+    ticket
+}
+
+/// Command buffers and queues used for uploads, when the transfer family differs from the graphics family the copy runs
+/// on the transfer queue and ownership of the destination is handed to the graphics queue afterwards
+#[derive(Clone, Copy)]
+pub struct UploadContext<'a> {
+    pub transfer_command_buffer: &'a TimelineCommandBuffer,
+    pub transfer_timeline: &'a GpuTimeline,
+    pub transfer_queue: &'a VulkanQueue,
+    pub graphics_command_buffer: &'a TimelineCommandBuffer,
+    pub graphics_timeline: &'a GpuTimeline,
+    pub graphics_queue: &'a VulkanQueue,
 }
 
-pub fn submit_commandbuffer_to_load_image(device: Arc<Mutex<ash::Device>>, setup_command_buffer: vk::CommandBuffer, setup_commands_reuse_fence: vk::Fence, setup_command_buffer_submit_queue: vk::Queue, tex: &VulkanTexture, img: &VulkanImage) {
-    let locked_device = device.clone();
-    let locked_device = locked_device.lock().unwrap();
+impl UploadContext<'_> {
+    pub fn has_separate_transfer(&self) -> bool {
+        self.transfer_queue.family_index != self.graphics_queue.family_index
+    }
+}
 
+fn make_color_subresource_range() -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        level_count: 1,
+        layer_count: 1,
+        ..Default::default()
+    }
+}
+
+/// Returns the graphics timeline ticket after which the texture is ready to sample and the staging image can be released
+pub fn submit_commandbuffer_to_load_image(device: Arc<DeviceContext>, upload_context: &UploadContext<'_>, tex: &VulkanTexture, img: &VulkanImage) -> GpuTicket {
     let image_extent = vk::Extent3D {
         width: img.dimensions.width,
         height: img.dimensions.height,
         depth: 1,
     };
 
-    record_submit_commandbuffer(
-        &locked_device,
-        setup_command_buffer,
-        setup_commands_reuse_fence,
-        setup_command_buffer_submit_queue,
+    let separate_transfer = upload_context.has_separate_transfer();
+    let (src_queue_family_index, dst_queue_family_index) = match separate_transfer {
+        true => (upload_context.transfer_queue.family_index, upload_context.graphics_queue.family_index),
+        false => (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED),
+    };
+    let (copy_command_buffer, copy_timeline, copy_queue) = match separate_transfer {
+        true => (upload_context.transfer_command_buffer, upload_context.transfer_timeline, upload_context.transfer_queue),
+        false => (upload_context.graphics_command_buffer, upload_context.graphics_timeline, upload_context.graphics_queue),
+    };
+
+    let copy_ticket = record_submit_timeline_commandbuffer(
+        &device,
+        copy_command_buffer,
+        copy_timeline,
+        copy_queue,
+        &[],
         &[],
         &[],
         &[],
@@ -130,12 +190,7 @@ pub fn submit_commandbuffer_to_load_image(device: Arc<Mutex<ash::Device>>, setup
                 dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
                 new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
                 image: tex.texture_image,
-                subresource_range: vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    level_count: 1,
-                    layer_count: 1,
-                    ..Default::default()
-                },
+                subresource_range: make_color_subresource_range(),
                 ..Default::default()
             };
             unsafe { device.cmd_pipeline_barrier(
@@ -162,24 +217,28 @@ pub fn submit_commandbuffer_to_load_image(device: Arc<Mutex<ash::Device>>, setup
                 vk::ImageLayout::TRANSFER_DST_OPTIMAL,
                 &[buffer_copy_regions],
             ) };
+            // with a separate transfer family this is the release half of the ownership transfer
             let texture_barrier_end = vk::ImageMemoryBarrier {
                 src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
-                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                dst_access_mask: match separate_transfer {
+                    true => vk::AccessFlags::empty(),
+                    false => vk::AccessFlags::SHADER_READ,
+                },
                 old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
                 new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                src_queue_family_index,
+                dst_queue_family_index,
                 image: tex.texture_image,
-                subresource_range: vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    level_count: 1,
-                    layer_count: 1,
-                    ..Default::default()
-                },
+                subresource_range: make_color_subresource_range(),
                 ..Default::default()
             };
             unsafe { device.cmd_pipeline_barrier(
                 texture_command_buffer,
                 vk::PipelineStageFlags::TRANSFER,
-                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                match separate_transfer {
+                    true => vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    false => vk::PipelineStageFlags::FRAGMENT_SHADER,
+                },
                 vk::DependencyFlags::empty(),
                 &[],
                 &[],
@@ -187,4 +246,48 @@ pub fn submit_commandbuffer_to_load_image(device: Arc<Mutex<ash::Device>>, setup
             ) };
         },
     );
+
+    if !separate_transfer {
+        tex.last_use.mark(copy_ticket);
+        img.last_use.mark(copy_ticket);
+        return copy_ticket;
+    }
+
+    // acquire half, must repeat the release barrier's layouts and queue families exactly
+    let acquire_ticket = record_submit_timeline_commandbuffer(
+        &device,
+        upload_context.graphics_command_buffer,
+        upload_context.graphics_timeline,
+        upload_context.graphics_queue,
+        &[],
+        &[],
+        &[(upload_context.transfer_timeline, copy_ticket, vk::PipelineStageFlags::FRAGMENT_SHADER)],
+        &[],
+        |device, acquire_command_buffer| {
+            let acquire_barrier = vk::ImageMemoryBarrier {
+                src_access_mask: vk::AccessFlags::empty(),
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                src_queue_family_index,
+                dst_queue_family_index,
+                image: tex.texture_image,
+                subresource_range: make_color_subresource_range(),
+                ..Default::default()
+            };
+            unsafe { device.cmd_pipeline_barrier(
+                acquire_command_buffer,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[acquire_barrier],
+            ) };
+        },
+    );
+    // the acquire waits for the copy, so its graphics ticket covers the staging image too
+    tex.last_use.mark(acquire_ticket);
+    img.last_use.mark(acquire_ticket);
+    acquire_ticket
 }
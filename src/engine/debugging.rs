@@ -1,14 +1,69 @@
 use std::borrow::Cow;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
 use ash::ext::debug_utils;
 use ash::vk;
+use log::Level;
 use std::os::raw::c_void;
 use std::ffi::CStr;
 
+/// Log target every messenger message goes to
+pub const VULKAN_LOG_TARGET: &str = "vulkan";
+
+/// What the messenger reports and how validation errors are treated
+#[derive(Debug, Clone, Copy)]
+pub struct DebugMessengerConfig {
+    /// Messages below this are never delivered, INFO and VERBOSE are mostly loader chatter
+    pub min_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    /// `VulkanDebugger::check_validation_errors` panics once an error was reported, on by default in test builds
+    pub panic_on_validation_error: bool,
+}
+
+impl Default for DebugMessengerConfig {
+    fn default() -> Self {
+        Self {
+            min_severity: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING,
+            panic_on_validation_error: cfg!(test),
+        }
+    }
+}
+
+/// Shared with the callback through its user data pointer, boxed so the address stays put
+#[derive(Default)]
+pub struct DebugMessageCounts {
+    pub errors: AtomicU64,
+    pub warnings: AtomicU64,
+    pub first_error: Mutex<Option<String>>,
+}
+
+fn severities_from(min_severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> vk::DebugUtilsMessageSeverityFlagsEXT {
+    [
+        vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING,
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+    ]
+    .into_iter()
+    .filter(|severity| severity.as_raw() >= min_severity.as_raw())
+    .fold(vk::DebugUtilsMessageSeverityFlagsEXT::empty(), |flags, severity| flags | severity)
+}
+
+/// Accepts `verbose`, `info`, `warning` and `error`
+pub fn parse_message_severity(value: &str) -> Option<vk::DebugUtilsMessageSeverityFlagsEXT> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "verbose" => Some(vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE),
+        "info" => Some(vk::DebugUtilsMessageSeverityFlagsEXT::INFO),
+        "warning" | "warn" => Some(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING),
+        "error" => Some(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR),
+        _ => None,
+    }
+}
+
 pub unsafe extern "system" fn vulkan_debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT<'_>,
-    _user_data: *mut c_void,
+    user_data: *mut c_void,
 ) -> vk::Bool32 {
     let callback_data = *p_callback_data;
     let message_id_number = callback_data.message_id_number;
@@ -25,7 +80,26 @@ pub unsafe extern "system" fn vulkan_debug_callback(
         CStr::from_ptr(callback_data.p_message).to_string_lossy()
     };
 
-    println!("{message_severity:?}:\n{message_type:?} [{message_id_name} ({message_id_number})] : {message}\n");
+    let level = match message_severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => Level::Error,
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => Level::Warn,
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => Level::Info,
+        _ => Level::Debug,
+    };
+    log::log!(target: VULKAN_LOG_TARGET, level, "{message_type:?} [{message_id_name} ({message_id_number})] : {message}");
+
+    if let Some(counts) = (user_data as *const DebugMessageCounts).as_ref() {
+        match level {
+            Level::Error => {
+                counts.errors.fetch_add(1, Ordering::Relaxed);
+                counts.first_error.lock().unwrap().get_or_insert_with(|| message.into_owned());
+            }
+            Level::Warn => {
+                counts.warnings.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
 
     vk::FALSE
 }
@@ -33,22 +107,25 @@ pub unsafe extern "system" fn vulkan_debug_callback(
 pub struct VulkanDebugger {
     debug_call_back: vk::DebugUtilsMessengerEXT,
     debug_utils_loader: debug_utils::Instance,
+    pub config: DebugMessengerConfig,
+    counts: Box<DebugMessageCounts>,
 }
 
 impl VulkanDebugger {
-    pub unsafe fn new(entry: &ash::Entry, instance: &ash::Instance) -> Self {
+    /// # Safety
+    ///
+    /// `instance` must have been created from `entry` with `VK_EXT_debug_utils` enabled, and must outlive the debugger
+    pub unsafe fn new(entry: &ash::Entry, instance: &ash::Instance, config: DebugMessengerConfig) -> Self {
+        let counts = Box::<DebugMessageCounts>::default();
         let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
-            .message_severity(
-                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
-            )
+            .message_severity(severities_from(config.min_severity))
             .message_type(
                 vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
                     | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
                     | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
             )
-            .pfn_user_callback(Some(vulkan_debug_callback));
+            .pfn_user_callback(Some(vulkan_debug_callback))
+            .user_data(std::ptr::addr_of!(*counts).cast_mut().cast());
 
         let debug_utils_loader = debug_utils::Instance::new(entry, instance);
         let debug_call_back = debug_utils_loader
@@ -58,7 +135,27 @@ impl VulkanDebugger {
         Self {
             debug_call_back,
             debug_utils_loader,
+            config,
+            counts,
+        }
+    }
+
+    pub fn error_count(&self) -> u64 {
+        self.counts.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn warning_count(&self) -> u64 {
+        self.counts.warnings.load(Ordering::Relaxed)
+    }
+
+    /// Panics if configured to and an error was reported. Unwinding out of the callback itself would cross the
+    /// driver's stack, so errors are only counted there and surfaced here, the runner checks after every frame.
+    pub fn check_validation_errors(&self) {
+        if !self.config.panic_on_validation_error || self.error_count() == 0 {
+            return;
         }
+        let first_error = self.counts.first_error.lock().unwrap().clone().unwrap_or_default();
+        panic!("{} Vulkan validation error(s), the first was: {}", self.error_count(), first_error);
     }
 }
 
@@ -68,5 +165,8 @@ impl Drop for VulkanDebugger {
             self.debug_utils_loader
                 .destroy_debug_utils_messenger(self.debug_call_back, None);
         }
+        if self.error_count() > 0 || self.warning_count() > 0 {
+            log::warn!(target: VULKAN_LOG_TARGET, "{} validation error(s) and {} warning(s) were reported", self.error_count(), self.warning_count());
+        }
     }
 }
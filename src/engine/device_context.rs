@@ -0,0 +1,152 @@
+use std::{ffi::CString, ops::Deref, sync::{Mutex, MutexGuard}};
+
+use ash::{ext::debug_utils, vk, Device, Instance};
+
+use super::{gpu_timeline::{GpuTicket, LastUse}, retirement_queue::RetirementQueue, vulkan_physical_device::QueueFamilyIndices};
+
+/// A device queue, submissions and presents must hold its lock since Vulkan requires queue access to be
+/// externally synchronized
+pub struct VulkanQueue {
+    pub queue: vk::Queue,
+    pub family_index: u32,
+    submit_lock: Mutex<()>,
+}
+
+impl VulkanQueue {
+    pub fn new(device: &Device, family_index: u32) -> Self {
+        Self {
+            queue: unsafe { device.get_device_queue(family_index, 0) },
+            family_index,
+            submit_lock: Mutex::new(()),
+        }
+    }
+
+    /// Hold the returned guard for the duration of `queue_submit`, `queue_present` or `queue_wait_idle`
+    pub fn lock(&self) -> MutexGuard<'_, ()> {
+        self.submit_lock.lock().unwrap()
+    }
+}
+
+/// Everything needed to create resources and record commands, shared between threads as `Arc<DeviceContext>`.
+/// `ash::Device` is `Send + Sync` and Vulkan's synchronization rules apply per object, so the only locking
+/// here is around queues.
+pub struct DeviceContext {
+    pub instance: Instance,
+    pub physical_device: vk::PhysicalDevice,
+    pub device: Device,
+    pub memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pub queue_families: QueueFamilyIndices,
+    /// One per unique queue family, families that share a queue share its lock
+    pub queues: Vec<VulkanQueue>,
+    /// Object names and command buffer labels, `None` when the instance was created without debug utils
+    pub debug_utils: Option<debug_utils::Device>,
+    /// Handles of dropped wrappers the GPU may still be using
+    pub retirement: RetirementQueue,
+}
+
+impl DeviceContext {
+    /// `debug_utils` says whether the instance was created with the extension
+    pub fn new(instance: &Instance, physical_device: vk::PhysicalDevice, device: Device, queue_families: QueueFamilyIndices, debug_utils: bool) -> Self {
+        let memory_properties = unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let queues = queue_families
+            .unique()
+            .into_iter()
+            .map(|family_index| VulkanQueue::new(&device, family_index))
+            .collect();
+
+        let debug_utils = debug_utils.then(|| debug_utils::Device::new(instance, &device));
+
+        Self {
+            instance: instance.clone(),
+            physical_device,
+            device,
+            memory_properties,
+            queue_families,
+            queues,
+            debug_utils,
+            retirement: RetirementQueue::default(),
+        }
+    }
+
+    /// Shows up in validation messages and captures instead of the raw handle
+    pub fn set_object_name<H: vk::Handle>(&self, handle: H, name: &str) {
+        let Some(debug_utils) = self.debug_utils.as_ref() else {
+            return;
+        };
+        let name = CString::new(name).unwrap_or_default();
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+            .object_handle(handle)
+            .object_name(&name);
+        unsafe { debug_utils.set_debug_utils_object_name(&name_info).expect("Failed to set object name") };
+    }
+
+    pub fn begin_label(&self, command_buffer: vk::CommandBuffer, name: &str, color: [f32; 4]) {
+        let Some(debug_utils) = self.debug_utils.as_ref() else {
+            return;
+        };
+        let name = CString::new(name).unwrap_or_default();
+        let label = vk::DebugUtilsLabelEXT::default().label_name(&name).color(color);
+        unsafe { debug_utils.cmd_begin_debug_utils_label(command_buffer, &label) };
+    }
+
+    pub fn end_label(&self, command_buffer: vk::CommandBuffer) {
+        if let Some(debug_utils) = self.debug_utils.as_ref() {
+            unsafe { debug_utils.cmd_end_debug_utils_label(command_buffer) };
+        }
+    }
+
+    /// Wraps whatever `record` puts into `command_buffer` in a label region
+    pub fn label_scope<R>(&self, command_buffer: vk::CommandBuffer, name: &str, color: [f32; 4], record: impl FnOnce() -> R) -> R {
+        self.begin_label(command_buffer, name, color);
+        let result = record();
+        self.end_label(command_buffer);
+        result
+    }
+
+    pub fn queue(&self, family_index: u32) -> &VulkanQueue {
+        self.queues
+            .iter()
+            .find(|queue| queue.family_index == family_index)
+            .expect("No queue was created for this queue family")
+    }
+
+    pub fn graphics_queue(&self) -> &VulkanQueue {
+        self.queue(self.queue_families.graphics)
+    }
+
+    pub fn present_queue(&self) -> &VulkanQueue {
+        self.queue(self.queue_families.present)
+    }
+
+    pub fn transfer_queue(&self) -> &VulkanQueue {
+        self.queue(self.queue_families.transfer)
+    }
+
+    /// Called from wrappers' `Drop`, destroys their handles once the graphics timeline passes `last_use` or right
+    /// away when they were never submitted
+    pub fn retire<F: FnOnce(&Device) + Send + 'static>(&self, last_use: &LastUse, destroy: F) {
+        match last_use.ticket() {
+            0 => destroy(&self.device),
+            ticket => self.retirement.retire_after(ticket, destroy),
+        }
+    }
+
+    /// Destroys the retired handles whose last use is at most `completed`
+    pub fn destroy_retired(&self, completed: GpuTicket) -> usize {
+        self.retirement.destroy_until(&self.device, completed)
+    }
+
+    /// Locks every queue first, `vkDeviceWaitIdle` counts as access to all of them
+    pub fn wait_idle(&self) {
+        let _queue_guards: Vec<MutexGuard<'_, ()>> = self.queues.iter().map(|queue| queue.lock()).collect();
+        unsafe { self.device.device_wait_idle().expect("Failed to wait for device idle") };
+    }
+}
+
+impl Deref for DeviceContext {
+    type Target = Device;
+
+    fn deref(&self) -> &Device {
+        &self.device
+    }
+}
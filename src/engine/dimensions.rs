@@ -16,14 +16,6 @@ impl Dimensions {
         }
     }
 
-    pub fn extent(&self) -> vk::Extent3D {
-        vk::Extent3D {
-            width: self.width,
-            height: self.height,
-            depth: self.depth,
-        }
-    }
-
     pub fn extent2d(&self) -> vk::Extent2D {
         vk::Extent2D {
             width: self.width,
@@ -0,0 +1,410 @@
+use std::{env, error::Error, fs, path::{Path, PathBuf}};
+
+use log::LevelFilter;
+
+use super::{
+    debugging::{parse_message_severity, DebugMessengerConfig},
+    logging::{parse_level_filter, DEFAULT_LOG_LEVEL},
+    presentation::{PresentationConfig, VsyncMode},
+    vulkan_physical_device::PhysicalDeviceSelector,
+};
+
+/// Read from the working directory when neither `--config` nor `ENGINE_CONFIG` name a file
+pub const DEFAULT_CONFIG_PATH: &str = "engine.toml";
+/// Player bindings, applied over the app's defaults when the file exists
+pub const DEFAULT_BINDINGS_PATH: &str = "bindings.toml";
+/// Frames the CPU may record ahead of the GPU. The acquire semaphores rotate through the swapchain images, so more
+/// frames than images would reuse one that a frame still waits on.
+pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+
+/// Every setting as `key`, the runtime environment variable that overrides it and a description for `--help`
+pub const CONFIG_KEYS: &[(&str, &str, &str)] = &[
+    ("window.width", "WINDOW_WIDTH", "inner window width in logical pixels"),
+    ("window.height", "WINDOW_HEIGHT", "inner window height in logical pixels"),
+    ("window.x", "WINDOW_X", "window position, used together with window.y"),
+    ("window.y", "WINDOW_Y", "window position, used together with window.x"),
+    ("window.mode", "WINDOW_MODE", "windowed, borderless or always_on_top"),
+    ("validation.enabled", "VULKAN_DEBUG", "enable the validation layer and debug messenger"),
+    ("validation.severity", "VULKAN_LOG_SEVERITY", "lowest message severity, verbose, info, warning or error"),
+    ("validation.panic_on_error", "VULKAN_PANIC_ON_ERROR", "panic after a frame that reported a validation error"),
+    ("log_level", "LOG_LEVEL", "off, error, warn, info, debug or trace"),
+    ("device", "VULKAN_DEVICE", "physical device index or part of its name"),
+    ("vsync", "VSYNC", "on, adaptive or off"),
+    ("swapchain_images", "SWAPCHAIN_IMAGES", "swapchain image count, clamped to what the surface allows"),
+    ("hdr", "HDR", "prefer an HDR swapchain when the surface offers one"),
+    ("msaa_samples", "MSAA_SAMPLES", "scene sample count, clamped to what the device supports"),
+    ("frames_in_flight", "FRAMES_IN_FLIGHT", "frames recorded ahead of the GPU, 1 or 2"),
+    ("recording_threads", "RECORDING_THREADS", "worker threads recording the scene"),
+    ("fps_limit", "FPS_LIMIT", "frame rate cap, 0 for none"),
+    ("profile_trace", "PROFILE_TRACE", "write a Chrome trace to this path on exit"),
+    ("input_bindings", "INPUT_BINDINGS", "input bindings file applied over the app's defaults"),
+    ("input_record", "INPUT_RECORD", "write every frame's input events to this path"),
+    ("input_replay", "INPUT_REPLAY", "replay a recording instead of window input, exiting when it ends"),
+    ("shader_directory", "SHADER_DIRECTORY", "load compiled shaders from this directory instead of the embedded ones"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowMode {
+    Windowed,
+    Borderless,
+    AlwaysOnTop,
+}
+
+impl WindowMode {
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode.trim().to_lowercase().as_str() {
+            "windowed" => Some(Self::Windowed),
+            "borderless" | "fullscreen" => Some(Self::Borderless),
+            "always_on_top" | "on_top" => Some(Self::AlwaysOnTop),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowConfig {
+    pub width: u32,
+    pub height: u32,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub mode: WindowMode,
+}
+
+impl WindowConfig {
+    pub fn position(&self) -> Option<(i32, i32)> {
+        self.x.zip(self.y)
+    }
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            x: None,
+            y: None,
+            mode: WindowMode::Windowed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ValidationConfig {
+    pub enabled: bool,
+    pub messenger: DebugMessengerConfig,
+}
+
+/// Startup settings, layered as defaults, then the config file, then environment variables, then command line flags
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub window: WindowConfig,
+    pub validation: ValidationConfig,
+    pub log_level: LevelFilter,
+    pub device: Option<PhysicalDeviceSelector>,
+    pub presentation: PresentationConfig,
+    pub msaa_samples: u32,
+    pub frames_in_flight: usize,
+    /// `None` picks from the available parallelism
+    pub recording_threads: Option<usize>,
+    pub fps_limit: Option<f32>,
+    pub profile_trace: Option<PathBuf>,
+    pub input_bindings: PathBuf,
+    pub input_record: Option<PathBuf>,
+    pub input_replay: Option<PathBuf>,
+    /// `None` uses the shaders build.rs compiled into the binary
+    pub shader_directory: Option<PathBuf>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            window: WindowConfig::default(),
+            validation: ValidationConfig {
+                enabled: false,
+                messenger: DebugMessengerConfig::default(),
+            },
+            log_level: DEFAULT_LOG_LEVEL,
+            device: None,
+            presentation: PresentationConfig::default(),
+            msaa_samples: 4,
+            frames_in_flight: MAX_FRAMES_IN_FLIGHT,
+            recording_threads: None,
+            fps_limit: None,
+            profile_trace: None,
+            input_bindings: PathBuf::from(DEFAULT_BINDINGS_PATH),
+            input_record: None,
+            input_replay: None,
+            shader_directory: None,
+        }
+    }
+}
+
+/// Read before the config existed, when anything but `1` meant off, so other values still turn them off
+const LENIENT_ENV_FLAGS: &[&str] = &["VULKAN_DEBUG", "VULKAN_PANIC_ON_ERROR"];
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "on" | "yes" => Some(true),
+        "0" | "false" | "off" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.trim().parse().map_err(|_| format!("Invalid value {:?} for {}", value, key))
+}
+
+fn or_invalid<T>(key: &str, value: &str, parsed: Option<T>) -> Result<T, String> {
+    parsed.ok_or_else(|| format!("Invalid value {:?} for {}", value, key))
+}
+
+impl EngineConfig {
+    /// Defaults, then the config file, then environment variables, then `args` (without the program name). `None`
+    /// when `--help` was passed, the caller shows `print_help` and exits.
+    pub fn load<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Self>, Box<dyn Error>> {
+        Self::load_with(args, |variable| env::var(variable).ok())
+    }
+
+    /// `load` reading environment variables through `env_var`
+    pub fn load_with<I, EnvVar>(args: I, env_var: EnvVar) -> Result<Option<Self>, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = String>,
+        EnvVar: Fn(&str) -> Option<String>,
+    {
+        let Some(flags) = parse_flags(args)? else {
+            return Ok(None);
+        };
+        let mut config = Self::default();
+
+        let explicit_path = flags
+            .iter()
+            .find(|(key, _)| key == "config")
+            .map(|(_, path)| PathBuf::from(path))
+            .or_else(|| env_var("ENGINE_CONFIG").map(PathBuf::from));
+        match explicit_path {
+            Some(path) => config.apply_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => config.apply_file(Path::new(DEFAULT_CONFIG_PATH))?,
+            None => {}
+        }
+
+        config.apply_env_with(env_var)?;
+
+        for (key, value) in flags.iter().filter(|(key, _)| key != "config") {
+            config.set(key, value)?;
+        }
+
+        config.validate();
+        Ok(Some(config))
+    }
+
+    /// Applies a TOML file, tables nest keys so `[window] width = 1280` sets `window.width`
+    pub fn apply_file(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let contents = fs::read_to_string(path).map_err(|error| format!("Failed to read config {}: {}", path.display(), error))?;
+        let table: toml::Table = contents.parse().map_err(|error| format!("Failed to parse config {}: {}", path.display(), error))?;
+
+        let mut entries = Vec::new();
+        flatten_table("", &table, &mut entries);
+        for (key, value) in entries {
+            self.set(&key, &value).map_err(|error| format!("{} in {}", error, path.display()))?;
+        }
+        Ok(())
+    }
+
+    pub fn apply_env_with(&mut self, env_var: impl Fn(&str) -> Option<String>) -> Result<(), Box<dyn Error>> {
+        for (key, variable, _) in CONFIG_KEYS {
+            if let Some(mut value) = env_var(variable) {
+                if LENIENT_ENV_FLAGS.contains(variable) && parse_bool(&value).is_none() {
+                    log::warn!("{}={:?} is not a boolean, treating it as off", variable, value);
+                    value = "0".to_string();
+                }
+                self.set(key, &value).map_err(|error| format!("{} from {}", error, variable))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "window.width" => self.window.width = parse_value(key, value)?,
+            "window.height" => self.window.height = parse_value(key, value)?,
+            "window.x" => self.window.x = Some(parse_value(key, value)?),
+            "window.y" => self.window.y = Some(parse_value(key, value)?),
+            "window.mode" => self.window.mode = or_invalid(key, value, WindowMode::parse(value))?,
+            "validation.enabled" => self.validation.enabled = or_invalid(key, value, parse_bool(value))?,
+            "validation.severity" => self.validation.messenger.min_severity = or_invalid(key, value, parse_message_severity(value))?,
+            "validation.panic_on_error" => self.validation.messenger.panic_on_validation_error = or_invalid(key, value, parse_bool(value))?,
+            "log_level" => self.log_level = or_invalid(key, value, parse_level_filter(value))?,
+            "device" => self.device = Some(PhysicalDeviceSelector::parse(value)),
+            "vsync" => self.presentation.vsync = or_invalid(key, value, VsyncMode::parse(value))?,
+            "swapchain_images" => self.presentation.image_count = Some(parse_value(key, value)?),
+            "hdr" => self.presentation.hdr = or_invalid(key, value, parse_bool(value))?,
+            "msaa_samples" => self.msaa_samples = parse_value(key, value)?,
+            "frames_in_flight" => self.frames_in_flight = parse_value(key, value)?,
+            "recording_threads" => self.recording_threads = Some(parse_value(key, value)?),
+            "fps_limit" => self.fps_limit = Some(parse_value::<f32>(key, value)?).filter(|limit| *limit > 0.0),
+            "profile_trace" => self.profile_trace = Some(PathBuf::from(value.trim())),
+            "input_bindings" => self.input_bindings = PathBuf::from(value.trim()),
+            "input_record" => self.input_record = Some(PathBuf::from(value.trim())),
+            "input_replay" => self.input_replay = Some(PathBuf::from(value.trim())),
+            "shader_directory" => self.shader_directory = Some(PathBuf::from(value.trim())),
+            _ => return Err(format!("Unknown config key {}, see --help", key)),
+        }
+        Ok(())
+    }
+
+    /// Clamps values that are out of range instead of failing, device limits are applied later
+    fn validate(&mut self) {
+        if !(1..=MAX_FRAMES_IN_FLIGHT).contains(&self.frames_in_flight) {
+            let clamped = self.frames_in_flight.clamp(1, MAX_FRAMES_IN_FLIGHT);
+            log::warn!("frames_in_flight {} is out of range, using {}", self.frames_in_flight, clamped);
+            self.frames_in_flight = clamped;
+        }
+        self.window.width = self.window.width.max(1);
+        self.window.height = self.window.height.max(1);
+        self.recording_threads = self.recording_threads.map(|threads| threads.max(1));
+    }
+}
+
+type Flags = Vec<(String, String)>;
+
+/// Turns `--key=value`, `--key value` and bare `--key` (meaning true) into pairs, `None` if help was asked for
+fn parse_flags<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Flags>, Box<dyn Error>> {
+    let mut flags = Vec::new();
+    let mut args = args.into_iter().peekable();
+
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            return Ok(None);
+        }
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(format!("Unexpected argument {}, flags look like --window.width=1280", arg).into());
+        };
+
+        match flag.split_once('=') {
+            Some((key, value)) => flags.push((key.to_string(), value.to_string())),
+            None => {
+                let value = match args.peek() {
+                    Some(next) if !next.starts_with("--") => args.next().unwrap(),
+                    _ => "true".to_string(),
+                };
+                flags.push((flag.to_string(), value));
+            }
+        }
+    }
+
+    Ok(Some(flags))
+}
+
+fn flatten_table(prefix: &str, table: &toml::Table, entries: &mut Vec<(String, String)>) {
+    for (name, value) in table.iter() {
+        let key = match prefix {
+            "" => name.clone(),
+            prefix => format!("{}.{}", prefix, name),
+        };
+        match value {
+            toml::Value::Table(table) => flatten_table(&key, table, entries),
+            toml::Value::String(value) => entries.push((key, value.clone())),
+            value => entries.push((key, value.to_string())),
+        }
+    }
+}
+
+pub fn print_help() {
+    println!("Settings are read from {} (or --config / ENGINE_CONFIG), then environment variables, then flags\n", DEFAULT_CONFIG_PATH);
+    for (key, variable, description) in CONFIG_KEYS {
+        println!("  --{:<28} {:<24} {}", key, variable, description);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::test_directory::TestDirectory;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn load(arguments: &[&str], variables: &[(&str, &str)]) -> Result<Option<EngineConfig>, Box<dyn Error>> {
+        let variables: Vec<(String, String)> = variables.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        EngineConfig::load_with(args(arguments), |variable| {
+            variables.iter().find(|(name, _)| name == variable).map(|(_, value)| value.clone())
+        })
+    }
+
+    #[test]
+    fn flags_override_the_environment_which_overrides_the_file() {
+        let directory = TestDirectory::with_files("config-layers", &[("config.toml", "msaa_samples = 2\nvsync = \"off\"\n\n[window]\nwidth = 1000\nheight = 700\n")]);
+        let path = directory.path().join("config.toml");
+        let config = load(
+            &["--config", path.to_str().unwrap(), "--window.width=1200"],
+            &[("WINDOW_WIDTH", "1100"), ("MSAA_SAMPLES", "8")],
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(config.window.width, 1200);
+        assert_eq!(config.window.height, 700);
+        assert_eq!(config.msaa_samples, 8);
+        assert_eq!(config.presentation.vsync, VsyncMode::Off);
+        assert_eq!(config.log_level, DEFAULT_LOG_LEVEL);
+    }
+
+    #[test]
+    fn the_config_file_can_come_from_the_environment() {
+        let directory = TestDirectory::with_files("config-env", &[("config.toml", "log_level = \"debug\"\n")]);
+        let path = directory.path().join("config.toml");
+        let config = load(&[], &[("ENGINE_CONFIG", path.to_str().unwrap())]).unwrap().unwrap();
+        assert_eq!(config.log_level, LevelFilter::Debug);
+    }
+
+    #[test]
+    fn bare_flags_mean_true_and_separate_values_are_accepted() {
+        let config = load(&["--hdr", "--validation.enabled", "--msaa_samples", "2"], &[]).unwrap().unwrap();
+        assert!(config.presentation.hdr);
+        assert!(config.validation.enabled);
+        assert_eq!(config.msaa_samples, 2);
+    }
+
+    #[test]
+    fn validation_errors_panic_in_test_builds_unless_turned_off() {
+        assert!(EngineConfig::default().validation.messenger.panic_on_validation_error);
+        let config = load(&[], &[("VULKAN_PANIC_ON_ERROR", "0")]).unwrap().unwrap();
+        assert!(!config.validation.messenger.panic_on_validation_error);
+    }
+
+    #[test]
+    fn old_debug_variables_treat_unknown_values_as_off() {
+        assert!(!load(&[], &[("VULKAN_DEBUG", "yes please")]).unwrap().unwrap().validation.enabled);
+        assert!(load(&[], &[("VULKAN_DEBUG", "1")]).unwrap().unwrap().validation.enabled);
+        assert!(!load(&[], &[("VULKAN_PANIC_ON_ERROR", "")]).unwrap().unwrap().validation.messenger.panic_on_validation_error);
+        assert!(load(&["--validation.enabled=maybe"], &[]).is_err());
+    }
+
+    #[test]
+    fn help_stops_loading() {
+        assert!(load(&["--window.width=1200", "--help"], &[]).unwrap().is_none());
+        assert!(load(&["-h"], &[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn out_of_range_values_are_clamped() {
+        let config = load(&["--frames_in_flight=4", "--window.width=0", "--recording_threads=0"], &[]).unwrap().unwrap();
+        assert_eq!(config.frames_in_flight, MAX_FRAMES_IN_FLIGHT);
+        assert_eq!(config.window.width, 1);
+        assert_eq!(config.recording_threads, Some(1));
+    }
+
+    #[test]
+    fn invalid_settings_are_errors() {
+        assert!(load(&["--window.width=wide"], &[]).is_err());
+        assert!(load(&["--no_such_key=1"], &[]).is_err());
+        assert!(load(&["window.width=1200"], &[]).is_err());
+        assert!(load(&[], &[("VSYNC", "sometimes")]).is_err());
+
+        let directory = TestDirectory::with_files("config-invalid", &[("config.toml", "[window]\nmode = \"sideways\"\n")]);
+        let path = directory.path().join("config.toml");
+        let error = load(&["--config", path.to_str().unwrap()], &[]).unwrap_err().to_string();
+        assert!(error.contains("window.mode"), "{}", error);
+    }
+}
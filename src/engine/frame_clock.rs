@@ -0,0 +1,243 @@
+use std::{collections::VecDeque, thread, time::{Duration, Instant}};
+
+/// Rate fixed updates run at unless the app picks another one
+pub const DEFAULT_FIXED_UPDATE_RATE: f32 = 60.0;
+/// Upper bound on fixed updates per frame so a long stall doesn't spiral into ever longer frames
+pub const MAX_FIXED_UPDATES_PER_FRAME: u32 = 8;
+/// Frames kept for the rolling statistics, a few seconds at typical refresh rates
+pub const FRAME_TIME_HISTORY: usize = 240;
+/// Sleeping overshoots by up to a scheduler tick, the last stretch before a frame limit is spun instead
+const LIMITER_SPIN_MARGIN: Duration = Duration::from_millis(1);
+
+/// Frame times in seconds over the last `FRAME_TIME_HISTORY` frames
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameTimeStatistics {
+    pub min: f32,
+    pub average: f32,
+    pub max: f32,
+    pub p50: f32,
+    pub p95: f32,
+    pub p99: f32,
+}
+
+impl FrameTimeStatistics {
+    pub fn average_fps(&self) -> f32 {
+        if self.average > 0.0 { 1.0 / self.average } else { 0.0 }
+    }
+}
+
+/// Measures frame deltas, accumulates time for fixed updates and optionally caps the frame rate
+pub struct FrameClock {
+    last_frame: Instant,
+    /// Sum of the deltas rather than wall time, so replays with recorded deltas see the same times
+    total: f64,
+    frame_count: u64,
+    fixed_timestep: f32,
+    fixed_accumulator: f32,
+    fps_limit: Option<f32>,
+    frame_times: VecDeque<f32>,
+}
+
+impl FrameClock {
+    /// Rates that aren't positive and finite fall back to `DEFAULT_FIXED_UPDATE_RATE`
+    pub fn new(fixed_update_rate: f32, fps_limit: Option<f32>) -> Self {
+        let fixed_update_rate = match fixed_update_rate.is_finite() && fixed_update_rate > 0.0 {
+            true => fixed_update_rate,
+            false => {
+                log::warn!("Fixed update rate {} is not a positive number, using {}", fixed_update_rate, DEFAULT_FIXED_UPDATE_RATE);
+                DEFAULT_FIXED_UPDATE_RATE
+            }
+        };
+        let now = Instant::now();
+        Self {
+            last_frame: now,
+            total: 0.0,
+            frame_count: 0,
+            fixed_timestep: 1.0 / fixed_update_rate,
+            fixed_accumulator: 0.0,
+            fps_limit: fps_limit.filter(|limit| *limit > 0.0),
+            frame_times: VecDeque::with_capacity(FRAME_TIME_HISTORY),
+        }
+    }
+
+    /// Waits out the frame limit if there is one, then starts a new frame. Returns the delta in seconds.
+    pub fn tick(&mut self) -> f32 {
+        let delta = self.wait_for_frame();
+        self.advance(delta)
+    }
+
+    /// Paces like `tick` but advances by `delta` instead of the measured time, replays use it so fixed updates
+    /// run exactly as often as they did while recording
+    pub fn tick_replayed(&mut self, delta: f32) -> f32 {
+        self.wait_for_frame();
+        self.advance(delta)
+    }
+
+    fn wait_for_frame(&mut self) -> f32 {
+        if let Some(fps_limit) = self.fps_limit {
+            let frame_end = self.last_frame + Duration::from_secs_f32(1.0 / fps_limit);
+            let now = Instant::now();
+            if frame_end > now + LIMITER_SPIN_MARGIN {
+                thread::sleep(frame_end - now - LIMITER_SPIN_MARGIN);
+            }
+            while Instant::now() < frame_end {
+                std::hint::spin_loop();
+            }
+        }
+
+        let now = Instant::now();
+        let delta = now.duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+        delta
+    }
+
+    fn advance(&mut self, delta: f32) -> f32 {
+        self.total += delta as f64;
+        self.frame_count += 1;
+
+        if self.frame_times.len() == FRAME_TIME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(delta);
+
+        // drop time that can't be caught up on instead of running the backlog over the next frames
+        let max_accumulated = self.fixed_timestep * MAX_FIXED_UPDATES_PER_FRAME as f32;
+        self.fixed_accumulator = (self.fixed_accumulator + delta).min(max_accumulated);
+
+        delta
+    }
+
+    /// Seconds since the clock was created
+    pub fn total(&self) -> f64 {
+        self.total
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn fixed_timestep(&self) -> f32 {
+        self.fixed_timestep
+    }
+
+    /// Takes one fixed step out of the accumulator, call until it returns false
+    pub fn consume_fixed_step(&mut self) -> bool {
+        if self.fixed_accumulator < self.fixed_timestep {
+            return false;
+        }
+        self.fixed_accumulator -= self.fixed_timestep;
+        true
+    }
+
+    /// How far the current frame is between the last fixed update and the next one, for interpolating state
+    pub fn alpha(&self) -> f32 {
+        self.fixed_accumulator / self.fixed_timestep
+    }
+
+    pub fn fps_limit(&self) -> Option<f32> {
+        self.fps_limit
+    }
+
+    pub fn set_fps_limit(&mut self, fps_limit: Option<f32>) {
+        self.fps_limit = fps_limit.filter(|limit| *limit > 0.0);
+    }
+
+    pub fn statistics(&self) -> FrameTimeStatistics {
+        if self.frame_times.is_empty() {
+            return FrameTimeStatistics::default();
+        }
+
+        let mut sorted: Vec<f32> = self.frame_times.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+        let percentile = |percent: f32| sorted[((sorted.len() - 1) as f32 * percent).round() as usize];
+
+        FrameTimeStatistics {
+            min: sorted[0],
+            average: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            max: sorted[sorted.len() - 1],
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed_steps(clock: &mut FrameClock) -> u32 {
+        let mut steps = 0;
+        while clock.consume_fixed_step() {
+            steps += 1;
+        }
+        steps
+    }
+
+    #[test]
+    fn advance_sums_deltas() {
+        let mut clock = FrameClock::new(10.0, None);
+        assert_eq!(clock.tick_replayed(0.25), 0.25);
+        assert_eq!(clock.tick_replayed(0.5), 0.5);
+
+        assert_eq!(clock.total(), 0.75);
+        assert_eq!(clock.frame_count(), 2);
+    }
+
+    #[test]
+    fn fixed_steps_consume_the_accumulator_and_leave_the_remainder_as_alpha() {
+        let mut clock = FrameClock::new(4.0, None);
+        assert_eq!(clock.fixed_timestep(), 0.25);
+
+        clock.advance(0.125);
+        assert_eq!(fixed_steps(&mut clock), 0);
+        assert_eq!(clock.alpha(), 0.5);
+
+        clock.advance(0.625);
+        assert_eq!(fixed_steps(&mut clock), 3);
+        assert_eq!(clock.alpha(), 0.0);
+    }
+
+    #[test]
+    fn long_frames_are_clamped_to_the_fixed_update_limit() {
+        let mut clock = FrameClock::new(4.0, None);
+        clock.advance(100.0);
+
+        assert_eq!(fixed_steps(&mut clock), MAX_FIXED_UPDATES_PER_FRAME);
+        assert_eq!(clock.alpha(), 0.0);
+        assert_eq!(clock.total(), 100.0);
+    }
+
+    #[test]
+    fn invalid_fixed_update_rates_fall_back_to_the_default() {
+        for rate in [0.0, -30.0, f32::NAN, f32::INFINITY] {
+            assert_eq!(FrameClock::new(rate, None).fixed_timestep(), 1.0 / DEFAULT_FIXED_UPDATE_RATE);
+        }
+        assert_eq!(FrameClock::new(60.0, Some(0.0)).fps_limit(), None);
+    }
+
+    #[test]
+    fn statistics_cover_the_recent_history() {
+        let mut clock = FrameClock::new(DEFAULT_FIXED_UPDATE_RATE, None);
+        assert_eq!(clock.statistics().average_fps(), 0.0);
+
+        for frame in 1..=100 {
+            clock.advance(frame as f32 / 1000.0);
+        }
+        let statistics = clock.statistics();
+        assert_eq!(statistics.min, 0.001);
+        assert_eq!(statistics.max, 0.1);
+        assert!((statistics.average - 0.0505).abs() < 1e-6);
+        assert_eq!(statistics.p50, 0.051);
+        assert_eq!(statistics.p95, 0.095);
+        assert_eq!(statistics.p99, 0.099);
+
+        // only the last FRAME_TIME_HISTORY frames count
+        for _ in 0..FRAME_TIME_HISTORY {
+            clock.advance(0.02);
+        }
+        let statistics = clock.statistics();
+        assert_eq!((statistics.min, statistics.max, statistics.p99), (0.02, 0.02, 0.02));
+        assert!((statistics.average_fps() - 50.0).abs() < 1e-3);
+    }
+}
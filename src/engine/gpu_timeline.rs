@@ -0,0 +1,100 @@
+use std::{cell::Cell, sync::{atomic::{AtomicU64, Ordering}, Arc}};
+
+use ash::vk;
+
+use super::device_context::DeviceContext;
+
+/// Value a timeline reaches once a particular submission has finished executing
+pub type GpuTicket = u64;
+
+/// Counts submissions to one queue with a timeline semaphore, every submission signals the next value so "has the GPU
+/// finished with this" becomes a comparison against the semaphore's counter. Only submit to a single queue per timeline,
+/// signals have to arrive in increasing order.
+pub struct GpuTimeline {
+    pub device: Arc<DeviceContext>,
+    pub semaphore: vk::Semaphore,
+    last_submitted: AtomicU64,
+}
+
+impl GpuTimeline {
+    pub fn new(device: Arc<DeviceContext>) -> Self {
+        let mut semaphore_type_info = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+        let semaphore_create_info = vk::SemaphoreCreateInfo::default().push_next(&mut semaphore_type_info);
+
+        let semaphore = unsafe { device
+            .create_semaphore(&semaphore_create_info, None)
+            .expect("Failed to create timeline semaphore") };
+
+        Self {
+            device,
+            semaphore,
+            last_submitted: AtomicU64::new(0),
+        }
+    }
+
+    /// Reserves the value the next submission signals, the submission must happen or later waits never return
+    pub fn next_ticket(&self) -> GpuTicket {
+        self.last_submitted.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    pub fn completed(&self) -> GpuTicket {
+        unsafe { self.device
+            .get_semaphore_counter_value(self.semaphore)
+            .expect("Failed to read timeline semaphore") }
+    }
+
+    pub fn wait(&self, ticket: GpuTicket) {
+        if ticket == 0 {
+            return;
+        }
+
+        let semaphores = [self.semaphore];
+        let values = [ticket];
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+        unsafe { self.device
+            .wait_semaphores(&wait_info, u64::MAX)
+            .expect("Failed to wait for timeline semaphore") };
+    }
+}
+
+impl Drop for GpuTimeline {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_semaphore(self.semaphore, None);
+        }
+    }
+}
+
+/// The ticket of the last graphics submission that used a resource. Clones share the ticket, so a frame can hold on to
+/// the ones the app recorded and stamp them once its own ticket is known. Never submitted resources stay at 0.
+#[derive(Clone, Default)]
+pub struct LastUse(Arc<AtomicU64>);
+
+impl LastUse {
+    pub fn mark(&self, ticket: GpuTicket) {
+        self.0.fetch_max(ticket, Ordering::SeqCst);
+    }
+
+    pub fn ticket(&self) -> GpuTicket {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A command buffer whose reuse is gated on the timeline ticket of its last submission instead of a fence
+pub struct TimelineCommandBuffer {
+    pub command_buffer: vk::CommandBuffer,
+    pub last_ticket: Cell<GpuTicket>,
+}
+
+impl TimelineCommandBuffer {
+    pub fn new(command_buffer: vk::CommandBuffer) -> Self {
+        Self {
+            command_buffer,
+            last_ticket: Cell::new(0),
+        }
+    }
+}
@@ -0,0 +1,442 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, error::Error, fmt, fs, path::Path};
+
+use super::{input_state::InputState, keyboard::{Key, Modifiers}, mouse::MouseButton};
+
+/// Touchpads scroll in pixels, this many count as one wheel notch on an axis
+pub const SCROLL_PIXELS_PER_LINE: f64 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WheelDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// What a digital binding listens to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingInput {
+    Key(Key),
+    Mouse(MouseButton),
+    /// Active for the frames the wheel turned this way
+    Wheel(WheelDirection),
+}
+
+/// An input with the modifiers that have to be held with it, `Ctrl+S` in a bindings file. Bindings without
+/// modifiers ignore them, so Shift+W still moves forward.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    pub modifiers: Modifiers,
+    pub input: BindingInput,
+}
+
+impl Binding {
+    pub fn key(key: Key) -> Self {
+        Self { modifiers: Modifiers::default(), input: BindingInput::Key(key) }
+    }
+
+    pub fn mouse(button: MouseButton) -> Self {
+        Self { modifiers: Modifiers::default(), input: BindingInput::Mouse(button) }
+    }
+
+    pub fn wheel(direction: WheelDirection) -> Self {
+        Self { modifiers: Modifiers::default(), input: BindingInput::Wheel(direction) }
+    }
+
+    pub fn with_modifiers(self, modifiers: Modifiers) -> Self {
+        Self { modifiers, ..self }
+    }
+
+    pub fn is_active(&self, input: &InputState) -> bool {
+        if !input.modifiers.contains(self.modifiers) {
+            return false;
+        }
+        match self.input {
+            BindingInput::Key(key) => input.is_down(key),
+            BindingInput::Mouse(button) => input.is_mouse_down(button),
+            BindingInput::Wheel(WheelDirection::Up) => input.scroll_lines.1 > 0.0 || input.scroll_pixels.1 > 0.0,
+            BindingInput::Wheel(WheelDirection::Down) => input.scroll_lines.1 < 0.0 || input.scroll_pixels.1 < 0.0,
+            BindingInput::Wheel(WheelDirection::Right) => input.scroll_lines.0 > 0.0 || input.scroll_pixels.0 > 0.0,
+            BindingInput::Wheel(WheelDirection::Left) => input.scroll_lines.0 < 0.0 || input.scroll_pixels.0 < 0.0,
+        }
+    }
+
+    /// Accepts `S`, `Ctrl+S`, `MouseLeft`, `MouseButton7` and `WheelUp`, names are case insensitive
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let input_name = parts.pop().filter(|name| !name.is_empty()).ok_or_else(|| format!("Empty binding {:?}", text))?;
+
+        let mut modifiers = Modifiers::default();
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "shift" => modifiers.shift = true,
+                "ctrl" | "control" => modifiers.control = true,
+                "alt" | "option" => modifiers.alt = true,
+                "super" | "cmd" | "command" | "win" => modifiers.super_key = true,
+                _ => return Err(format!("Unknown modifier {:?} in binding {:?}", modifier, text)),
+            }
+        }
+
+        let lowercase = input_name.to_ascii_lowercase();
+        let input = match lowercase.as_str() {
+            "wheelup" => BindingInput::Wheel(WheelDirection::Up),
+            "wheeldown" => BindingInput::Wheel(WheelDirection::Down),
+            "wheelleft" => BindingInput::Wheel(WheelDirection::Left),
+            "wheelright" => BindingInput::Wheel(WheelDirection::Right),
+            name => match name.strip_prefix("mouse") {
+                Some(button) => BindingInput::Mouse(MouseButton::parse(button).ok_or_else(|| format!("Unknown mouse button in binding {:?}", text))?),
+                None => BindingInput::Key(Key::parse(input_name).ok_or_else(|| format!("Unknown key in binding {:?}", text))?),
+            },
+        };
+
+        Ok(Self { modifiers, input })
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let modifiers = [
+            (self.modifiers.control, "Ctrl+"),
+            (self.modifiers.shift, "Shift+"),
+            (self.modifiers.alt, "Alt+"),
+            (self.modifiers.super_key, "Super+"),
+        ];
+        for (_, prefix) in modifiers.iter().filter(|(held, _)| *held) {
+            f.write_str(prefix)?;
+        }
+        match self.input {
+            BindingInput::Key(key) => f.write_str(key.name()),
+            BindingInput::Mouse(button) => write!(f, "Mouse{}", button.name()),
+            BindingInput::Wheel(direction) => write!(f, "Wheel{:?}", direction),
+        }
+    }
+}
+
+/// What an analog axis reads, values from several bindings of one axis are summed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AxisBinding {
+    /// -1 while `negative` is held, 1 while `positive` is, 0 for both, `A/D` in a bindings file
+    Buttons { negative: Binding, positive: Binding },
+    /// Wheel notches this frame, touchpad pixels are converted with `SCROLL_PIXELS_PER_LINE`
+    WheelX,
+    WheelY,
+    /// Raw mouse motion this frame in device units
+    MouseX,
+    MouseY,
+}
+
+impl AxisBinding {
+    pub fn buttons(negative: Binding, positive: Binding) -> Self {
+        Self::Buttons { negative, positive }
+    }
+
+    pub fn value(&self, input: &InputState) -> f32 {
+        match self {
+            Self::Buttons { negative, positive } => positive.is_active(input) as i32 as f32 - negative.is_active(input) as i32 as f32,
+            Self::WheelX => input.scroll_lines.0 + (input.scroll_pixels.0 / SCROLL_PIXELS_PER_LINE) as f32,
+            Self::WheelY => input.scroll_lines.1 + (input.scroll_pixels.1 / SCROLL_PIXELS_PER_LINE) as f32,
+            Self::MouseX => input.raw_mouse_delta.0 as f32,
+            Self::MouseY => input.raw_mouse_delta.1 as f32,
+        }
+    }
+
+    /// Accepts `WheelX`, `WheelY`, `MouseX`, `MouseY` or two bindings as `negative/positive`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.trim().to_ascii_lowercase().as_str() {
+            "wheelx" => Ok(Self::WheelX),
+            "wheely" => Ok(Self::WheelY),
+            "mousex" => Ok(Self::MouseX),
+            "mousey" => Ok(Self::MouseY),
+            _ => {
+                let (negative, positive) = text.split_once('/').ok_or_else(|| format!("Axis binding {:?} should look like A/D", text))?;
+                Ok(Self::buttons(Binding::parse(negative)?, Binding::parse(positive)?))
+            }
+        }
+    }
+}
+
+impl fmt::Display for AxisBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Buttons { negative, positive } => write!(f, "{}/{}", negative, positive),
+            Self::WheelX => f.write_str("WheelX"),
+            Self::WheelY => f.write_str("WheelY"),
+            Self::MouseX => f.write_str("MouseX"),
+            Self::MouseY => f.write_str("MouseY"),
+        }
+    }
+}
+
+/// Named digital actions and analog axes on top of `InputState`. Apps bind their defaults in `App::init`, the
+/// runner then applies the player's bindings file over them and calls `update` once per frame.
+///
+/// ```toml
+/// [actions]
+/// save = ["Ctrl+S"]
+/// jump = ["Space", "MouseRight"]
+///
+/// [axes]
+/// move_x = ["A/D", "Left/Right"]
+/// zoom = ["WheelY"]
+/// ```
+#[derive(Debug, Default)]
+pub struct InputActions {
+    actions: BTreeMap<String, Vec<Binding>>,
+    axes: BTreeMap<String, Vec<AxisBinding>>,
+    active: HashSet<String>,
+    previously_active: HashSet<String>,
+    axis_values: HashMap<String, f32>,
+}
+
+impl InputActions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a binding to `action`, actions can have any number of them
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        self.actions.entry(action.to_string()).or_default().push(binding);
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        self.axes.entry(axis.to_string()).or_default().push(binding);
+    }
+
+    /// Replaces every binding of `action`, for rebinding menus
+    pub fn rebind(&mut self, action: &str, bindings: Vec<Binding>) {
+        self.actions.insert(action.to_string(), bindings);
+    }
+
+    pub fn rebind_axis(&mut self, axis: &str, bindings: Vec<AxisBinding>) {
+        self.axes.insert(axis.to_string(), bindings);
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn axis_bindings(&self, axis: &str) -> &[AxisBinding] {
+        self.axes.get(axis).map_or(&[], Vec::as_slice)
+    }
+
+    /// Evaluates every action and axis against this frame's input, the runner calls it after applying the events
+    pub fn update(&mut self, input: &InputState) {
+        std::mem::swap(&mut self.active, &mut self.previously_active);
+        self.active.clear();
+        for (action, bindings) in self.actions.iter() {
+            if bindings.iter().any(|binding| binding.is_active(input)) {
+                self.active.insert(action.clone());
+            }
+        }
+
+        self.axis_values.clear();
+        for (axis, bindings) in self.axes.iter() {
+            let value = bindings.iter().map(|binding| binding.value(input)).sum();
+            self.axis_values.insert(axis.clone(), value);
+        }
+    }
+
+    pub fn is_active(&self, action: &str) -> bool {
+        self.active.contains(action)
+    }
+
+    /// True for the frame the action became active, a chord fires whichever of its inputs came last
+    pub fn just_activated(&self, action: &str) -> bool {
+        self.active.contains(action) && !self.previously_active.contains(action)
+    }
+
+    pub fn just_deactivated(&self, action: &str) -> bool {
+        !self.active.contains(action) && self.previously_active.contains(action)
+    }
+
+    /// 0 for unknown axes
+    pub fn axis(&self, axis: &str) -> f32 {
+        self.axis_values.get(axis).copied().unwrap_or(0.0)
+    }
+
+    /// Replaces the bindings of every action and axis listed in the file, the rest keep their defaults
+    pub fn apply_file(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let contents = fs::read_to_string(path).map_err(|error| format!("Failed to read bindings {}: {}", path.display(), error))?;
+        self.apply_toml(&contents).map_err(|error| format!("{} in {}", error, path.display()))?;
+        Ok(())
+    }
+
+    pub fn apply_toml(&mut self, contents: &str) -> Result<(), String> {
+        let table: toml::Table = contents.parse().map_err(|error| format!("Failed to parse bindings: {}", error))?;
+
+        for (section, value) in table.iter() {
+            let entries = value.as_table().ok_or_else(|| format!("[{}] should be a table", section))?;
+            for (name, bindings) in entries.iter() {
+                let texts = binding_strings(name, bindings)?;
+                match section.as_str() {
+                    "actions" => {
+                        let bindings = texts.iter().map(|text| Binding::parse(text)).collect::<Result<_, _>>()?;
+                        self.rebind(name, bindings);
+                    }
+                    "axes" => {
+                        let bindings = texts.iter().map(|text| AxisBinding::parse(text)).collect::<Result<_, _>>()?;
+                        self.rebind_axis(name, bindings);
+                    }
+                    _ => return Err(format!("Unknown bindings section [{}], expected [actions] or [axes]", section)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes every binding, so the file round trips through `apply_file`
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_toml()).map_err(|error| format!("Failed to write bindings {}: {}", path.display(), error))?;
+        Ok(())
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut table = toml::Table::new();
+        table.insert("actions".to_string(), toml::Value::Table(strings_table(&self.actions)));
+        table.insert("axes".to_string(), toml::Value::Table(strings_table(&self.axes)));
+        table.to_string()
+    }
+}
+
+fn binding_strings(name: &str, value: &toml::Value) -> Result<Vec<String>, String> {
+    match value {
+        toml::Value::String(text) => Ok(vec![text.clone()]),
+        toml::Value::Array(values) => values
+            .iter()
+            .map(|value| value.as_str().map(str::to_string).ok_or_else(|| format!("Bindings of {} should be strings", name)))
+            .collect(),
+        _ => Err(format!("Bindings of {} should be a string or a list of strings", name)),
+    }
+}
+
+fn strings_table<T: fmt::Display>(bindings: &BTreeMap<String, Vec<T>>) -> toml::Table {
+    bindings
+        .iter()
+        .map(|(name, bindings)| {
+            let values = bindings.iter().map(|binding| toml::Value::String(binding.to_string())).collect();
+            (name.clone(), toml::Value::Array(values))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{input_state::InputStateEvent, mouse::ScrollDelta};
+
+    const CONTROL: Modifiers = Modifiers { shift: false, control: true, alt: false, super_key: false };
+
+    #[test]
+    fn bindings_round_trip_through_display() {
+        let bindings = [
+            Binding::key(Key::S),
+            Binding::key(Key::S).with_modifiers(CONTROL),
+            Binding::key(Key::Z).with_modifiers(Modifiers { shift: true, control: true, alt: true, super_key: true }),
+            Binding::mouse(MouseButton::Right),
+            Binding::mouse(MouseButton::Other(7)),
+            Binding::wheel(WheelDirection::Up),
+            Binding::wheel(WheelDirection::Left).with_modifiers(CONTROL),
+        ];
+        for binding in bindings {
+            assert_eq!(Binding::parse(&binding.to_string()), Ok(binding), "{}", binding);
+        }
+        assert_eq!(Binding::key(Key::S).with_modifiers(CONTROL).to_string(), "Ctrl+S");
+        assert_eq!(Binding::mouse(MouseButton::Other(7)).to_string(), "MouseButton7");
+    }
+
+    #[test]
+    fn binding_names_are_case_insensitive_and_accept_aliases() {
+        assert_eq!(Binding::parse(" control + s "), Ok(Binding::key(Key::S).with_modifiers(CONTROL)));
+        assert_eq!(Binding::parse("mouseleft"), Ok(Binding::mouse(MouseButton::Left)));
+        assert_eq!(Binding::parse("WHEELDOWN"), Ok(Binding::wheel(WheelDirection::Down)));
+        assert_eq!(Binding::parse("Cmd+Q").map(|binding| binding.modifiers.super_key), Ok(true));
+    }
+
+    #[test]
+    fn invalid_bindings_are_rejected() {
+        assert!(Binding::parse("").is_err());
+        assert!(Binding::parse("Ctrl+").is_err());
+        assert!(Binding::parse("Hyper+S").is_err());
+        assert!(Binding::parse("NotAKey").is_err());
+        assert!(Binding::parse("MouseSideways").is_err());
+        assert!(AxisBinding::parse("A").is_err());
+        assert!(AxisBinding::parse("A/Hyper+D").is_err());
+    }
+
+    #[test]
+    fn axis_bindings_round_trip_through_display() {
+        let bindings = [
+            AxisBinding::buttons(Binding::key(Key::A), Binding::key(Key::D)),
+            AxisBinding::buttons(Binding::wheel(WheelDirection::Down), Binding::key(Key::Up).with_modifiers(CONTROL)),
+            AxisBinding::WheelX,
+            AxisBinding::WheelY,
+            AxisBinding::MouseX,
+            AxisBinding::MouseY,
+        ];
+        for binding in bindings {
+            assert_eq!(AxisBinding::parse(&binding.to_string()), Ok(binding), "{}", binding);
+        }
+        assert_eq!(AxisBinding::parse("wheely"), Ok(AxisBinding::WheelY));
+    }
+
+    #[test]
+    fn bindings_files_round_trip() {
+        let mut actions = InputActions::new();
+        actions.bind("save", Binding::key(Key::S).with_modifiers(CONTROL));
+        actions.bind("jump", Binding::key(Key::Space));
+        actions.bind("jump", Binding::mouse(MouseButton::Right));
+        actions.bind_axis("move_x", AxisBinding::buttons(Binding::key(Key::A), Binding::key(Key::D)));
+        actions.bind_axis("zoom", AxisBinding::WheelY);
+
+        let mut loaded = InputActions::new();
+        loaded.apply_toml(&actions.to_toml()).unwrap();
+        assert_eq!(loaded.bindings("save"), actions.bindings("save"));
+        assert_eq!(loaded.bindings("jump"), actions.bindings("jump"));
+        assert_eq!(loaded.axis_bindings("move_x"), actions.axis_bindings("move_x"));
+        assert_eq!(loaded.axis_bindings("zoom"), actions.axis_bindings("zoom"));
+    }
+
+    #[test]
+    fn bindings_files_only_replace_what_they_list() {
+        let mut actions = InputActions::new();
+        actions.bind("jump", Binding::key(Key::Space));
+        actions.bind("fire", Binding::mouse(MouseButton::Left));
+        actions.apply_toml("[actions]\njump = \"Ctrl+J\"\n\n[axes]\nzoom = [\"WheelY\", \"Minus/Equal\"]\n").unwrap();
+
+        assert_eq!(actions.bindings("jump"), [Binding::key(Key::J).with_modifiers(CONTROL)]);
+        assert_eq!(actions.bindings("fire"), [Binding::mouse(MouseButton::Left)]);
+        assert_eq!(actions.axis_bindings("zoom").len(), 2);
+
+        assert!(actions.apply_toml("[buttons]\njump = \"J\"\n").is_err());
+        assert!(actions.apply_toml("[actions]\njump = 3\n").is_err());
+        assert!(actions.apply_toml("[actions]\njump = \"Hyper+J\"\n").is_err());
+    }
+
+    #[test]
+    fn chords_need_their_modifiers_and_wheel_bindings_last_a_frame() {
+        let mut actions = InputActions::new();
+        actions.bind("save", Binding::key(Key::S).with_modifiers(CONTROL));
+        actions.bind("forward", Binding::key(Key::W));
+        actions.bind("zoom_in", Binding::wheel(WheelDirection::Up));
+        let mut input = InputState::new();
+
+        input.consume(InputStateEvent::KeyPressed(Key::S));
+        input.consume(InputStateEvent::KeyPressed(Key::W));
+        actions.update(&input);
+        assert!(!actions.is_active("save"));
+        assert!(actions.is_active("forward"));
+
+        input.begin_frame();
+        input.consume(InputStateEvent::ModifiersChanged(CONTROL));
+        input.consume(InputStateEvent::Scrolled(ScrollDelta::Lines(0.0, 1.0)));
+        actions.update(&input);
+        assert!(actions.just_activated("save"));
+        assert!(actions.is_active("forward"), "bindings without modifiers ignore held ones");
+        assert!(actions.just_activated("zoom_in"));
+
+        input.begin_frame();
+        actions.update(&input);
+        assert!(actions.is_active("save") && !actions.just_activated("save"));
+        assert!(actions.just_deactivated("zoom_in"));
+    }
+}
@@ -0,0 +1,273 @@
+use std::{error::Error, fs::{self, File}, io::{BufWriter, Write}, path::Path};
+
+use super::{input_state::InputStateEvent, keyboard::{Key, Modifiers}, mouse::{MouseButton, ScrollDelta}};
+
+/// First line of every recording, replays refuse files without it
+pub const INPUT_RECORDING_HEADER: &str = "# ash-base input recording v1";
+
+/// One frame of a recording, every frame gets one even without events so replays advance the clock the same way
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedFrame {
+    pub frame: u64,
+    /// `FrameClock::total` during the frame, what `Frame::time` was
+    pub time: f64,
+    /// `FrameClock::delta` of the frame, replays tick the clock with it
+    pub delta: f32,
+    pub events: Vec<InputStateEvent>,
+}
+
+/// Writes the events applied each frame as text, a `frame <number> <time> <delta>` line followed by one line per
+/// event. Floats are written with enough digits to read back exactly.
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = File::create(path).map_err(|error| format!("Failed to create input recording {}: {}", path.display(), error))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{}", INPUT_RECORDING_HEADER)?;
+        Ok(Self { writer })
+    }
+
+    pub fn record_frame(&mut self, frame: u64, time: f64, delta: f32, events: &[InputStateEvent]) {
+        let result = writeln!(self.writer, "frame {} {} {}", frame, time, delta)
+            .and_then(|_| events.iter().try_for_each(|event| writeln!(self.writer, "{}", format_event(event))));
+        if let Err(error) = result {
+            log::warn!("Failed to write input recording: {}", error);
+        }
+    }
+}
+
+impl Drop for InputRecorder {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+/// A recording read back in full, handed out one frame at a time
+pub struct InputReplay {
+    frames: Vec<RecordedFrame>,
+    next_frame: usize,
+}
+
+impl InputReplay {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path).map_err(|error| format!("Failed to read input recording {}: {}", path.display(), error))?;
+        let frames = parse_recording(&contents).map_err(|error| format!("{} in {}", error, path.display()))?;
+        Ok(Self { frames, next_frame: 0 })
+    }
+
+    /// `None` once every recorded frame was replayed
+    pub fn next_frame(&mut self) -> Option<&RecordedFrame> {
+        let frame = self.frames.get(self.next_frame)?;
+        self.next_frame += 1;
+        Some(frame)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+pub fn parse_recording(contents: &str) -> Result<Vec<RecordedFrame>, String> {
+    let mut lines = contents.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim() == INPUT_RECORDING_HEADER => {}
+        _ => return Err(format!("Missing {:?} header", INPUT_RECORDING_HEADER)),
+    }
+
+    let mut frames: Vec<RecordedFrame> = Vec::new();
+    for (line_index, line) in lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line_error = |error: String| format!("{} on line {}", error, line_index + 1);
+
+        if let Some(frame_line) = line.strip_prefix("frame ") {
+            let fields: Vec<&str> = frame_line.split_whitespace().collect();
+            let [frame, time, delta] = fields[..] else {
+                return Err(line_error(format!("Expected frame <number> <time> <delta>, got {:?}", line)));
+            };
+            frames.push(RecordedFrame {
+                frame: parse_field(frame).map_err(line_error)?,
+                time: parse_field(time).map_err(line_error)?,
+                delta: parse_field(delta).map_err(line_error)?,
+                events: Vec::new(),
+            });
+        } else {
+            let event = parse_event(line).map_err(line_error)?;
+            frames.last_mut().ok_or_else(|| line_error("Event before the first frame".to_string()))?.events.push(event);
+        }
+    }
+    Ok(frames)
+}
+
+fn parse_field<T: std::str::FromStr>(field: &str) -> Result<T, String> {
+    field.parse().map_err(|_| format!("Invalid number {:?}", field))
+}
+
+fn format_modifiers(modifiers: Modifiers) -> String {
+    let held: Vec<&str> = [
+        (modifiers.shift, "shift"),
+        (modifiers.control, "ctrl"),
+        (modifiers.alt, "alt"),
+        (modifiers.super_key, "super"),
+    ]
+    .into_iter()
+    .filter_map(|(held, name)| held.then_some(name))
+    .collect();
+    match held.is_empty() {
+        true => "none".to_string(),
+        false => held.join("+"),
+    }
+}
+
+fn parse_modifiers(text: &str) -> Result<Modifiers, String> {
+    let mut modifiers = Modifiers::default();
+    if text == "none" {
+        return Ok(modifiers);
+    }
+    for name in text.split('+') {
+        match name {
+            "shift" => modifiers.shift = true,
+            "ctrl" => modifiers.control = true,
+            "alt" => modifiers.alt = true,
+            "super" => modifiers.super_key = true,
+            _ => return Err(format!("Unknown modifier {:?}", name)),
+        }
+    }
+    Ok(modifiers)
+}
+
+pub fn format_event(event: &InputStateEvent) -> String {
+    match event {
+        InputStateEvent::CursorMoved((x, y)) => format!("CursorMoved {} {}", x, y),
+        InputStateEvent::CursorEntered => "CursorEntered".to_string(),
+        InputStateEvent::CursorLeft => "CursorLeft".to_string(),
+        InputStateEvent::RawMouseMotion((x, y)) => format!("RawMouseMotion {} {}", x, y),
+        InputStateEvent::Scrolled(ScrollDelta::Lines(x, y)) => format!("Scrolled Lines {} {}", x, y),
+        InputStateEvent::Scrolled(ScrollDelta::Pixels(x, y)) => format!("Scrolled Pixels {} {}", x, y),
+        InputStateEvent::MouseButtonPressed(button) => format!("MouseButtonPressed {}", button.name()),
+        InputStateEvent::MouseButtonReleased(button) => format!("MouseButtonReleased {}", button.name()),
+        InputStateEvent::KeyPressed(key) => format!("KeyPressed {}", key.name()),
+        InputStateEvent::KeyReleased(key) => format!("KeyReleased {}", key.name()),
+        InputStateEvent::ModifiersChanged(modifiers) => format!("ModifiersChanged {}", format_modifiers(*modifiers)),
+        InputStateEvent::FocusLost => "FocusLost".to_string(),
+        InputStateEvent::KeepAlive => "KeepAlive".to_string(),
+    }
+}
+
+/// The inverse of `format_event`
+pub fn parse_event(line: &str) -> Result<InputStateEvent, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let key = |name: &str| Key::parse(name).ok_or_else(|| format!("Unknown key {:?}", name));
+    let button = |name: &str| MouseButton::parse(name).ok_or_else(|| format!("Unknown mouse button {:?}", name));
+
+    let event = match fields[..] {
+        ["CursorMoved", x, y] => InputStateEvent::CursorMoved((parse_field(x)?, parse_field(y)?)),
+        ["CursorEntered"] => InputStateEvent::CursorEntered,
+        ["CursorLeft"] => InputStateEvent::CursorLeft,
+        ["RawMouseMotion", x, y] => InputStateEvent::RawMouseMotion((parse_field(x)?, parse_field(y)?)),
+        ["Scrolled", "Lines", x, y] => InputStateEvent::Scrolled(ScrollDelta::Lines(parse_field(x)?, parse_field(y)?)),
+        ["Scrolled", "Pixels", x, y] => InputStateEvent::Scrolled(ScrollDelta::Pixels(parse_field(x)?, parse_field(y)?)),
+        ["MouseButtonPressed", name] => InputStateEvent::MouseButtonPressed(button(name)?),
+        ["MouseButtonReleased", name] => InputStateEvent::MouseButtonReleased(button(name)?),
+        ["KeyPressed", name] => InputStateEvent::KeyPressed(key(name)?),
+        ["KeyReleased", name] => InputStateEvent::KeyReleased(key(name)?),
+        ["ModifiersChanged", modifiers] => InputStateEvent::ModifiersChanged(parse_modifiers(modifiers)?),
+        ["FocusLost"] => InputStateEvent::FocusLost,
+        ["KeepAlive"] => InputStateEvent::KeepAlive,
+        _ => return Err(format!("Unknown input event {:?}", line)),
+    };
+    Ok(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::test_directory::TestDirectory;
+
+    fn every_event() -> Vec<InputStateEvent> {
+        vec![
+            InputStateEvent::CursorMoved((412.25, -3.0)),
+            InputStateEvent::CursorEntered,
+            InputStateEvent::CursorLeft,
+            InputStateEvent::RawMouseMotion((0.1, 1e-9)),
+            InputStateEvent::Scrolled(ScrollDelta::Lines(0.0, -1.0)),
+            InputStateEvent::Scrolled(ScrollDelta::Pixels(12.5, 0.3)),
+            InputStateEvent::MouseButtonPressed(MouseButton::Left),
+            InputStateEvent::MouseButtonReleased(MouseButton::Other(9)),
+            InputStateEvent::KeyPressed(Key::Space),
+            InputStateEvent::KeyReleased(Key::NumpadEnter),
+            InputStateEvent::ModifiersChanged(Modifiers::default()),
+            InputStateEvent::ModifiersChanged(Modifiers { shift: true, control: true, alt: false, super_key: true }),
+            InputStateEvent::FocusLost,
+            InputStateEvent::KeepAlive,
+        ]
+    }
+
+    #[test]
+    fn events_round_trip_through_text() {
+        for event in every_event() {
+            let line = format_event(&event);
+            assert_eq!(parse_event(&line), Ok(event), "{}", line);
+        }
+        assert_eq!(format_event(&InputStateEvent::ModifiersChanged(Modifiers { shift: true, control: true, alt: false, super_key: false })), "ModifiersChanged shift+ctrl");
+    }
+
+    #[test]
+    fn invalid_events_are_rejected() {
+        assert!(parse_event("KeyPressed NotAKey").is_err());
+        assert!(parse_event("MouseButtonPressed Sideways").is_err());
+        assert!(parse_event("ModifiersChanged shift+hyper").is_err());
+        assert!(parse_event("ModifiersChanged").is_err());
+        assert!(parse_event("CursorMoved 1.0").is_err());
+        assert!(parse_event("CursorMoved x 1.0").is_err());
+        assert!(parse_event("Teleported").is_err());
+    }
+
+    #[test]
+    fn recordings_round_trip_through_a_file() {
+        let directory = TestDirectory::new("input-recording");
+        let path = directory.path().join("recording.txt");
+        let frames = [
+            RecordedFrame { frame: 0, time: 0.0, delta: 0.016_666_668, events: every_event() },
+            RecordedFrame { frame: 1, time: 0.1 + 0.2, delta: 1.0 / 3.0, events: Vec::new() },
+        ];
+        {
+            let mut recorder = InputRecorder::create(&path).unwrap();
+            for frame in frames.iter() {
+                recorder.record_frame(frame.frame, frame.time, frame.delta, &frame.events);
+            }
+        }
+
+        let mut replay = InputReplay::open(&path).unwrap();
+        assert_eq!(replay.frame_count(), 2);
+        assert_eq!(replay.next_frame(), Some(&frames[0]));
+        assert_eq!(replay.next_frame(), Some(&frames[1]));
+        assert_eq!(replay.next_frame(), None);
+    }
+
+    #[test]
+    fn malformed_recordings_are_rejected() {
+        assert!(parse_recording("").is_err());
+        assert!(parse_recording("frame 0 0 0.016\n").is_err());
+        assert!(parse_recording("# ash-base input recording v2\nframe 0 0 0.016\n").is_err());
+
+        let header = INPUT_RECORDING_HEADER;
+        let error = parse_recording(&format!("{}\nKeyPressed A\n", header)).unwrap_err();
+        assert!(error.contains("line 2"), "{}", error);
+        let error = parse_recording(&format!("{}\nframe 0 0\n", header)).unwrap_err();
+        assert!(error.contains("line 2"), "{}", error);
+        let error = parse_recording(&format!("{}\nframe 0 0 0.016\n\n# comment\nKeyPressed Hyper\n", header)).unwrap_err();
+        assert!(error.contains("line 5"), "{}", error);
+    }
+
+    #[test]
+    fn blank_lines_and_comments_are_skipped() {
+        let frames = parse_recording(&format!("{}\n\nframe 3 0.5 0.25\n# a note\nKeyPressed W\n", INPUT_RECORDING_HEADER)).unwrap();
+        assert_eq!(frames, [RecordedFrame { frame: 3, time: 0.5, delta: 0.25, events: vec![InputStateEvent::KeyPressed(Key::W)] }]);
+    }
+}
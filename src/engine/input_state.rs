@@ -1,11 +1,33 @@
 use std::sync::mpsc;
 
+use super::{keyboard::{Key, KeySet, Modifiers}, mouse::{MouseButton, MouseButtonSet, ScrollDelta}};
+
 #[derive(Default, Debug)]
 pub struct InputState {
     pub cursor_position: (f64, f64),
+    /// Cursor movement accumulated since `begin_frame`, in window pixels
     pub cursor_delta: (f64, f64),
-    pub mouse_buttons: [bool; 3],
-    pub keys: Vec<bool>,
+    /// Unaccelerated device motion since `begin_frame`, keeps coming when the cursor is captured or at the
+    /// window's edge, use it for mouse look
+    pub raw_mouse_delta: (f64, f64),
+    /// Scroll since `begin_frame`, wheels with notches count lines and touchpads count pixels
+    pub scroll_lines: (f32, f32),
+    pub scroll_pixels: (f64, f64),
+    /// Whether the cursor is over the window, `cursor_position` is stale while it's outside
+    pub cursor_inside: bool,
+    /// Buttons held right now
+    pub mouse_buttons: MouseButtonSet,
+    /// Buttons that went down or up since `begin_frame`
+    pub mouse_buttons_pressed: MouseButtonSet,
+    pub mouse_buttons_released: MouseButtonSet,
+    /// Keys held right now
+    pub keys: KeySet,
+    /// Keys that went down or up since `begin_frame`, a key tapped within one frame is in both
+    pub keys_pressed: KeySet,
+    pub keys_released: KeySet,
+    pub modifiers: Modifiers,
+    /// Set once a position arrived after entering the window, the first one after entering is not a movement
+    has_cursor_position: bool,
     pub sender: Option<mpsc::Sender<InputStateEvent>>,
     pub receiver: Option<mpsc::Receiver<InputStateEvent>>,
 }
@@ -16,8 +38,18 @@ impl InputState {
         Self {
             cursor_position: (0.0, 0.0),
             cursor_delta: (0.0, 0.0),
-            mouse_buttons: [false; 3],
-            keys: vec![false; 1024],
+            raw_mouse_delta: (0.0, 0.0),
+            scroll_lines: (0.0, 0.0),
+            scroll_pixels: (0.0, 0.0),
+            cursor_inside: false,
+            mouse_buttons: MouseButtonSet::default(),
+            mouse_buttons_pressed: MouseButtonSet::default(),
+            mouse_buttons_released: MouseButtonSet::default(),
+            keys: KeySet::default(),
+            keys_pressed: KeySet::default(),
+            keys_released: KeySet::default(),
+            modifiers: Modifiers::default(),
+            has_cursor_position: false,
             sender: Some(sender),
             receiver: Some(receiver),
         }
@@ -26,33 +58,116 @@ impl InputState {
     pub fn consume(&mut self, event: InputStateEvent) {
         match event {
             InputStateEvent::CursorMoved(position) => {
-                self.cursor_delta = (
-                    position.0 - self.cursor_position.0,
-                    position.1 - self.cursor_position.1,
-                );
+                if self.has_cursor_position {
+                    self.cursor_delta.0 += position.0 - self.cursor_position.0;
+                    self.cursor_delta.1 += position.1 - self.cursor_position.1;
+                }
                 self.cursor_position = position;
+                self.has_cursor_position = true;
+                // some platforms skip the enter event for a cursor that starts over the window
+                self.cursor_inside = true;
+            }
+            InputStateEvent::CursorEntered => {
+                self.cursor_inside = true;
+            }
+            InputStateEvent::CursorLeft => {
+                self.cursor_inside = false;
+                self.has_cursor_position = false;
+            }
+            InputStateEvent::RawMouseMotion(delta) => {
+                self.raw_mouse_delta.0 += delta.0;
+                self.raw_mouse_delta.1 += delta.1;
+            }
+            InputStateEvent::Scrolled(ScrollDelta::Lines(x, y)) => {
+                self.scroll_lines.0 += x;
+                self.scroll_lines.1 += y;
+            }
+            InputStateEvent::Scrolled(ScrollDelta::Pixels(x, y)) => {
+                self.scroll_pixels.0 += x;
+                self.scroll_pixels.1 += y;
             }
             InputStateEvent::MouseButtonPressed(button) => {
-                self.mouse_buttons[button as usize] = true;
+                if !self.mouse_buttons.contains(button) {
+                    self.mouse_buttons.insert(button);
+                    self.mouse_buttons_pressed.insert(button);
+                }
             }
             InputStateEvent::MouseButtonReleased(button) => {
-                self.mouse_buttons[button as usize] = false;
+                if self.mouse_buttons.contains(button) {
+                    self.mouse_buttons.remove(button);
+                    self.mouse_buttons_released.insert(button);
+                }
             }
             InputStateEvent::KeyPressed(key) => {
-                self.keys[key as usize] = true; // careful here, if this struct was created without keys being initialized large enough this will panic
+                // held keys repeat, only the first press is an edge
+                if !self.keys.contains(key) {
+                    self.keys.insert(key);
+                    self.keys_pressed.insert(key);
+                }
             }
             InputStateEvent::KeyReleased(key) => {
-                self.keys[key as usize] = false;
+                if self.keys.contains(key) {
+                    self.keys.remove(key);
+                    self.keys_released.insert(key);
+                }
+            }
+            InputStateEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers;
+            }
+            InputStateEvent::FocusLost => {
+                // the releases go to whichever window has focus now, without this keys would stay stuck down
+                for key in self.keys.iter().collect::<Vec<_>>() {
+                    self.consume(InputStateEvent::KeyReleased(key));
+                }
+                for button in self.mouse_buttons.iter().collect::<Vec<_>>() {
+                    self.consume(InputStateEvent::MouseButtonReleased(button));
+                }
+                self.modifiers = Modifiers::default();
             }
             InputStateEvent::KeepAlive => {}
         }
     }
 
-    pub fn consume_channel_events(&mut self) {
-        let gathered_events = self.receiver.as_ref().unwrap().try_iter().collect::<Vec<_>>();
-        for event in gathered_events.into_iter() {
-            self.consume(event);
-        }
+    /// Clears the per frame edges and deltas, the runner calls this before applying the frame's events
+    pub fn begin_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.mouse_buttons_pressed.clear();
+        self.mouse_buttons_released.clear();
+        self.cursor_delta = (0.0, 0.0);
+        self.raw_mouse_delta = (0.0, 0.0);
+        self.scroll_lines = (0.0, 0.0);
+        self.scroll_pixels = (0.0, 0.0);
+    }
+
+    pub fn is_down(&self, key: Key) -> bool {
+        self.keys.contains(key)
+    }
+
+    /// True for the whole frame the key went down in, including every `fixed_update` of that frame
+    pub fn just_pressed(&self, key: Key) -> bool {
+        self.keys_pressed.contains(key)
+    }
+
+    pub fn just_released(&self, key: Key) -> bool {
+        self.keys_released.contains(key)
+    }
+
+    pub fn is_mouse_down(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.contains(button)
+    }
+
+    pub fn mouse_just_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons_pressed.contains(button)
+    }
+
+    pub fn mouse_just_released(&self, button: MouseButton) -> bool {
+        self.mouse_buttons_released.contains(button)
+    }
+
+    /// Drains the channel without applying anything, for callers that want to see each event first
+    pub fn take_channel_events(&mut self) -> Vec<InputStateEvent> {
+        self.receiver.as_ref().unwrap().try_iter().collect()
     }
 
     pub fn sender_clone(&self) -> mpsc::Sender<InputStateEvent> {
@@ -63,9 +178,17 @@ impl InputState {
 #[derive(Default, Debug, Clone, PartialEq)]
 pub enum InputStateEvent {
     CursorMoved((f64, f64)),
-    MouseButtonPressed(u8),
-    MouseButtonReleased(u8),
-    KeyPressed(u8),
-    KeyReleased(u8),
+    CursorEntered,
+    CursorLeft,
+    /// Device motion from `DeviceEvent::MouseMotion`, not tied to the cursor
+    RawMouseMotion((f64, f64)),
+    Scrolled(ScrollDelta),
+    MouseButtonPressed(MouseButton),
+    MouseButtonReleased(MouseButton),
+    KeyPressed(Key),
+    KeyReleased(Key),
+    ModifiersChanged(Modifiers),
+    /// Releases everything held, the window stops receiving key and button events once it loses focus
+    FocusLost,
     #[default] KeepAlive,
 }
@@ -0,0 +1,121 @@
+use winit::keyboard::{KeyCode, ModifiersState};
+
+/// Declares `Key` with one variant per listed winit `KeyCode`, so the enum, the code mapping and the names used in
+/// bindings files can't drift apart
+macro_rules! keys {
+    ($($key:ident => $code:ident,)*) => {
+        /// Physical keys by position on a US layout, `Key::W` is the same key on AZERTY where it is labelled Z
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(u8)]
+        pub enum Key {
+            $($key,)*
+        }
+
+        impl Key {
+            pub const ALL: &'static [Self] = &[$(Self::$key,)*];
+
+            pub fn from_key_code(code: KeyCode) -> Option<Self> {
+                match code {
+                    $(KeyCode::$code => Some(Self::$key),)*
+                    _ => None,
+                }
+            }
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Self::$key => stringify!($key),)*
+                }
+            }
+        }
+    };
+}
+
+keys! {
+    A => KeyA, B => KeyB, C => KeyC, D => KeyD, E => KeyE, F => KeyF, G => KeyG, H => KeyH, I => KeyI,
+    J => KeyJ, K => KeyK, L => KeyL, M => KeyM, N => KeyN, O => KeyO, P => KeyP, Q => KeyQ, R => KeyR,
+    S => KeyS, T => KeyT, U => KeyU, V => KeyV, W => KeyW, X => KeyX, Y => KeyY, Z => KeyZ,
+    Digit0 => Digit0, Digit1 => Digit1, Digit2 => Digit2, Digit3 => Digit3, Digit4 => Digit4,
+    Digit5 => Digit5, Digit6 => Digit6, Digit7 => Digit7, Digit8 => Digit8, Digit9 => Digit9,
+    F1 => F1, F2 => F2, F3 => F3, F4 => F4, F5 => F5, F6 => F6,
+    F7 => F7, F8 => F8, F9 => F9, F10 => F10, F11 => F11, F12 => F12,
+    Up => ArrowUp, Down => ArrowDown, Left => ArrowLeft, Right => ArrowRight,
+    Space => Space, Enter => Enter, Escape => Escape, Tab => Tab, Backspace => Backspace,
+    Insert => Insert, Delete => Delete, Home => Home, End => End, PageUp => PageUp, PageDown => PageDown,
+    LeftShift => ShiftLeft, RightShift => ShiftRight, LeftControl => ControlLeft, RightControl => ControlRight,
+    LeftAlt => AltLeft, RightAlt => AltRight, LeftSuper => SuperLeft, RightSuper => SuperRight,
+    CapsLock => CapsLock, Minus => Minus, Equal => Equal, LeftBracket => BracketLeft, RightBracket => BracketRight,
+    Backslash => Backslash, Semicolon => Semicolon, Quote => Quote, Backquote => Backquote,
+    Comma => Comma, Period => Period, Slash => Slash,
+    Numpad0 => Numpad0, Numpad1 => Numpad1, Numpad2 => Numpad2, Numpad3 => Numpad3, Numpad4 => Numpad4,
+    Numpad5 => Numpad5, Numpad6 => Numpad6, Numpad7 => Numpad7, Numpad8 => Numpad8, Numpad9 => Numpad9,
+    NumpadAdd => NumpadAdd, NumpadSubtract => NumpadSubtract, NumpadMultiply => NumpadMultiply,
+    NumpadDivide => NumpadDivide, NumpadDecimal => NumpadDecimal, NumpadEnter => NumpadEnter,
+}
+
+// KeySet stores one bit per key
+const _: () = assert!(Key::ALL.len() <= 128);
+
+impl Key {
+    /// Case insensitive, the inverse of `Key::name`
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim();
+        Self::ALL.iter().copied().find(|key| key.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// A set of keys as a bitmask, cheap to copy and clear every frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeySet(u128);
+
+impl KeySet {
+    pub fn insert(&mut self, key: Key) {
+        self.0 |= 1 << key as u8;
+    }
+
+    pub fn remove(&mut self, key: Key) {
+        self.0 &= !(1 << key as u8);
+    }
+
+    pub fn contains(&self, key: Key) -> bool {
+        self.0 & (1 << key as u8) != 0
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Key> + '_ {
+        Key::ALL.iter().copied().filter(|key| self.contains(*key))
+    }
+}
+
+/// Modifier state as the window reports it, either side of the keyboard counts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub control: bool,
+    pub alt: bool,
+    /// Command on macOS, the Windows key elsewhere
+    pub super_key: bool,
+}
+
+impl Modifiers {
+    /// True when every modifier set in `required` is held here
+    pub fn contains(&self, required: Self) -> bool {
+        (!required.shift || self.shift)
+            && (!required.control || self.control)
+            && (!required.alt || self.alt)
+            && (!required.super_key || self.super_key)
+    }
+}
+
+impl From<ModifiersState> for Modifiers {
+    fn from(state: ModifiersState) -> Self {
+        Self {
+            shift: state.shift_key(),
+            control: state.control_key(),
+            alt: state.alt_key(),
+            super_key: state.super_key(),
+        }
+    }
+}
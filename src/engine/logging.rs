@@ -0,0 +1,39 @@
+use std::io::Write;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Level used when nothing else is asked for
+pub const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Info;
+
+/// Writes records to stderr as `LEVEL [target] message`, warnings and errors are what matter so they go unbuffered.
+/// Filters by `log::max_level` so the level can still change after it was installed.
+pub struct ConsoleLogger;
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let _ = writeln!(std::io::stderr().lock(), "{:<5} [{}] {}", record.level(), record.target(), record.args());
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// Accepts `off`, `error`, `warn`, `info`, `debug` and `trace`
+pub fn parse_level_filter(value: &str) -> Option<LevelFilter> {
+    value.trim().parse().ok()
+}
+
+/// Installs the console logger, does nothing if the app already installed a logger of its own
+pub fn init_logging(level: LevelFilter) {
+    if log::set_logger(&ConsoleLogger).is_ok() {
+        log::set_max_level(level);
+    }
+}
@@ -0,0 +1,121 @@
+use winit::event::{MouseButton as WinitMouseButton, MouseScrollDelta};
+
+/// Buttons past this index are dropped, mice rarely report more than a handful
+pub const MOUSE_BUTTON_COUNT: usize = 32;
+const NAMED_BUTTON_COUNT: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+    /// Buttons without a name, numbered as the platform reports them
+    Other(u16),
+}
+
+impl MouseButton {
+    /// Slot in a `MouseButtonSet`, `None` for buttons past `MOUSE_BUTTON_COUNT`
+    pub fn index(self) -> Option<usize> {
+        let index = match self {
+            Self::Left => 0,
+            Self::Right => 1,
+            Self::Middle => 2,
+            Self::Back => 3,
+            Self::Forward => 4,
+            Self::Other(button) => NAMED_BUTTON_COUNT + button as usize,
+        };
+        (index < MOUSE_BUTTON_COUNT).then_some(index)
+    }
+
+    pub fn from_index(index: usize) -> Self {
+        match index {
+            0 => Self::Left,
+            1 => Self::Right,
+            2 => Self::Middle,
+            3 => Self::Back,
+            4 => Self::Forward,
+            index => Self::Other((index - NAMED_BUTTON_COUNT) as u16),
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Self::Other(button) => format!("Button{}", button),
+            named => format!("{:?}", named),
+        }
+    }
+
+    /// Case insensitive, the inverse of `MouseButton::name`
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim().to_ascii_lowercase();
+        match name.as_str() {
+            "left" => Some(Self::Left),
+            "right" => Some(Self::Right),
+            "middle" => Some(Self::Middle),
+            "back" => Some(Self::Back),
+            "forward" => Some(Self::Forward),
+            other => other.strip_prefix("button").and_then(|button| button.parse().ok()).map(Self::Other),
+        }
+    }
+}
+
+impl From<WinitMouseButton> for MouseButton {
+    fn from(button: WinitMouseButton) -> Self {
+        match button {
+            WinitMouseButton::Left => Self::Left,
+            WinitMouseButton::Right => Self::Right,
+            WinitMouseButton::Middle => Self::Middle,
+            WinitMouseButton::Back => Self::Back,
+            WinitMouseButton::Forward => Self::Forward,
+            WinitMouseButton::Other(button) => Self::Other(button),
+        }
+    }
+}
+
+/// A set of mouse buttons as a bitmask, the mouse counterpart of `KeySet`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseButtonSet(u32);
+
+impl MouseButtonSet {
+    pub fn insert(&mut self, button: MouseButton) {
+        if let Some(index) = button.index() {
+            self.0 |= 1 << index;
+        }
+    }
+
+    pub fn remove(&mut self, button: MouseButton) {
+        if let Some(index) = button.index() {
+            self.0 &= !(1 << index);
+        }
+    }
+
+    pub fn contains(&self, button: MouseButton) -> bool {
+        button.index().is_some_and(|index| self.0 & (1 << index) != 0)
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = MouseButton> + '_ {
+        (0..MOUSE_BUTTON_COUNT).filter(|index| self.0 & (1 << index) != 0).map(MouseButton::from_index)
+    }
+}
+
+/// Wheels with notches report lines, touchpads report pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScrollDelta {
+    Lines(f32, f32),
+    Pixels(f64, f64),
+}
+
+impl From<MouseScrollDelta> for ScrollDelta {
+    fn from(delta: MouseScrollDelta) -> Self {
+        match delta {
+            MouseScrollDelta::LineDelta(x, y) => Self::Lines(x, y),
+            MouseScrollDelta::PixelDelta(position) => Self::Pixels(position.x, position.y),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{sync::Arc, thread};
+
+use ash::{vk, Device};
+
+use super::{device_context::DeviceContext, vulkan_commands::VulkanCommandPool};
+
+/// Worker count used when nothing else is asked for, recording is cheap enough that more threads rarely pay off
+pub const DEFAULT_RECORDING_THREADS: usize = 4;
+
+pub fn default_recording_thread_count() -> usize {
+    thread::available_parallelism()
+        .map(|threads| threads.get())
+        .unwrap_or(1)
+        .min(DEFAULT_RECORDING_THREADS)
+}
+
+/// Where secondaries are executed, they inherit the render pass and must stay inside this subpass
+#[derive(Debug, Clone, Copy)]
+pub struct SecondaryInheritance {
+    pub render_pass: vk::RenderPass,
+    pub subpass: u32,
+    pub framebuffer: vk::Framebuffer,
+}
+
+/// Command pools can only be used from one thread at a time, so every worker owns its own
+pub struct RecordingWorker {
+    pub command_pool: VulkanCommandPool,
+    pub command_buffer: vk::CommandBuffer,
+}
+
+/// Splits a frame's draw list into contiguous chunks recorded on worker threads into secondary command buffers,
+/// the primary then executes them in order so the draw order is unchanged
+pub struct ParallelRecorder {
+    pub device: Arc<DeviceContext>,
+    pub workers: Vec<RecordingWorker>,
+}
+
+impl ParallelRecorder {
+    pub fn new(device: Arc<DeviceContext>, queue_family_index: u32, thread_count: usize) -> Self {
+        let workers = (0..thread_count.max(1))
+            .map(|_| {
+                let command_pool = unsafe { VulkanCommandPool::new(device.clone(), queue_family_index) };
+                let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::default()
+                    .command_buffer_count(1)
+                    .command_pool(command_pool.command_pool)
+                    .level(vk::CommandBufferLevel::SECONDARY);
+                let command_buffer = unsafe { device
+                    .allocate_command_buffers(&command_buffer_allocate_info)
+                    .expect("Failed to allocate secondary command buffer")[0] };
+
+                RecordingWorker { command_pool, command_buffer }
+            })
+            .collect();
+
+        Self { device, workers }
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Records `jobs` across the workers and returns the secondaries to execute, in draw order. The previous
+    /// submission that executed them must have completed, their pools are reset here.
+    pub fn record_secondaries<Job: Sync, RecordFunction>(&self, inheritance: SecondaryInheritance, jobs: &[Job], record: RecordFunction) -> Vec<vk::CommandBuffer>
+    where
+        RecordFunction: Fn(&Device, vk::CommandBuffer, &[Job]) + Sync,
+    {
+        if jobs.is_empty() {
+            return Vec::new();
+        }
+
+        let chunk_size = jobs.len().div_ceil(self.workers.len());
+        let device: &Device = &self.device;
+        let record = &record;
+
+        thread::scope(|scope| {
+            let recordings: Vec<_> = jobs
+                .chunks(chunk_size)
+                .zip(self.workers.iter())
+                .map(|(chunk, worker)| {
+                    let command_pool = worker.command_pool.command_pool;
+                    let command_buffer = worker.command_buffer;
+                    scope.spawn(move || {
+                        unsafe { record_secondary(device, command_pool, command_buffer, inheritance, |device, command_buffer| record(device, command_buffer, chunk)) };
+                        command_buffer
+                    })
+                })
+                .collect();
+
+            recordings
+                .into_iter()
+                .map(|recording| recording.join().expect("Command recording thread panicked"))
+                .collect()
+        })
+    }
+}
+
+unsafe fn record_secondary<F: FnOnce(&Device, vk::CommandBuffer)>(device: &Device, command_pool: vk::CommandPool, command_buffer: vk::CommandBuffer, inheritance: SecondaryInheritance, func: F) {
+    device
+        .reset_command_pool(command_pool, vk::CommandPoolResetFlags::empty())
+        .expect("Reset command pool failed.");
+
+    let inheritance_info = vk::CommandBufferInheritanceInfo::default()
+        .render_pass(inheritance.render_pass)
+        .subpass(inheritance.subpass)
+        .framebuffer(inheritance.framebuffer);
+    let command_buffer_begin_info = vk::CommandBufferBeginInfo::default()
+        .flags(vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE | vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+        .inheritance_info(&inheritance_info);
+
+    device
+        .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+        .expect("Begin secondary commandbuffer");
+    func(device, command_buffer);
+    device
+        .end_command_buffer(command_buffer)
+        .expect("End secondary commandbuffer");
+}
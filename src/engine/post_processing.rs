@@ -0,0 +1,573 @@
+use std::{error::Error, ffi, ptr, slice, sync::Arc};
+
+use ash::{vk, Device};
+
+use super::{
+    device_context::DeviceContext,
+    gpu_timeline::GpuTicket,
+    presentation::DisplayEncoding,
+    shader_library::ShaderLibrary,
+    shader_manifest::{ShaderVariantKey, DEFAULT_SHADER_VARIANT},
+    vulkan_bindings::make_image_sampler_fragment_layout_binding,
+    vulkan_depth_image::VulkanDepthImage,
+    vulkan_descriptor::{make_image_sampler_pool_size, update_device_descriptor_sets, VulkanDescriptorPool, VulkanDescriptorSetLayouts},
+    vulkan_framebuffer::{make_color_depth_framebuffer_attachments, VulkanFramebuffers},
+    vulkan_offscreen_target::VulkanOffscreenTarget,
+    vulkan_pipeline::{VulkanPipeline, VulkanPipelineLayout},
+    vulkan_pipeline_cache::VulkanPipelineCache,
+    vulkan_render_pass::{VulkanColorDepthRenderPass, VulkanColorRenderPass},
+    vulkan_sampler::VulkanSampler,
+    vulkan_shaders::VulkanShader,
+    vulkan_texture::{VulkanTexture, VulkanTextureView},
+};
+
+/// Linear half float so blending stays in linear space and bright values survive until tone mapping
+pub const POST_PROCESS_COLOR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+pub const DEFAULT_PAPER_WHITE_NITS: f32 = 200.0;
+pub const COLOR_LUT_SIZE: u32 = 16;
+
+const FULLSCREEN_VERTEX_SHADER: &str = "post/fullscreen.vert";
+const POST_PROCESS_FRAGMENT_SHADER: &str = "post/post.frag";
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct PostProcessParameters {
+    pub resolution: [f32; 2],
+    pub time: f32,
+    pub intensity: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PostProcessEffect {
+    Grayscale,
+    Vignette,
+    ColorLut,
+    Blur,
+    Crt,
+}
+
+impl PostProcessEffect {
+    /// Every effect in the order the chain runs them
+    pub const ALL: [Self; 5] = [Self::ColorLut, Self::Grayscale, Self::Blur, Self::Vignette, Self::Crt];
+
+    pub fn shader_variant(&self) -> &'static str {
+        match self {
+            Self::Grayscale => "grayscale",
+            Self::Vignette => "vignette",
+            Self::ColorLut => "color_lut",
+            Self::Blur => "blur",
+            Self::Crt => "crt",
+        }
+    }
+
+    pub fn fragment_shader_key(&self) -> ShaderVariantKey {
+        ShaderVariantKey::new(POST_PROCESS_FRAGMENT_SHADER, self.shader_variant())
+    }
+}
+
+/// The final copy into the swapchain, encoding linear color the way the swapchain expects
+pub fn blit_fragment_shader_key(display_encoding: DisplayEncoding) -> ShaderVariantKey {
+    let variant = match display_encoding {
+        DisplayEncoding::Srgb => DEFAULT_SHADER_VARIANT,
+        DisplayEncoding::Unorm => "encode_srgb",
+        DisplayEncoding::Hdr10 => "hdr10",
+        DisplayEncoding::ExtendedSrgb => "extended_srgb",
+    };
+    ShaderVariantKey::new(POST_PROCESS_FRAGMENT_SHADER, variant)
+}
+
+/// RGBA8 pixels of a `size * size` by `size` strip lut that maps every color to itself
+pub fn make_identity_color_lut(size: u32) -> Vec<u8> {
+    let max = (size - 1) as f32;
+    let mut pixels = Vec::with_capacity((size * size * size * 4) as usize);
+    for green in 0..size {
+        for blue in 0..size {
+            for red in 0..size {
+                pixels.extend_from_slice(&[
+                    (red as f32 / max * 255.0).round() as u8,
+                    (green as f32 / max * 255.0).round() as u8,
+                    (blue as f32 / max * 255.0).round() as u8,
+                    255,
+                ]);
+            }
+        }
+    }
+    pixels
+}
+
+pub struct PostProcessPass {
+    pub effect: PostProcessEffect,
+    pub enabled: bool,
+    pub intensity: f32,
+    pub pipeline: VulkanPipeline,
+    _fragment_shader: VulkanShader,
+}
+
+/// Everything sized to the swapchain, rebuilt whenever the swapchain is. Each frame in flight has its own, and the
+/// descriptor sets live here too so a frame still in flight keeps sampling the old targets while new ones are written. The private
+/// fields back the framebuffers and descriptor sets.
+pub struct PostProcessTargets {
+    pub scene_framebuffer: VulkanFramebuffers,
+    pub effect_framebuffers: VulkanFramebuffers,
+    pub present_framebuffers: VulkanFramebuffers,
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    descriptor_pool: VulkanDescriptorPool,
+    multisampled_color_target: Option<VulkanOffscreenTarget>,
+    color_targets: [VulkanOffscreenTarget; 2],
+}
+
+impl PostProcessTargets {
+    fn mark_used(&self, ticket: GpuTicket) {
+        self.scene_framebuffer.last_use.mark(ticket);
+        self.effect_framebuffers.last_use.mark(ticket);
+        self.present_framebuffers.last_use.mark(ticket);
+        self.descriptor_pool.last_use.mark(ticket);
+        if let Some(multisampled_color_target) = self.multisampled_color_target.as_ref() {
+            multisampled_color_target.last_use.mark(ticket);
+        }
+        for color_target in self.color_targets.iter() {
+            color_target.last_use.mark(ticket);
+        }
+    }
+}
+
+/// Renders the scene offscreen, then ping-pongs between two color targets for each enabled effect
+/// before a final copy into the swapchain image
+pub struct PostProcessChain {
+    pub device: Arc<DeviceContext>,
+    pub device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pub extent: vk::Extent2D,
+    pub samples: vk::SampleCountFlags,
+    pub surface_format: vk::SurfaceFormatKHR,
+    /// Brightness of scene white on HDR displays, ignored for SDR swapchains
+    pub paper_white_nits: f32,
+    pub passes: Vec<PostProcessPass>,
+    pub blit_pipeline: VulkanPipeline,
+    pub blit_fragment_shader: VulkanShader,
+    pub vertex_shader: VulkanShader,
+    pub pipeline_layout: VulkanPipelineLayout,
+    pub descriptor_set_layouts: VulkanDescriptorSetLayouts,
+    pub frames_in_flight: usize,
+    /// One set per frame in flight, indexed by frame slot
+    pub targets: Vec<PostProcessTargets>,
+    pub sampler: VulkanSampler,
+    pub color_lut_view: VulkanTextureView,
+    /// Backs `color_lut_view`
+    color_lut: VulkanTexture,
+    pub scene_render_pass: VulkanColorDepthRenderPass,
+    pub effect_render_pass: VulkanColorRenderPass,
+    pub present_render_pass: VulkanColorRenderPass,
+}
+
+/// Everything `PostProcessChain::new` reads from the renderer to build the chain
+#[derive(Clone, Copy)]
+pub struct PostProcessChainInfo<'a> {
+    pub device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pub shader_library: &'a ShaderLibrary,
+    pub pipeline_cache: &'a VulkanPipelineCache,
+    pub surface_format: vk::SurfaceFormatKHR,
+    pub extent: vk::Extent2D,
+    pub samples: vk::SampleCountFlags,
+    pub depth_image: &'a VulkanDepthImage,
+    pub present_image_views: &'a [vk::ImageView],
+    pub frames_in_flight: usize,
+    pub enabled_effects: &'a [PostProcessEffect],
+}
+
+impl PostProcessChain {
+    pub fn new(device: Arc<DeviceContext>, color_lut: VulkanTexture, info: PostProcessChainInfo<'_>) -> Result<Self, Box<dyn Error>> {
+        let PostProcessChainInfo {
+            device_memory_properties,
+            shader_library,
+            pipeline_cache,
+            surface_format,
+            extent,
+            samples,
+            depth_image,
+            present_image_views,
+            frames_in_flight,
+            enabled_effects,
+        } = info;
+        let scene_render_pass = VulkanColorDepthRenderPass::new_offscreen(device.clone(), POST_PROCESS_COLOR_FORMAT, depth_image.format, samples);
+        let effect_render_pass = VulkanColorRenderPass::new(device.clone(), POST_PROCESS_COLOR_FORMAT, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        let present_render_pass = VulkanColorRenderPass::new(device.clone(), surface_format.format, vk::ImageLayout::PRESENT_SRC_KHR);
+
+        let sampler = VulkanSampler::new(device.clone());
+        let color_lut_view = VulkanTextureView::new(device.clone(), &color_lut);
+
+        let descriptor_set_layouts = VulkanDescriptorSetLayouts::new(device.clone(), vec![
+            make_image_sampler_fragment_layout_binding(1, 0),
+            make_image_sampler_fragment_layout_binding(1, 1),
+        ]);
+
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: size_of::<PostProcessParameters>() as u32,
+        }];
+        let pipeline_layout = VulkanPipelineLayout::new_with_push_constants(device.clone(), &descriptor_set_layouts, &push_constant_ranges);
+
+        let vertex_shader = shader_library.create_shader(device.clone(), &ShaderVariantKey::default_variant(FULLSCREEN_VERTEX_SHADER))?;
+        let blit_fragment_shader = shader_library.create_shader(device.clone(), &blit_fragment_shader_key(DisplayEncoding::from_surface_format(&surface_format)))?;
+        let blit_pipeline = create_fullscreen_pipeline(device.clone(), pipeline_cache, &pipeline_layout, present_render_pass.render_pass, &vertex_shader, &blit_fragment_shader);
+
+        let mut passes = Vec::with_capacity(PostProcessEffect::ALL.len());
+        for effect in PostProcessEffect::ALL {
+            let fragment_shader = shader_library.create_shader(device.clone(), &effect.fragment_shader_key())?;
+            let pipeline = create_fullscreen_pipeline(device.clone(), pipeline_cache, &pipeline_layout, effect_render_pass.render_pass, &vertex_shader, &fragment_shader);
+            passes.push(PostProcessPass {
+                effect,
+                enabled: enabled_effects.contains(&effect),
+                intensity: 1.0,
+                pipeline,
+                _fragment_shader: fragment_shader,
+            });
+        }
+
+        let mut chain = Self {
+            device,
+            device_memory_properties,
+            extent,
+            samples,
+            surface_format,
+            paper_white_nits: DEFAULT_PAPER_WHITE_NITS,
+            passes,
+            blit_pipeline,
+            blit_fragment_shader,
+            vertex_shader,
+            pipeline_layout,
+            descriptor_set_layouts,
+            frames_in_flight,
+            targets: Vec::new(),
+            sampler,
+            color_lut_view,
+            color_lut,
+            scene_render_pass,
+            effect_render_pass,
+            present_render_pass,
+        };
+        chain.targets = (0..frames_in_flight).map(|_| chain.create_targets(extent, depth_image, present_image_views)).collect();
+
+        Ok(chain)
+    }
+
+    /// Recreates the size dependent targets, dropping the old ones retires them until the frames using them finish
+    pub fn resize(&mut self, extent: vk::Extent2D, depth_image: &VulkanDepthImage, present_image_views: &[vk::ImageView]) {
+        self.targets.clear();
+        self.extent = extent;
+        self.targets = (0..self.frames_in_flight).map(|_| self.create_targets(extent, depth_image, present_image_views)).collect();
+    }
+
+    fn create_targets(&self, extent: vk::Extent2D, depth_image: &VulkanDepthImage, present_image_views: &[vk::ImageView]) -> PostProcessTargets {
+        let color_targets = [
+            VulkanOffscreenTarget::new(extent, POST_PROCESS_COLOR_FORMAT, self.device.clone(), self.device_memory_properties),
+            VulkanOffscreenTarget::new(extent, POST_PROCESS_COLOR_FORMAT, self.device.clone(), self.device_memory_properties),
+        ];
+
+        let multisampled_color_target = match self.samples {
+            vk::SampleCountFlags::TYPE_1 => None,
+            samples => Some(VulkanOffscreenTarget::new_multisampled(extent, POST_PROCESS_COLOR_FORMAT, samples, self.device.clone(), self.device_memory_properties)),
+        };
+
+        let scene_framebuffer = VulkanFramebuffers::new_from_attachments(
+            self.device.clone(),
+            extent,
+            self.scene_render_pass.render_pass,
+            vec![make_color_depth_framebuffer_attachments(
+                color_targets[0].image_view,
+                depth_image.depth_image_view,
+                multisampled_color_target.as_ref().map(|target| target.image_view),
+            )],
+        );
+        let effect_framebuffers = VulkanFramebuffers::new_from_attachments(
+            self.device.clone(),
+            extent,
+            self.effect_render_pass.render_pass,
+            color_targets.iter().map(|target| vec![target.image_view]).collect(),
+        );
+        let present_framebuffers = VulkanFramebuffers::new_from_attachments(
+            self.device.clone(),
+            extent,
+            self.present_render_pass.render_pass,
+            present_image_views.iter().map(|&view| vec![view]).collect(),
+        );
+
+        // one set samples each ping-pong target
+        let descriptor_pool = VulkanDescriptorPool::new(self.device.clone(), vec![make_image_sampler_pool_size(4)], 2);
+        let set_layout = self.descriptor_set_layouts.descriptor_set_layouts[0];
+        let descriptor_sets = descriptor_pool.allocate_descriptor_sets(&[set_layout, set_layout]);
+
+        let image_infos: Vec<[vk::DescriptorImageInfo; 2]> = color_targets
+            .iter()
+            .map(|target| [
+                vk::DescriptorImageInfo {
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    image_view: target.image_view,
+                    sampler: self.sampler.sampler,
+                },
+                self.color_lut_view.get_descriptor_info(&self.sampler),
+            ])
+            .collect();
+        let write_desc_sets = descriptor_sets
+            .iter()
+            .zip(image_infos.iter())
+            .map(|(&descriptor_set, image_info)| vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: 0,
+                descriptor_count: 2,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                p_image_info: image_info.as_ptr(),
+                ..Default::default()
+            })
+            .collect();
+        update_device_descriptor_sets(self.device.clone(), &write_desc_sets);
+
+        PostProcessTargets {
+            scene_framebuffer,
+            effect_framebuffers,
+            present_framebuffers,
+            descriptor_sets,
+            descriptor_pool,
+            multisampled_color_target,
+            color_targets,
+        }
+    }
+
+    /// Rebuilds the scene render pass and targets for a new sample count, the depth image must already match it
+    pub fn set_sample_count(&mut self, samples: vk::SampleCountFlags, depth_image: &VulkanDepthImage, present_image_views: &[vk::ImageView]) {
+        self.samples = samples;
+        self.scene_render_pass = VulkanColorDepthRenderPass::new_offscreen(self.device.clone(), POST_PROCESS_COLOR_FORMAT, depth_image.format, samples);
+        self.resize(self.extent, depth_image, present_image_views);
+    }
+
+    /// Rebuilds the final copy into the swapchain for a new swapchain format, the present views must already use it
+    pub fn set_surface_format(&mut self, surface_format: vk::SurfaceFormatKHR, shader_library: &ShaderLibrary, pipeline_cache: &VulkanPipelineCache, depth_image: &VulkanDepthImage, present_image_views: &[vk::ImageView]) -> Result<(), Box<dyn Error>> {
+        let blit_fragment_shader = shader_library.create_shader(self.device.clone(), &blit_fragment_shader_key(DisplayEncoding::from_surface_format(&surface_format)))?;
+        let present_render_pass = VulkanColorRenderPass::new(self.device.clone(), surface_format.format, vk::ImageLayout::PRESENT_SRC_KHR);
+        let blit_pipeline = create_fullscreen_pipeline(self.device.clone(), pipeline_cache, &self.pipeline_layout, present_render_pass.render_pass, &self.vertex_shader, &blit_fragment_shader);
+
+        self.surface_format = surface_format;
+        // pipeline first, it was built against the render pass
+        self.blit_pipeline = blit_pipeline;
+        self.present_render_pass = present_render_pass;
+        self.blit_fragment_shader = blit_fragment_shader;
+        self.resize(self.extent, depth_image, present_image_views);
+
+        Ok(())
+    }
+
+    pub fn display_encoding(&self) -> DisplayEncoding {
+        DisplayEncoding::from_surface_format(&self.surface_format)
+    }
+
+    pub fn set_enabled(&mut self, effect: PostProcessEffect, enabled: bool) {
+        if let Some(pass) = self.passes.iter_mut().find(|pass| pass.effect == effect) {
+            pass.enabled = enabled;
+        }
+    }
+
+    pub fn toggle(&mut self, effect: PostProcessEffect) {
+        let enabled = self.is_enabled(effect);
+        self.set_enabled(effect, !enabled);
+    }
+
+    pub fn is_enabled(&self, effect: PostProcessEffect) -> bool {
+        self.passes.iter().any(|pass| pass.effect == effect && pass.enabled)
+    }
+
+    pub fn set_intensity(&mut self, effect: PostProcessEffect, intensity: f32) {
+        if let Some(pass) = self.passes.iter_mut().find(|pass| pass.effect == effect) {
+            pass.intensity = intensity;
+        }
+    }
+
+    pub fn scene_framebuffer(&self, frame_slot: usize) -> vk::Framebuffer {
+        self.targets[frame_slot].scene_framebuffer.framebuffers[0]
+    }
+
+    /// Stamps everything `record` uses for `frame_slot` with the ticket of the submission it was recorded into
+    pub fn mark_used(&self, frame_slot: usize, ticket: GpuTicket) {
+        for pass in self.passes.iter() {
+            pass.pipeline.last_use.mark(ticket);
+        }
+        self.blit_pipeline.last_use.mark(ticket);
+        self.pipeline_layout.last_use.mark(ticket);
+        self.descriptor_set_layouts.last_use.mark(ticket);
+        self.sampler.last_use.mark(ticket);
+        self.color_lut_view.last_use.mark(ticket);
+        self.color_lut.last_use.mark(ticket);
+        self.scene_render_pass.last_use.mark(ticket);
+        self.effect_render_pass.last_use.mark(ticket);
+        self.present_render_pass.last_use.mark(ticket);
+        self.targets[frame_slot].mark_used(ticket);
+    }
+
+    /// Records every enabled effect and the final copy into the swapchain image, call after the scene render pass ended
+    pub fn record(&self, device: &Device, command_buffer: vk::CommandBuffer, frame_slot: usize, present_index: usize, time: f32) {
+        let targets = &self.targets[frame_slot];
+        let mut source_index = 0;
+
+        for pass in self.passes.iter().filter(|pass| pass.enabled) {
+            let destination_index = 1 - source_index;
+            self.record_fullscreen_pass(
+                device,
+                command_buffer,
+                self.effect_render_pass.render_pass,
+                targets.effect_framebuffers.framebuffers[destination_index],
+                pass.pipeline.pipeline,
+                targets.descriptor_sets[source_index],
+                PostProcessParameters {
+                    resolution: [self.extent.width as f32, self.extent.height as f32],
+                    time,
+                    intensity: pass.intensity,
+                },
+            );
+            source_index = destination_index;
+        }
+
+        self.record_fullscreen_pass(
+            device,
+            command_buffer,
+            self.present_render_pass.render_pass,
+            targets.present_framebuffers.framebuffers[present_index],
+            self.blit_pipeline.pipeline,
+            targets.descriptor_sets[source_index],
+            PostProcessParameters {
+                resolution: [self.extent.width as f32, self.extent.height as f32],
+                time,
+                intensity: self.paper_white_nits,
+            },
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn record_fullscreen_pass(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        render_pass: vk::RenderPass,
+        framebuffer: vk::Framebuffer,
+        pipeline: vk::Pipeline,
+        descriptor_set: vk::DescriptorSet,
+        parameters: PostProcessParameters,
+    ) {
+        let render_pass_begin_info = vk::RenderPassBeginInfo::default()
+            .render_pass(render_pass)
+            .framebuffer(framebuffer)
+            .render_area(self.extent.into());
+
+        let viewports = [vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: self.extent.width as f32,
+            height: self.extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+        let scissors = [self.extent.into()];
+
+        unsafe {
+            let parameter_bytes = slice::from_raw_parts(
+                ptr::from_ref(&parameters).cast::<u8>(),
+                size_of::<PostProcessParameters>(),
+            );
+
+            device.cmd_begin_render_pass(command_buffer, &render_pass_begin_info, vk::SubpassContents::INLINE);
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout.pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            device.cmd_set_viewport(command_buffer, 0, &viewports);
+            device.cmd_set_scissor(command_buffer, 0, &scissors);
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout.pipeline_layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                parameter_bytes,
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            device.cmd_end_render_pass(command_buffer);
+        }
+    }
+}
+
+fn create_fullscreen_pipeline(
+    device: Arc<DeviceContext>,
+    pipeline_cache: &VulkanPipelineCache,
+    pipeline_layout: &VulkanPipelineLayout,
+    render_pass: vk::RenderPass,
+    vertex_shader: &VulkanShader,
+    fragment_shader: &VulkanShader,
+) -> VulkanPipeline {
+    let shader_entry_name = ffi::CString::new("main").unwrap();
+    let shader_stage_create_infos = [
+        vk::PipelineShaderStageCreateInfo {
+            module: vertex_shader.shader_module,
+            p_name: shader_entry_name.as_ptr(),
+            stage: vk::ShaderStageFlags::VERTEX,
+            ..Default::default()
+        },
+        vk::PipelineShaderStageCreateInfo {
+            module: fragment_shader.shader_module,
+            p_name: shader_entry_name.as_ptr(),
+            stage: vk::ShaderStageFlags::FRAGMENT,
+            ..Default::default()
+        },
+    ];
+
+    let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo::default();
+    let vertex_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo {
+        topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+        ..Default::default()
+    };
+
+    let viewport_state_info = vk::PipelineViewportStateCreateInfo::default()
+        .scissor_count(1)
+        .viewport_count(1);
+
+    let rasterization_info = vk::PipelineRasterizationStateCreateInfo {
+        front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+        cull_mode: vk::CullModeFlags::NONE,
+        line_width: 1.0,
+        polygon_mode: vk::PolygonMode::FILL,
+        ..Default::default()
+    };
+
+    let multisample_state_info = vk::PipelineMultisampleStateCreateInfo::default()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let depth_state_info = vk::PipelineDepthStencilStateCreateInfo::default();
+
+    let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
+        blend_enable: 0,
+        color_write_mask: vk::ColorComponentFlags::RGBA,
+        ..Default::default()
+    }];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
+        .attachments(&color_blend_attachment_states);
+
+    let dynamic_state = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_info =
+        vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_state);
+
+    let graphic_pipeline_infos = vk::GraphicsPipelineCreateInfo::default()
+        .stages(&shader_stage_create_infos)
+        .vertex_input_state(&vertex_input_state_info)
+        .input_assembly_state(&vertex_input_assembly_state_info)
+        .viewport_state(&viewport_state_info)
+        .rasterization_state(&rasterization_info)
+        .multisample_state(&multisample_state_info)
+        .depth_stencil_state(&depth_state_info)
+        .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state_info)
+        .layout(pipeline_layout.pipeline_layout)
+        .render_pass(render_pass);
+
+    VulkanPipeline::new(device, pipeline_cache, graphic_pipeline_infos)
+}
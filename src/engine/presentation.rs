@@ -0,0 +1,165 @@
+use ash::vk;
+
+/// How presentation is paced against the display refresh
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VsyncMode {
+    /// Waits for vertical blank, never tears
+    On,
+    /// Waits for vertical blank unless a frame is late, then presents immediately
+    Adaptive,
+    /// Presents as fast as possible, preferring mailbox over tearing when available
+    Off,
+}
+
+impl VsyncMode {
+    /// In order of preference, FIFO is always supported so every list ends with it
+    pub fn present_mode_preference(&self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            Self::On => &[vk::PresentModeKHR::FIFO],
+            Self::Adaptive => &[vk::PresentModeKHR::FIFO_RELAXED, vk::PresentModeKHR::FIFO],
+            Self::Off => &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::FIFO],
+        }
+    }
+
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode.trim().to_lowercase().as_str() {
+            "on" | "1" | "true" | "fifo" => Some(Self::On),
+            "adaptive" | "relaxed" => Some(Self::Adaptive),
+            "off" | "0" | "false" | "mailbox" | "immediate" => Some(Self::Off),
+            _ => None,
+        }
+    }
+}
+
+/// sRGB formats come first so the presentation engine does the linear to gamma conversion for us
+pub fn default_preferred_surface_formats() -> Vec<vk::SurfaceFormatKHR> {
+    [
+        vk::Format::B8G8R8A8_SRGB,
+        vk::Format::R8G8B8A8_SRGB,
+        vk::Format::A8B8G8R8_SRGB_PACK32,
+        vk::Format::B8G8R8A8_UNORM,
+        vk::Format::R8G8B8A8_UNORM,
+    ]
+    .into_iter()
+    .map(|format| vk::SurfaceFormatKHR {
+        format,
+        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+    })
+    .collect()
+}
+
+/// HDR10 first, then scRGB, only usable when the instance enabled VK_EXT_swapchain_colorspace
+pub fn hdr_surface_formats() -> Vec<vk::SurfaceFormatKHR> {
+    vec![
+        vk::SurfaceFormatKHR {
+            format: vk::Format::A2B10G10R10_UNORM_PACK32,
+            color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+        },
+        vk::SurfaceFormatKHR {
+            format: vk::Format::R16G16B16A16_SFLOAT,
+            color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+        },
+    ]
+}
+
+/// How linear scene color has to be encoded for the swapchain, decides the final blit shader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayEncoding {
+    /// The swapchain format is `_SRGB` and the hardware encodes on write
+    Srgb,
+    /// A UNORM swapchain in the sRGB color space, the shader has to encode
+    Unorm,
+    /// Rec. 2020 primaries with the PQ transfer function
+    Hdr10,
+    /// Linear extended range sRGB where 1.0 is 80 nits
+    ExtendedSrgb,
+}
+
+impl DisplayEncoding {
+    pub fn from_surface_format(surface_format: &vk::SurfaceFormatKHR) -> Self {
+        match surface_format.color_space {
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => Self::Hdr10,
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => Self::ExtendedSrgb,
+            _ if is_srgb_format(surface_format.format) => Self::Srgb,
+            _ => Self::Unorm,
+        }
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, Self::Hdr10 | Self::ExtendedSrgb)
+    }
+}
+
+pub fn is_srgb_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::B8G8R8A8_SRGB | vk::Format::R8G8B8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32 | vk::Format::B8G8R8_SRGB | vk::Format::R8G8B8_SRGB
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresentationConfig {
+    pub vsync: VsyncMode,
+    pub preferred_surface_formats: Vec<vk::SurfaceFormatKHR>,
+    /// `None` asks for one more image than the surface minimum
+    pub image_count: Option<u32>,
+    /// Prefer an HDR swapchain when the surface offers one, needs the post processing chain for tone mapping
+    pub hdr: bool,
+}
+
+impl PresentationConfig {
+    pub fn surface_format_preference(&self, allow_hdr: bool) -> Vec<vk::SurfaceFormatKHR> {
+        match self.hdr && allow_hdr {
+            true => hdr_surface_formats().into_iter().chain(self.preferred_surface_formats.iter().cloned()).collect(),
+            false => self.preferred_surface_formats.clone(),
+        }
+    }
+}
+
+impl Default for PresentationConfig {
+    fn default() -> Self {
+        Self {
+            vsync: VsyncMode::Off,
+            preferred_surface_formats: default_preferred_surface_formats(),
+            image_count: None,
+            hdr: false,
+        }
+    }
+}
+
+pub fn select_present_mode(available_present_modes: &[vk::PresentModeKHR], vsync: VsyncMode) -> vk::PresentModeKHR {
+    vsync
+        .present_mode_preference()
+        .iter()
+        .cloned()
+        .find(|mode| available_present_modes.contains(mode))
+        .unwrap_or(vk::PresentModeKHR::FIFO)
+}
+
+/// First preferred format the surface supports, otherwise whatever the surface lists first
+pub fn select_surface_format(available_surface_formats: &[vk::SurfaceFormatKHR], preferred_surface_formats: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR {
+    // a single UNDEFINED entry means the surface has no preference at all
+    if let [only_format] = available_surface_formats {
+        if only_format.format == vk::Format::UNDEFINED {
+            return preferred_surface_formats.first().cloned().unwrap_or(*only_format);
+        }
+    }
+
+    preferred_surface_formats
+        .iter()
+        .find(|preferred| available_surface_formats.contains(preferred))
+        .or_else(|| available_surface_formats.first())
+        .cloned()
+        .expect("Surface reports no formats")
+}
+
+pub fn select_image_count(surface_capabilities: &vk::SurfaceCapabilitiesKHR, requested_image_count: Option<u32>) -> u32 {
+    let image_count = requested_image_count
+        .unwrap_or(surface_capabilities.min_image_count + 1)
+        .max(surface_capabilities.min_image_count);
+
+    match surface_capabilities.max_image_count {
+        0 => image_count,
+        max_image_count => image_count.min(max_image_count),
+    }
+}
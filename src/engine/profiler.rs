@@ -0,0 +1,459 @@
+use std::{
+    collections::VecDeque, fmt::Write as _, fs, io, path::Path, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex}, time::Instant
+};
+
+use ash::{vk, Device};
+
+use super::{device_context::DeviceContext, gpu_timeline::{GpuTicket, GpuTimeline}};
+
+/// Each GPU scope takes two timestamps, scopes past this are dropped for the frame
+pub const MAX_GPU_SCOPES_PER_FRAME: u32 = 256;
+/// Profiled frames kept for the app and for trace export
+pub const PROFILE_HISTORY: usize = 300;
+
+static NEXT_THREAD_INDEX: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static THREAD_INDEX: u64 = NEXT_THREAD_INDEX.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Which row a scope shows up on in a trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileTrack {
+    Cpu(u64),
+    Gpu,
+}
+
+/// A timed region, times are microseconds since the profiler was created
+#[derive(Debug, Clone)]
+pub struct ProfileScope {
+    pub name: &'static str,
+    pub track: ProfileTrack,
+    pub start_us: f64,
+    pub duration_us: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProfiledFrame {
+    pub frame_index: usize,
+    pub cpu_scopes: Vec<ProfileScope>,
+    pub gpu_scopes: Vec<ProfileScope>,
+}
+
+impl ProfiledFrame {
+    /// Total time of every CPU scope called `name` this frame
+    pub fn cpu_time_us(&self, name: &str) -> f64 {
+        self.cpu_scopes.iter().filter(|scope| scope.name == name).map(|scope| scope.duration_us).sum()
+    }
+
+    /// Total time of every GPU scope called `name` this frame
+    pub fn gpu_time_us(&self, name: &str) -> f64 {
+        self.gpu_scopes.iter().filter(|scope| scope.name == name).map(|scope| scope.duration_us).sum()
+    }
+
+    /// From the first GPU timestamp of the frame to the last
+    pub fn gpu_frame_time_us(&self) -> f64 {
+        let start = self.gpu_scopes.iter().map(|scope| scope.start_us).fold(f64::INFINITY, f64::min);
+        let end = self.gpu_scopes.iter().map(|scope| scope.start_us + scope.duration_us).fold(f64::NEG_INFINITY, f64::max);
+        if end > start { end - start } else { 0.0 }
+    }
+}
+
+/// Returned by `Profiler::begin_gpu_scope`, `None` when GPU profiling is unavailable or the frame ran out of queries
+#[derive(Debug, Clone, Copy)]
+pub struct GpuScope {
+    query: u32,
+}
+
+/// Ends its CPU scope when dropped
+pub struct CpuScope<'a> {
+    profiler: &'a Profiler,
+    name: &'static str,
+    start: Instant,
+}
+
+impl Drop for CpuScope<'_> {
+    fn drop(&mut self) {
+        self.profiler.record_cpu_scope(self.name, self.start, Instant::now());
+    }
+}
+
+struct RecordingFrame {
+    frame: ProfiledFrame,
+    slot: usize,
+    gpu_scope_names: Vec<&'static str>,
+}
+
+struct SubmittedFrame {
+    frame: ProfiledFrame,
+    ticket: GpuTicket,
+    submitted_us: f64,
+    gpu_scope_names: Vec<&'static str>,
+}
+
+struct ProfilerSlot {
+    query_pool: vk::QueryPool,
+    submitted: Option<SubmittedFrame>,
+}
+
+struct ProfilerState {
+    frame_count: usize,
+    /// Query pools kept in rotation, a pool is only reused once the frame that wrote it has been read back
+    frame_slots: usize,
+    recording: Option<RecordingFrame>,
+    slots: Vec<ProfilerSlot>,
+    history: VecDeque<ProfiledFrame>,
+}
+
+/// CPU scopes from any thread and GPU timestamp scopes from any command buffer of the frame's submission. GPU results
+/// are read back once the frame's ticket completes and placed on the CPU timeline relative to the submit, so the
+/// offset between the two tracks is approximate.
+pub struct Profiler {
+    pub device: Arc<DeviceContext>,
+    start: Instant,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f64,
+    timestamp_mask: u64,
+    state: Mutex<ProfilerState>,
+}
+
+impl Profiler {
+    /// `frames_in_flight` sizes the query pool rotation so recording a frame never waits on one still in flight
+    pub fn new(device: Arc<DeviceContext>, frames_in_flight: usize) -> Self {
+        let frame_slots = frames_in_flight.max(1) + 1;
+        let (timestamp_period, timestamp_valid_bits) = unsafe {
+            let properties = device.instance.get_physical_device_properties(device.physical_device);
+            let queue_families = device.instance.get_physical_device_queue_family_properties(device.physical_device);
+            (
+                properties.limits.timestamp_period as f64,
+                queue_families[device.queue_families.graphics as usize].timestamp_valid_bits,
+            )
+        };
+
+        let slots = match timestamp_valid_bits {
+            0 => {
+                log::warn!("The graphics queue doesn't support timestamps, GPU profiling is disabled");
+                Vec::new()
+            }
+            _ => (0..frame_slots)
+                .map(|_| {
+                    let query_pool_info = vk::QueryPoolCreateInfo::default()
+                        .query_type(vk::QueryType::TIMESTAMP)
+                        .query_count(MAX_GPU_SCOPES_PER_FRAME * 2);
+                    let query_pool = unsafe { device
+                        .create_query_pool(&query_pool_info, None)
+                        .expect("Failed to create timestamp query pool") };
+                    ProfilerSlot { query_pool, submitted: None }
+                })
+                .collect(),
+        };
+
+        Self {
+            device,
+            start: Instant::now(),
+            timestamp_period,
+            timestamp_mask: match timestamp_valid_bits {
+                64.. => u64::MAX,
+                bits => (1 << bits) - 1,
+            },
+            state: Mutex::new(ProfilerState {
+                frame_count: 0,
+                frame_slots,
+                recording: None,
+                slots,
+                history: VecDeque::with_capacity(PROFILE_HISTORY),
+            }),
+        }
+    }
+
+    pub fn gpu_enabled(&self) -> bool {
+        !self.state.lock().unwrap().slots.is_empty()
+    }
+
+    fn micros_since_start(&self, instant: Instant) -> f64 {
+        instant.duration_since(self.start).as_secs_f64() * 1_000_000.0
+    }
+
+    /// Starts collecting scopes for a new frame and reads back any GPU results that are ready
+    pub fn begin_frame(&self, frame_index: usize, timeline: &GpuTimeline) {
+        self.collect(timeline);
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(unfinished) = state.recording.take() {
+            Self::push_history(&mut state.history, unfinished.frame);
+        }
+
+        let slot = state.frame_count % state.frame_slots;
+        state.frame_count += 1;
+        // the slot's previous frame is normally long finished, but its queries can't be reset before being read
+        let pending_ticket = state.slots.get(slot).and_then(|slot| slot.submitted.as_ref()).map(|submitted| submitted.ticket);
+        if let Some(ticket) = pending_ticket {
+            drop(state);
+            timeline.wait(ticket);
+            self.collect(timeline);
+            state = self.state.lock().unwrap();
+        }
+
+        state.recording = Some(RecordingFrame {
+            frame: ProfiledFrame { frame_index, ..Default::default() },
+            slot,
+            gpu_scope_names: Vec::new(),
+        });
+    }
+
+    /// Hands the frame's GPU scopes to the submission that signals `ticket`, or drops them if nothing was submitted
+    pub fn end_frame(&self, ticket: Option<GpuTicket>) {
+        let submitted_us = self.micros_since_start(Instant::now());
+        let mut state = self.state.lock().unwrap();
+        let Some(recording) = state.recording.take() else {
+            return;
+        };
+
+        match (ticket, state.slots.get_mut(recording.slot)) {
+            (Some(ticket), Some(slot)) if !recording.gpu_scope_names.is_empty() => {
+                slot.submitted = Some(SubmittedFrame {
+                    frame: recording.frame,
+                    ticket,
+                    submitted_us,
+                    gpu_scope_names: recording.gpu_scope_names,
+                });
+            }
+            _ => Self::push_history(&mut state.history, recording.frame),
+        }
+    }
+
+    /// Times the rest of the enclosing block on the calling thread
+    pub fn cpu_scope(&self, name: &'static str) -> CpuScope<'_> {
+        CpuScope { profiler: self, name, start: Instant::now() }
+    }
+
+    fn record_cpu_scope(&self, name: &'static str, start: Instant, end: Instant) {
+        let scope = ProfileScope {
+            name,
+            track: ProfileTrack::Cpu(THREAD_INDEX.with(|index| *index)),
+            start_us: self.micros_since_start(start),
+            duration_us: end.duration_since(start).as_secs_f64() * 1_000_000.0,
+        };
+        if let Some(recording) = self.state.lock().unwrap().recording.as_mut() {
+            recording.frame.cpu_scopes.push(scope);
+        }
+    }
+
+    /// Resets this frame's queries, record it in the primary before anything that executes a GPU scope
+    pub fn reset_gpu_queries(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        let state = self.state.lock().unwrap();
+        if let Some(slot) = state.recording.as_ref().and_then(|recording| state.slots.get(recording.slot)) {
+            unsafe { device.cmd_reset_query_pool(command_buffer, slot.query_pool, 0, MAX_GPU_SCOPES_PER_FRAME * 2) };
+        }
+    }
+
+    /// Writes the start timestamp, every scope that was begun must be ended in the same submission
+    pub fn begin_gpu_scope(&self, device: &Device, command_buffer: vk::CommandBuffer, name: &'static str) -> Option<GpuScope> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let recording = state.recording.as_mut()?;
+        let query_pool = state.slots.get(recording.slot)?.query_pool;
+        if recording.gpu_scope_names.len() as u32 >= MAX_GPU_SCOPES_PER_FRAME {
+            return None;
+        }
+
+        let query = recording.gpu_scope_names.len() as u32 * 2;
+        recording.gpu_scope_names.push(name);
+        unsafe { device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::TOP_OF_PIPE, query_pool, query) };
+        Some(GpuScope { query })
+    }
+
+    pub fn end_gpu_scope(&self, device: &Device, command_buffer: vk::CommandBuffer, scope: Option<GpuScope>) {
+        let Some(scope) = scope else {
+            return;
+        };
+        let state = self.state.lock().unwrap();
+        if let Some(slot) = state.recording.as_ref().and_then(|recording| state.slots.get(recording.slot)) {
+            unsafe { device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::BOTTOM_OF_PIPE, slot.query_pool, scope.query + 1) };
+        }
+    }
+
+    /// Wraps whatever `record` puts into `command_buffer` in a GPU scope
+    pub fn gpu_scope<R>(&self, device: &Device, command_buffer: vk::CommandBuffer, name: &'static str, record: impl FnOnce() -> R) -> R {
+        let scope = self.begin_gpu_scope(device, command_buffer, name);
+        let result = record();
+        self.end_gpu_scope(device, command_buffer, scope);
+        result
+    }
+
+    /// Reads back every submitted frame whose ticket has completed
+    pub fn collect(&self, timeline: &GpuTimeline) {
+        let completed = timeline.completed();
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        for slot in state.slots.iter_mut() {
+            if slot.submitted.as_ref().is_none_or(|submitted| submitted.ticket > completed) {
+                continue;
+            }
+            let SubmittedFrame { mut frame, submitted_us, gpu_scope_names, .. } = slot.submitted.take().unwrap();
+
+            let mut timestamps = vec![0u64; gpu_scope_names.len() * 2];
+            let read_result = unsafe { self.device.get_query_pool_results(slot.query_pool, 0, &mut timestamps, vk::QueryResultFlags::TYPE_64) };
+            match read_result {
+                Ok(()) => {
+                    let first_timestamp = timestamps.iter().step_by(2).map(|timestamp| timestamp & self.timestamp_mask).min().unwrap_or(0);
+                    let to_micros = |ticks: u64| ticks as f64 * self.timestamp_period / 1000.0;
+                    frame.gpu_scopes = gpu_scope_names
+                        .iter()
+                        .zip(timestamps.chunks_exact(2))
+                        .map(|(name, pair)| {
+                            let begin = pair[0] & self.timestamp_mask;
+                            let end = pair[1] & self.timestamp_mask;
+                            ProfileScope {
+                                name,
+                                track: ProfileTrack::Gpu,
+                                start_us: submitted_us + to_micros(begin.wrapping_sub(first_timestamp) & self.timestamp_mask),
+                                duration_us: to_micros(end.wrapping_sub(begin) & self.timestamp_mask),
+                            }
+                        })
+                        .collect();
+                }
+                Err(error) => log::warn!("Failed to read GPU timestamps: {:?}", error),
+            }
+
+            Self::push_history(&mut state.history, frame);
+        }
+    }
+
+    fn push_history(history: &mut VecDeque<ProfiledFrame>, frame: ProfiledFrame) {
+        if history.len() == PROFILE_HISTORY {
+            history.pop_front();
+        }
+        history.push_back(frame);
+    }
+
+    /// The most recent frame with its GPU results read back, GPU scopes lag the CPU by a frame or two
+    pub fn latest_frame(&self) -> Option<ProfiledFrame> {
+        self.state.lock().unwrap().history.back().cloned()
+    }
+
+    pub fn history(&self) -> Vec<ProfiledFrame> {
+        self.state.lock().unwrap().history.iter().cloned().collect()
+    }
+
+    /// Everything in the history as Chrome `trace_event` JSON, open it in chrome://tracing or Perfetto
+    pub fn chrome_trace(&self) -> String {
+        format_chrome_trace(&self.history())
+    }
+
+    pub fn write_chrome_trace(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.chrome_trace())
+    }
+}
+
+fn format_chrome_trace(history: &[ProfiledFrame]) -> String {
+    let mut events: Vec<String> = vec![
+        r#"{"name":"thread_name","ph":"M","pid":1,"tid":0,"args":{"name":"GPU"}}"#.to_string(),
+    ];
+
+    for frame in history.iter() {
+        for scope in frame.cpu_scopes.iter().chain(frame.gpu_scopes.iter()) {
+            let tid = match scope.track {
+                ProfileTrack::Cpu(thread) => thread,
+                ProfileTrack::Gpu => 0,
+            };
+            let mut event = String::new();
+            write!(
+                event,
+                r#"{{"name":"{}","cat":"{}","ph":"X","pid":1,"tid":{},"ts":{:.3},"dur":{:.3},"args":{{"frame":{}}}}}"#,
+                escape_json(scope.name),
+                match scope.track { ProfileTrack::Cpu(_) => "cpu", ProfileTrack::Gpu => "gpu" },
+                tid,
+                scope.start_us,
+                scope.duration_us,
+                frame.frame_index,
+            ).unwrap();
+            events.push(event);
+        }
+    }
+
+    format!("{{\"traceEvents\":[\n{}\n]}}\n", events.join(",\n"))
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            character if (character as u32) < 0x20 => write!(escaped, "\\u{:04x}", character as u32).unwrap(),
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap();
+        for slot in state.slots.iter() {
+            unsafe { self.device.destroy_query_pool(slot.query_pool, None) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(name: &'static str, track: ProfileTrack, start_us: f64, duration_us: f64) -> ProfileScope {
+        ProfileScope { name, track, start_us, duration_us }
+    }
+
+    #[test]
+    fn escaped_names_survive_a_json_round_trip() {
+        for name in ["plain", "quote \" inside", "back\\slash", "new\nline\ttab", "bell\u{7}", "ünïcødé"] {
+            let json = format!("\"{}\"", escape_json(name));
+            assert_eq!(serde_json::from_str::<String>(&json).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn chrome_trace_is_valid_json_with_every_scope() {
+        let history = [
+            ProfiledFrame {
+                frame_index: 7,
+                cpu_scopes: vec![scope("update \"world\"", ProfileTrack::Cpu(3), 10.0, 2.5)],
+                gpu_scopes: vec![scope("scene", ProfileTrack::Gpu, 12.25, 100.0)],
+            },
+            ProfiledFrame {
+                frame_index: 8,
+                cpu_scopes: vec![scope("record", ProfileTrack::Cpu(4), 20.0, 1.0)],
+                gpu_scopes: Vec::new(),
+            },
+        ];
+
+        let trace: serde_json::Value = serde_json::from_str(&format_chrome_trace(&history)).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0]["ph"], "M");
+        assert_eq!(events[0]["args"]["name"], "GPU");
+
+        assert_eq!(events[1]["name"], "update \"world\"");
+        assert_eq!(events[1]["cat"], "cpu");
+        assert_eq!(events[1]["tid"], 3);
+        assert_eq!(events[1]["ts"], 10.0);
+        assert_eq!(events[1]["dur"], 2.5);
+        assert_eq!(events[1]["args"]["frame"], 7);
+
+        assert_eq!(events[2]["name"], "scene");
+        assert_eq!(events[2]["cat"], "gpu");
+        assert_eq!(events[2]["tid"], 0);
+        assert_eq!(events[2]["ts"], 12.25);
+
+        assert_eq!(events[3]["name"], "record");
+        assert_eq!(events[3]["args"]["frame"], 8);
+    }
+
+    #[test]
+    fn empty_history_still_names_the_gpu_track() {
+        let trace: serde_json::Value = serde_json::from_str(&format_chrome_trace(&[])).unwrap();
+        assert_eq!(trace["traceEvents"].as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,73 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use ash::Device;
+
+use super::gpu_timeline::GpuTicket;
+
+/// Destroys a dropped wrapper's raw handles
+pub type RetiredHandles = Box<dyn FnOnce(&Device) + Send>;
+
+/// Handles of dropped wrappers that a submission may still use, kept until the graphics timeline passes the last
+/// ticket that used them so replacing resources doesn't have to wait for the device to go idle. It lives on
+/// `DeviceContext` so every wrapper's `Drop` can reach it, see `DeviceContext::retire`.
+#[derive(Default)]
+pub struct RetirementQueue {
+    pending: Mutex<VecDeque<(GpuTicket, RetiredHandles)>>,
+}
+
+impl RetirementQueue {
+    pub fn retire_after<F: FnOnce(&Device) + Send + 'static>(&self, ticket: GpuTicket, destroy: F) {
+        let mut pending = self.pending.lock().unwrap();
+        // tickets are mostly retired in order, keep the queue sorted so collecting can stop at the first pending one
+        let position = pending.iter().rposition(|(pending_ticket, _)| *pending_ticket <= ticket).map_or(0, |index| index + 1);
+        pending.insert(position, (ticket, Box::new(destroy)));
+    }
+
+    /// Destroys everything whose ticket is at most `completed`, returns how many wrappers were destroyed
+    pub fn destroy_until(&self, device: &Device, completed: GpuTicket) -> usize {
+        let ready = self.take_until(completed);
+        let destroyed = ready.len();
+
+        // the lock is released, destroying can drop further wrappers that retire into this queue
+        for destroy in ready {
+            destroy(device);
+        }
+
+        destroyed
+    }
+
+    fn take_until(&self, completed: GpuTicket) -> Vec<RetiredHandles> {
+        let mut pending = self.pending.lock().unwrap();
+        let ready_count = pending.iter().take_while(|(ticket, _)| *ticket <= completed).count();
+        pending.drain(..ready_count).map(|(_, destroy)| destroy).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending_tickets(queue: &RetirementQueue) -> Vec<GpuTicket> {
+        queue.pending.lock().unwrap().iter().map(|(ticket, _)| *ticket).collect()
+    }
+
+    #[test]
+    fn out_of_order_retirements_stay_sorted() {
+        let queue = RetirementQueue::default();
+        for ticket in [3, 1, 2, 1] {
+            queue.retire_after(ticket, |_| ());
+        }
+        assert_eq!(pending_tickets(&queue), vec![1, 1, 2, 3]);
+    }
+
+    #[test]
+    fn only_completed_tickets_are_taken() {
+        let queue = RetirementQueue::default();
+        for ticket in [1, 2, 4] {
+            queue.retire_after(ticket, |_| ());
+        }
+        assert_eq!(queue.take_until(2).len(), 2);
+        assert_eq!(pending_tickets(&queue), vec![4]);
+        assert!(queue.take_until(3).is_empty());
+    }
+}
@@ -0,0 +1,74 @@
+use std::{error::Error, fs, io::Cursor, path::{Path, PathBuf}, sync::Arc};
+
+use super::{device_context::DeviceContext, shader_manifest::{ShaderManifest, ShaderVariantKey}, vulkan_shaders::VulkanShader};
+
+// EMBEDDED_MANIFEST and EMBEDDED_SHADERS, generated by build.rs from shader/permutations.manifest
+include!(concat!(env!("OUT_DIR"), "/embedded_shaders.rs"));
+
+/// Where compiled variants are read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShaderSource {
+    /// Compiled into the binary by build.rs
+    Embedded,
+    /// A shader directory laid out like shader/, for iterating on shaders without rebuilding
+    Directory(PathBuf),
+}
+
+/// Compiled shader variants described by shader/permutations.manifest, looked up by key at pipeline creation
+pub struct ShaderLibrary {
+    pub source: ShaderSource,
+    pub manifest: ShaderManifest,
+}
+
+impl ShaderLibrary {
+    pub fn embedded() -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            source: ShaderSource::Embedded,
+            manifest: ShaderManifest::parse(EMBEDDED_MANIFEST)?,
+        })
+    }
+
+    pub fn from_directory(shader_directory: &Path) -> Result<Self, Box<dyn Error>> {
+        let manifest = ShaderManifest::load(shader_directory)?;
+
+        Ok(Self {
+            source: ShaderSource::Directory(shader_directory.to_path_buf()),
+            manifest,
+        })
+    }
+
+    pub fn load_bytes(&self, key: &ShaderVariantKey) -> Result<Vec<u8>, Box<dyn Error>> {
+        let permutation = self
+            .manifest
+            .get(key)
+            .ok_or_else(|| format!("Shader variant {} is not declared in the shader manifest", key))?;
+
+        match &self.source {
+            ShaderSource::Embedded => {
+                let name = permutation.output_path().to_string_lossy().replace('\\', "/");
+                EMBEDDED_SHADERS
+                    .iter()
+                    .find(|(embedded_name, _)| *embedded_name == name)
+                    .map(|(_, bytes)| bytes.to_vec())
+                    .ok_or_else(|| format!("Shader variant {} was not compiled into this build, install glslc and rebuild", key).into())
+            }
+            ShaderSource::Directory(shader_directory) => {
+                let spv_path = shader_directory.join(permutation.output_path());
+                fs::read(&spv_path).map_err(|error| {
+                    format!(
+                        "Unable to read shader variant {} from {} ({}), run `cargo run --bin build_shaders`",
+                        key,
+                        spv_path.display(),
+                        error
+                    )
+                    .into()
+                })
+            }
+        }
+    }
+
+    pub fn create_shader(&self, device: Arc<DeviceContext>, key: &ShaderVariantKey) -> Result<VulkanShader, Box<dyn Error>> {
+        let bytes = self.load_bytes(key)?;
+        Ok(VulkanShader::new(device, Cursor::new(bytes.as_slice())))
+    }
+}
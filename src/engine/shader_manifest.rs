@@ -0,0 +1,156 @@
+use std::{error::Error, fmt, fs, path::{Path, PathBuf}};
+
+// Shared between the engine and scripts/build_shaders.rs, keep this file free of engine imports
+
+pub const SHADER_MANIFEST_FILE: &str = "permutations.manifest";
+pub const DEFAULT_SHADER_VARIANT: &str = "default";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShaderVariantKey {
+    pub source: String,
+    pub variant: String,
+}
+
+impl ShaderVariantKey {
+    pub fn new(source: &str, variant: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            variant: variant.to_string(),
+        }
+    }
+
+    pub fn default_variant(source: &str) -> Self {
+        Self::new(source, DEFAULT_SHADER_VARIANT)
+    }
+}
+
+impl fmt::Display for ShaderVariantKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.source, self.variant)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderPermutation {
+    pub key: ShaderVariantKey,
+    pub defines: Vec<String>,
+}
+
+impl ShaderPermutation {
+    /// The glslc stage name, taken from the source extension (vert, frag, ...)
+    pub fn stage(&self) -> &str {
+        Path::new(&self.key.source)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("")
+    }
+
+    /// Output path relative to the shader directory
+    pub fn output_path(&self) -> PathBuf {
+        let directory = Path::new(&self.key.source).parent().unwrap_or(Path::new(""));
+        let file_name = match self.key.variant.as_str() {
+            DEFAULT_SHADER_VARIANT => format!("{}.spv", self.stage()),
+            variant => format!("{}_{}.spv", self.stage(), variant),
+        };
+        directory.join(file_name)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ShaderManifest {
+    pub permutations: Vec<ShaderPermutation>,
+}
+
+impl ShaderManifest {
+    pub fn load(shader_directory: &Path) -> Result<Self, Box<dyn Error>> {
+        let manifest_path = shader_directory.join(SHADER_MANIFEST_FILE);
+        let text = fs::read_to_string(&manifest_path)
+            .map_err(|error| format!("Unable to read shader manifest {}: {}", manifest_path.display(), error))?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        let mut permutations: Vec<ShaderPermutation> = Vec::new();
+        let mut current_source: Option<String> = None;
+
+        for (line_index, raw_line) in text.lines().enumerate() {
+            let line = raw_line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                current_source = Some(line[1..line.len() - 1].trim().to_string());
+                continue;
+            }
+
+            let source = current_source
+                .as_ref()
+                .ok_or_else(|| format!("Shader manifest line {}: variant declared before any [source] section", line_index + 1))?;
+            let (variant, defines) = line
+                .split_once('=')
+                .ok_or_else(|| format!("Shader manifest line {}: expected `variant = DEFINES`", line_index + 1))?;
+
+            let permutation = ShaderPermutation {
+                key: ShaderVariantKey::new(source, variant.trim()),
+                defines: defines.split_whitespace().map(String::from).collect(),
+            };
+
+            if permutations.iter().any(|existing| existing.output_path() == permutation.output_path()) {
+                return Err(format!("Shader manifest line {}: {} collides with another variant output", line_index + 1, permutation.key).into());
+            }
+            permutations.push(permutation);
+        }
+
+        Ok(Self { permutations })
+    }
+
+    pub fn get(&self, key: &ShaderVariantKey) -> Option<&ShaderPermutation> {
+        self.permutations.iter().find(|permutation| &permutation.key == key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sections_and_defines() {
+        let manifest = ShaderManifest::parse(
+            "# comment\n[texture/texture.frag]\ndefault =\ntint = TINT ALPHA_CUTOFF=0.5 # trailing comment\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.permutations.len(), 2);
+
+        let tint = manifest.get(&ShaderVariantKey::new("texture/texture.frag", "tint")).unwrap();
+        assert_eq!(tint.defines, vec!["TINT".to_string(), "ALPHA_CUTOFF=0.5".to_string()]);
+        assert_eq!(tint.stage(), "frag");
+        assert_eq!(tint.output_path(), PathBuf::from("texture/frag_tint.spv"));
+
+        let default = manifest.get(&ShaderVariantKey::default_variant("texture/texture.frag")).unwrap();
+        assert!(default.defines.is_empty());
+        assert_eq!(default.output_path(), PathBuf::from("texture/frag.spv"));
+    }
+
+    #[test]
+    fn rejects_variants_outside_a_section() {
+        assert!(ShaderManifest::parse("default =\n").is_err());
+    }
+
+    #[test]
+    fn rejects_lines_without_an_equals_sign() {
+        assert!(ShaderManifest::parse("[a/a.frag]\ndefault\n").is_err());
+    }
+
+    #[test]
+    fn rejects_colliding_outputs() {
+        let error = ShaderManifest::parse("[a/first.frag]\ndefault =\n[a/second.frag]\ndefault =\n").unwrap_err();
+        assert!(error.to_string().contains("collides"));
+    }
+
+    #[test]
+    fn the_shipped_manifest_parses() {
+        let shader_directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("shader");
+        assert!(!ShaderManifest::load(&shader_directory).unwrap().permutations.is_empty());
+    }
+}
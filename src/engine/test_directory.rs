@@ -0,0 +1,42 @@
+use std::{fs, path::{Path, PathBuf}};
+
+/// A scratch directory under the system temp directory for tests, removed with everything in it on drop
+pub struct TestDirectory {
+    path: PathBuf,
+}
+
+impl TestDirectory {
+    /// `name` keeps tests running in parallel apart, the process id keeps concurrent test runs apart
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("ash-base-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub fn with_files(name: &str, files: &[(&str, &str)]) -> Self {
+        let directory = Self::new(name);
+        for (path, contents) in files {
+            directory.write(path, contents);
+        }
+        directory
+    }
+
+    /// Writes `contents` to `relative_path` inside the directory and returns the full path
+    pub fn write(&self, relative_path: &str, contents: &str) -> PathBuf {
+        let path = self.path.join(relative_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TestDirectory {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
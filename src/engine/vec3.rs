@@ -1,4 +1,5 @@
 #[derive(Clone, Debug, Copy)]
+#[repr(C)]
 pub struct Vector3 {
     pub x: f32,
     pub y: f32,
@@ -1,23 +1,56 @@
 use ash::vk;
 
-pub fn make_standard_depth_color_attachments(surface_format: vk::Format) -> [vk::AttachmentDescription; 2] {
-    [
+/// Color, depth and, when multisampled, a single sampled resolve attachment in that order.
+/// The multisampled color is only needed during the pass so it is never stored
+pub fn make_standard_depth_color_attachments(surface_format: vk::Format, depth_format: vk::Format, final_color_layout: vk::ImageLayout, samples: vk::SampleCountFlags) -> Vec<vk::AttachmentDescription> {
+    let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+
+    let mut attachments = vec![
         vk::AttachmentDescription {
             format: surface_format,
-            samples: vk::SampleCountFlags::TYPE_1,
+            samples,
             load_op: vk::AttachmentLoadOp::CLEAR,
-            store_op: vk::AttachmentStoreOp::STORE,
-            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            store_op: if multisampled { vk::AttachmentStoreOp::DONT_CARE } else { vk::AttachmentStoreOp::STORE },
+            final_layout: if multisampled { vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL } else { final_color_layout },
             ..Default::default()
         },
         vk::AttachmentDescription {
-            format: vk::Format::D16_UNORM,
-            samples: vk::SampleCountFlags::TYPE_1,
+            format: depth_format,
+            samples,
             load_op: vk::AttachmentLoadOp::CLEAR,
+            stencil_load_op: vk::AttachmentLoadOp::CLEAR,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
             initial_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
             final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
             ..Default::default()
         },
+    ];
+
+    if multisampled {
+        attachments.push(vk::AttachmentDescription {
+            format: surface_format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            store_op: vk::AttachmentStoreOp::STORE,
+            final_layout: final_color_layout,
+            ..Default::default()
+        });
+    }
+
+    attachments
+}
+
+/// A single color attachment that is fully overwritten by a full screen pass, so the old contents are never loaded
+pub fn make_fullscreen_color_attachments(color_format: vk::Format, final_color_layout: vk::ImageLayout) -> [vk::AttachmentDescription; 1] {
+    [
+        vk::AttachmentDescription {
+            format: color_format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            store_op: vk::AttachmentStoreOp::STORE,
+            final_layout: final_color_layout,
+            ..Default::default()
+        },
     ]
 }
 
@@ -45,3 +78,32 @@ pub fn make_color_subpass_dependency() -> vk::SubpassDependency {
         ..Default::default()
     }
 }
+
+/// Waits for earlier passes that sampled or wrote the attachment before this pass writes it
+pub fn make_sampled_color_input_subpass_dependency() -> vk::SubpassDependency {
+    vk::SubpassDependency {
+        src_subpass: vk::SUBPASS_EXTERNAL,
+        dst_subpass: 0,
+        src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::FRAGMENT_SHADER,
+        src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+        dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::FRAGMENT_SHADER,
+        dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::SHADER_READ,
+        ..Default::default()
+    }
+}
+
+/// Makes the written attachment visible to fragment shaders of the passes that sample it afterwards
+pub fn make_sampled_color_output_subpass_dependency() -> vk::SubpassDependency {
+    vk::SubpassDependency {
+        src_subpass: 0,
+        dst_subpass: vk::SUBPASS_EXTERNAL,
+        src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+        dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+        dst_access_mask: vk::AccessFlags::SHADER_READ,
+        ..Default::default()
+    }
+}
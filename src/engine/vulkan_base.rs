@@ -0,0 +1,820 @@
+use super::{clip_stack::{ClipPipelines, ClipStack, StencilClipPipelines}, commandbuffer::{submit_commandbuffer_to_ensure_depth_image_format, submit_commandbuffer_to_load_image, UploadContext}, debugging::VulkanDebugger, device_context::DeviceContext, engine_config::EngineConfig, gpu_timeline::{GpuTicket, GpuTimeline, LastUse, TimelineCommandBuffer}, image_manager::ImageManager, parallel_recording::{default_recording_thread_count, ParallelRecorder}, post_processing::{make_identity_color_lut, PostProcessChain, PostProcessChainInfo, PostProcessEffect, COLOR_LUT_SIZE}, presentation::{DisplayEncoding, PresentationConfig, VsyncMode}, shader_library::ShaderLibrary, shader_manifest::ShaderVariantKey, vec3::Vector3, vertex::Vertex, vulkan_bindings::{make_image_sampler_fragment_layout_binding, make_ubo_fragment_layout_binding}, vulkan_commands::{create_command_buffer, VulkanCommandPool}, vulkan_depth_image::{find_supported_depth_format, VulkanDepthImage, DEPTH_FORMAT_CANDIDATES}, vulkan_descriptor::{make_image_sampler_pool_size, make_ubo_pool_size, update_device_descriptor_sets, VulkanDescriptorPool, VulkanDescriptorSetLayouts}, vulkan_framebuffer::VulkanFramebuffers, vulkan_image::VulkanImage, vulkan_instance::make_vulkan_instance, vulkan_offscreen_target::VulkanOffscreenTarget, vulkan_logical_device::{make_logical_device, make_swapchain_device}, vulkan_physical_device::{clamp_sample_count, get_max_usable_sample_count, enumerate_physical_devices, select_physical_device}, vulkan_pipeline::{PipelineConfig, VulkanPipeline, VulkanPipelineLayout}, vulkan_pipeline_cache::{default_pipeline_cache_path, VulkanPipelineCache}, vulkan_render_pass::VulkanColorDepthRenderPass, vulkan_sampler::VulkanSampler, vulkan_semaphores::create_semaphores, vulkan_shaders::VulkanShader, vulkan_surface::VulkanSurface, vulkan_swapchain::{create_standard_swapchain, get_swapchain_image_views}, vulkan_texture::{TextureEncoding, VulkanTexture, VulkanTextureView}, vulkan_ubo::VulkanUniformBufferObject, winit_window::get_window_resolution};
+
+use std::{
+    cell::RefCell, default::Default, error::Error, ffi, ops::Drop, sync::{Arc, Mutex}
+};
+
+use ash::{
+    khr::swapchain,
+    vk, Entry, Instance,
+};
+use winit::window::Window;
+
+use std::mem;
+
+pub fn make_multisampled_color_image(samples: vk::SampleCountFlags, resolution: vk::Extent2D, format: vk::Format, device: Arc<DeviceContext>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Option<VulkanOffscreenTarget> {
+    match samples {
+        vk::SampleCountFlags::TYPE_1 => None,
+        samples => Some(VulkanOffscreenTarget::new_multisampled(resolution, format, samples, device, device_memory_properties)),
+    }
+}
+
+/// The private fields keep what the pipeline and its descriptor sets use alive, underscored ones are never read
+pub struct PipelineData {
+    texture: VulkanTexture,
+    sampler: VulkanSampler,
+    texture_view: VulkanTextureView,
+    /// One per frame in flight, bind the one at `Frame::frame_slot`
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    descriptor_pool: VulkanDescriptorPool,
+    uniform_buffers: Vec<VulkanUniformBufferObject>,
+    descriptor_set_layouts: VulkanDescriptorSetLayouts,
+    _vertex_shader: VulkanShader,
+    _fragment_shader: VulkanShader,
+    pub pipeline_layout: VulkanPipelineLayout,
+    pub viewports: [vk::Viewport; 1],
+    pub stencil_clip_pipelines: Option<StencilClipPipelines>,
+    pub graphics_pipelines: VulkanPipeline, // must be last for automatic drop to be last, https://github.com/rust-lang/rfcs/blob/246ff86b320a72f98ed2df92805e8e3d48b402d6/text/1857-stabilize-drop-order.md
+}
+
+impl PipelineData {
+    /// Stamps the pipelines and everything their descriptor sets reference with the ticket of a submission using them
+    pub fn mark_used(&self, ticket: GpuTicket) {
+        self.texture.last_use.mark(ticket);
+        self.sampler.last_use.mark(ticket);
+        self.texture_view.last_use.mark(ticket);
+        self.descriptor_pool.last_use.mark(ticket);
+        for uniform_buffer in self.uniform_buffers.iter() {
+            uniform_buffer.last_use.mark(ticket);
+        }
+        self.descriptor_set_layouts.last_use.mark(ticket);
+        self.pipeline_layout.last_use.mark(ticket);
+        if let Some(stencil_clip_pipelines) = self.stencil_clip_pipelines.as_ref() {
+            stencil_clip_pipelines.push_mask.last_use.mark(ticket);
+            stencil_clip_pipelines.pop_mask.last_use.mark(ticket);
+        }
+        self.graphics_pipelines.last_use.mark(ticket);
+    }
+}
+
+pub struct PipelineExtras {
+    vertex_shader_key: ShaderVariantKey,
+    fragment_shader_key: ShaderVariantKey,
+    raw_ubo_data: Vec<Vector3>,
+    pipeline_config: PipelineConfig,
+}
+
+pub struct VulkanBase {
+    pub instance: Instance,
+    pub device: Arc<DeviceContext>,
+    pub swapchain_device: swapchain::Device,
+    pub window: Arc<Mutex<Window>>,
+    pub depth_image: Option<VulkanDepthImage>,
+    pub msaa_color_image: Option<VulkanOffscreenTarget>,
+    pub debugger: Option<VulkanDebugger>,
+    pub command_pool: Option<VulkanCommandPool>,
+    pub transfer_command_pool: Option<VulkanCommandPool>,
+    /// Per thread pools for recording the scene into secondary command buffers, one set per frame in flight
+    pub parallel_recorders: Vec<ParallelRecorder>,
+    pub surface: Option<VulkanSurface>,
+    pub renderpass: Option<VulkanColorDepthRenderPass>,
+    pub framebuffers: Option<VulkanFramebuffers>,
+    pub pipeline_cache: Option<VulkanPipelineCache>,
+
+    pub pdevice: vk::PhysicalDevice,
+    pub device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+
+    pub surface_format: vk::SurfaceFormatKHR,
+    pub presentation_config: PresentationConfig,
+    pub surface_resolution: vk::Extent2D,
+    pub depth_format: vk::Format,
+
+    pub msaa_samples: vk::SampleCountFlags,
+    pub max_msaa_samples: vk::SampleCountFlags,
+
+    pub swapchain: vk::SwapchainKHR,
+    pub present_images: Vec<vk::Image>,
+    pub present_image_views: Vec<vk::ImageView>,
+
+    /// One per frame in flight, a frame waits for the previous use of its slot before recording
+    pub draw_command_buffers: Vec<TimelineCommandBuffer>,
+    pub setup_command_buffer: TimelineCommandBuffer,
+    pub transfer_command_buffer: TimelineCommandBuffer,
+
+    pub present_complete_semaphores: Vec<vk::Semaphore>,
+    pub rendering_complete_semaphores: Vec<vk::Semaphore>,
+
+    /// Signaled by every submission to the graphics queue
+    pub graphics_timeline: Option<Arc<GpuTimeline>>,
+    /// Signaled by every submission to the transfer queue
+    pub transfer_timeline: Option<Arc<GpuTimeline>>,
+
+    pub pipeline_data: Option<PipelineData>,
+    pub pipeline_extras: Option<PipelineExtras>,
+    pub post_processing: Option<PostProcessChain>,
+
+    pub current_swapchain_image: RefCell<usize>,
+    pub needs_swapchain_recreation: bool,
+    /// Stamped by every frame that presents, the swapchain and its views and semaphores are retired against it
+    pub swapchain_last_use: LastUse,
+    pub frame: RefCell<usize>,
+    pub image_manager: ImageManager,
+    pub shader_library: ShaderLibrary,
+}
+
+impl VulkanBase {
+    pub fn shared_device(&self) -> Arc<DeviceContext> {
+        self.device.clone()
+    }
+
+    pub fn get_next_swapchain_image_index(&self) -> usize {
+        let current_swapchain_image = *self.current_swapchain_image.borrow();
+        *self.current_swapchain_image.borrow_mut() = (current_swapchain_image + 1)
+            % self.present_images.len();
+        current_swapchain_image
+    }
+
+    pub fn increment_frame(&self) -> usize {
+        let frame = *self.frame.borrow();
+        *self.frame.borrow_mut() = frame + 1;
+        frame
+    }
+
+    pub fn new(window: Arc<Mutex<Window>>, config: &EngineConfig) -> Result<Self, Box<dyn Error>> {
+        unsafe {
+            let entry = Entry::linked();
+            let title = {
+                let locked_window = window.clone();
+                let locked_window = locked_window.lock().unwrap();
+                locked_window.title()
+            };
+
+            let shader_library = match config.shader_directory.as_ref() {
+                Some(shader_directory) => ShaderLibrary::from_directory(shader_directory)?,
+                None => ShaderLibrary::embedded()?,
+            };
+
+            let (instance, instance_features) = make_vulkan_instance(title.as_str(), &entry, window.clone(), config.validation.enabled)?;
+
+            let debugger = match config.validation.enabled && instance_features.debug_utils {
+                true => Some(VulkanDebugger::new(&entry, &instance, config.validation.messenger)),
+                false => None,
+            };
+            let surf = VulkanSurface::new(&entry, &instance, window.clone());
+            let available_physical_devices = enumerate_physical_devices(&instance, &surf.surface_loader, surf.surface);
+            let selected_device = select_physical_device(&available_physical_devices, config.device.as_ref())?;
+            log::info!("Using physical device {}", selected_device);
+            let pdevice = selected_device.physical_device;
+            let queue_families = selected_device.queue_families.unwrap();
+            let device = make_logical_device(&instance, pdevice, &queue_families, instance_features.debug_utils);
+            let presentation_config = config.presentation.clone();
+            // hdr needs the post processing chain to tone map, it is picked up once that is enabled
+            let surface_format = surf.get_format(&pdevice, &presentation_config.surface_format_preference(false));
+            if DisplayEncoding::from_surface_format(&surface_format) == DisplayEncoding::Unorm {
+                log::warn!("Surface has no sRGB format, colors are only gamma correct with post processing enabled");
+            }
+            let surface_resolution = surf.get_resolution(get_window_resolution(window.clone()), &pdevice);
+            let swapchain_device = make_swapchain_device(&device);
+            let swapchain = create_standard_swapchain(&pdevice, &surf, surface_format, surface_resolution, &queue_families, &presentation_config, vk::SwapchainKHR::null(), &swapchain_device);
+            let (present_images, present_image_views) = get_swapchain_image_views(device.clone(), &swapchain_device, swapchain, surface_format);
+
+            let command_pool = VulkanCommandPool::new(device.clone(), queue_families.graphics);
+            let setup_command_buffer = create_command_buffer(&command_pool, device.clone());
+            let draw_command_buffers: Vec<TimelineCommandBuffer> = (0..config.frames_in_flight)
+                .map(|_| TimelineCommandBuffer::new(create_command_buffer(&command_pool, device.clone())))
+                .collect();
+            let transfer_command_pool = VulkanCommandPool::new(device.clone(), queue_families.transfer);
+            let transfer_command_buffer = create_command_buffer(&transfer_command_pool, device.clone());
+            let recording_threads = config.recording_threads.unwrap_or_else(default_recording_thread_count);
+            let parallel_recorders = (0..config.frames_in_flight)
+                .map(|_| ParallelRecorder::new(device.clone(), queue_families.graphics, recording_threads))
+                .collect();
+            let device_memory_properties = device.memory_properties;
+            let physical_device_properties = instance.get_physical_device_properties(pdevice);
+            let pipeline_cache = VulkanPipelineCache::new(device.clone(), &physical_device_properties, &default_pipeline_cache_path(&physical_device_properties));
+            let max_msaa_samples = get_max_usable_sample_count(&instance, pdevice);
+            let msaa_samples = clamp_sample_count(config.msaa_samples, max_msaa_samples);
+            let depth_format = find_supported_depth_format(&instance, pdevice, &DEPTH_FORMAT_CANDIDATES);
+            let depth_img = VulkanDepthImage::new(surface_resolution, depth_format, msaa_samples, device.clone(), device_memory_properties);
+            let msaa_color_image = make_multisampled_color_image(msaa_samples, surface_resolution, surface_format.format, device.clone(), device_memory_properties);
+
+            let graphics_timeline = Arc::new(GpuTimeline::new(device.clone()));
+            let transfer_timeline = Arc::new(GpuTimeline::new(device.clone()));
+            let setup_command_buffer = TimelineCommandBuffer::new(setup_command_buffer);
+            let transfer_command_buffer = TimelineCommandBuffer::new(transfer_command_buffer);
+            device.set_object_name(setup_command_buffer.command_buffer, "setup command buffer");
+            for (slot, draw_command_buffer) in draw_command_buffers.iter().enumerate() {
+                device.set_object_name(draw_command_buffer.command_buffer, &format!("draw command buffer {}", slot));
+            }
+            device.set_object_name(transfer_command_buffer.command_buffer, "transfer command buffer");
+            device.set_object_name(graphics_timeline.semaphore, "graphics timeline");
+            device.set_object_name(transfer_timeline.semaphore, "transfer timeline");
+
+            submit_commandbuffer_to_ensure_depth_image_format(
+                device.clone(),
+                &setup_command_buffer,
+                &graphics_timeline,
+                device.graphics_queue(),
+                &depth_img,
+            );
+
+            let present_complete_semaphores = create_semaphores(device.clone(), present_images.len());
+            let rendering_complete_semaphores = create_semaphores(device.clone(), present_images.len());
+
+            let renderpass = VulkanColorDepthRenderPass::new(device.clone(), surface_format.format, depth_format, msaa_samples);
+
+            let framebuffers = VulkanFramebuffers::new(
+                device.clone(),
+                surface_resolution,
+                &renderpass,
+                &depth_img,
+                msaa_color_image.as_ref().map(|image| image.image_view),
+                &present_image_views,
+            );
+
+            Ok(Self {
+                instance,
+                device,
+                pdevice,
+                device_memory_properties,
+                window,
+                surface_format,
+                presentation_config,
+                surface_resolution,
+                depth_format,
+                msaa_samples,
+                max_msaa_samples,
+                swapchain_device,
+                swapchain,
+                present_images,
+                present_image_views,
+                draw_command_buffers,
+                setup_command_buffer,
+                present_complete_semaphores,
+                rendering_complete_semaphores,
+                transfer_command_buffer,
+                graphics_timeline: Some(graphics_timeline),
+                transfer_timeline: Some(transfer_timeline),
+                current_swapchain_image: RefCell::new(0),
+                needs_swapchain_recreation: false,
+                swapchain_last_use: LastUse::default(),
+                frame: RefCell::new(0),
+                depth_image: Some(depth_img),
+                msaa_color_image,
+                debugger: debugger,
+                command_pool: Some(command_pool),
+                transfer_command_pool: Some(transfer_command_pool),
+                parallel_recorders,
+                surface: Some(surf),
+                renderpass: Some(renderpass),
+                framebuffers: Some(framebuffers),
+                pipeline_cache: Some(pipeline_cache),
+                pipeline_data: None,
+                pipeline_extras: None,
+                post_processing: None,
+                image_manager: ImageManager::new(),
+                shader_library,
+            })
+        }
+    }
+
+    pub fn graphics_timeline(&self) -> &GpuTimeline {
+        self.graphics_timeline.as_ref().unwrap()
+    }
+
+    pub fn transfer_timeline(&self) -> &GpuTimeline {
+        self.transfer_timeline.as_ref().unwrap()
+    }
+
+    /// Destroys the retired handles whose last use has finished on the graphics queue
+    pub fn collect_retired(&self) -> usize {
+        self.device.destroy_retired(self.graphics_timeline().completed())
+    }
+
+    /// Stamps everything the runner records for `frame_slot` with the ticket of the frame's submission
+    pub fn mark_frame_used(&self, frame_slot: usize, ticket: GpuTicket) {
+        self.swapchain_last_use.mark(ticket);
+        if let Some(renderpass) = self.renderpass.as_ref() {
+            renderpass.last_use.mark(ticket);
+        }
+        if let Some(framebuffers) = self.framebuffers.as_ref() {
+            framebuffers.last_use.mark(ticket);
+        }
+        if let Some(depth_image) = self.depth_image.as_ref() {
+            depth_image.last_use.mark(ticket);
+        }
+        if let Some(msaa_color_image) = self.msaa_color_image.as_ref() {
+            msaa_color_image.last_use.mark(ticket);
+        }
+        if let Some(pipeline_data) = self.pipeline_data.as_ref() {
+            pipeline_data.mark_used(ticket);
+        }
+        if let Some(post_processing) = self.post_processing.as_ref() {
+            post_processing.mark_used(frame_slot, ticket);
+        }
+    }
+
+    pub fn frames_in_flight(&self) -> usize {
+        self.draw_command_buffers.len()
+    }
+
+    /// Which draw command buffer and recording pools `frame` uses
+    pub fn frame_slot(&self, frame: usize) -> usize {
+        frame % self.frames_in_flight()
+    }
+
+    pub fn parallel_recorder(&self, slot: usize) -> &ParallelRecorder {
+        &self.parallel_recorders[slot]
+    }
+
+    pub fn upload_context(&self) -> UploadContext<'_> {
+        UploadContext {
+            transfer_command_buffer: &self.transfer_command_buffer,
+            transfer_timeline: self.transfer_timeline(),
+            transfer_queue: self.device.transfer_queue(),
+            graphics_command_buffer: &self.setup_command_buffer,
+            graphics_timeline: self.graphics_timeline(),
+            graphics_queue: self.device.graphics_queue(),
+        }
+    }
+
+    /// A replaced image is retired until the last upload from it finishes
+    pub fn add_image(&mut self, name: &'static str, image: VulkanImage) {
+        self.image_manager.add_image(name, image);
+    }
+
+    /// The render pass scene pipelines are built against, offscreen while post processing is enabled
+    pub fn scene_render_pass(&self) -> vk::RenderPass {
+        match self.post_processing.as_ref() {
+            Some(post_processing) => post_processing.scene_render_pass.render_pass,
+            None => self.renderpass.as_ref().unwrap().render_pass,
+        }
+    }
+
+    pub fn clip_pipelines(&self) -> ClipPipelines {
+        let pipeline_data = self.pipeline_data.as_ref().unwrap();
+        ClipPipelines {
+            content: pipeline_data.graphics_pipelines.pipeline,
+            stencil: pipeline_data.stencil_clip_pipelines.as_ref().map(|pipelines| (pipelines.push_mask.pipeline, pipelines.pop_mask.pipeline)),
+        }
+    }
+
+    /// Stencil masks are only recorded when the current pipeline was built to test against them
+    pub fn clip_stack(&self) -> ClipStack {
+        ClipStack::new(self.surface_resolution, self.clip_pipelines().stencil.is_some())
+    }
+
+    pub fn scene_framebuffer(&self, frame_slot: usize, present_index: usize) -> vk::Framebuffer {
+        match self.post_processing.as_ref() {
+            Some(post_processing) => post_processing.scene_framebuffer(frame_slot),
+            None => self.framebuffers.as_ref().unwrap().framebuffers[present_index],
+        }
+    }
+
+    /// Leaves the current chain in place when the new one can't be built, e.g. a post effect variant missing from this build
+    ///
+    /// # Safety
+    ///
+    /// The swapchain and depth image must already exist, and the setup and transfer command buffers must not be
+    /// recording since the color lut upload is submitted on them
+    pub unsafe fn enable_post_processing(&mut self, enabled_effects: &[PostProcessEffect]) -> Result<(), Box<dyn Error>> {
+        let color_lut_image = VulkanImage::new_from_rgba(
+            &make_identity_color_lut(COLOR_LUT_SIZE),
+            COLOR_LUT_SIZE * COLOR_LUT_SIZE,
+            COLOR_LUT_SIZE,
+            self.shared_device(),
+            self.device_memory_properties,
+        );
+        let color_lut = VulkanTexture::new_from_image(&color_lut_image, TextureEncoding::Linear, self.device.clone(), self.device_memory_properties);
+        submit_commandbuffer_to_load_image(self.device.clone(), &self.upload_context(), &color_lut, &color_lut_image);
+
+        let post_processing = PostProcessChain::new(self.device.clone(), color_lut, PostProcessChainInfo {
+            device_memory_properties: self.device_memory_properties,
+            shader_library: &self.shader_library,
+            pipeline_cache: self.pipeline_cache.as_ref().unwrap(),
+            surface_format: self.surface_format,
+            extent: self.surface_resolution,
+            samples: self.msaa_samples,
+            depth_image: self.depth_image.as_ref().unwrap(),
+            present_image_views: &self.present_image_views,
+            frames_in_flight: self.frames_in_flight(),
+            enabled_effects,
+        })?;
+        self.add_image("color_lut", color_lut_image);
+        // the old chain is retired until the frames recorded with it finish
+        self.post_processing = Some(post_processing);
+        if self.presentation_config.hdr {
+            self.recreate_swapchain(self.surface_resolution);
+        }
+        self.recreate_pipeline();
+        Ok(())
+    }
+
+    pub fn disable_post_processing(&mut self) {
+        if self.post_processing.is_none() {
+            return;
+        }
+
+        self.post_processing = None;
+        // without tone mapping an hdr swapchain would show raw scene values
+        if DisplayEncoding::from_surface_format(&self.surface_format).is_hdr() {
+            self.recreate_swapchain(self.surface_resolution);
+        }
+        self.recreate_pipeline();
+    }
+
+    /// # Safety
+    ///
+    /// The swapchain, depth image and a `"sprite"` image must already exist
+    pub unsafe fn create_pipeline(&mut self, vertex_shader_key: ShaderVariantKey, fragment_shader_key: ShaderVariantKey, ubo: Vec<Vector3>, pipeline_config: PipelineConfig) {
+        self.pipeline_extras = Some(PipelineExtras {
+            vertex_shader_key: vertex_shader_key.clone(),
+            fragment_shader_key: fragment_shader_key.clone(),
+            raw_ubo_data: ubo.clone(),
+            pipeline_config,
+        });
+        let pipeline_config = pipeline_config.for_depth_format(self.depth_image.as_ref().unwrap().has_stencil());
+
+        // one copy per frame in flight, so a frame can rewrite its own while earlier frames still read theirs
+        let frames_in_flight = self.frames_in_flight();
+        let uniform_buffers: Vec<VulkanUniformBufferObject> = (0..frames_in_flight)
+            .map(|_| VulkanUniformBufferObject::new_from_vec3(ubo[0], self.shared_device(), self.device_memory_properties))
+            .collect();
+        let img = self.image_manager.get_image("sprite");
+        let images = vec![img];
+
+        let tex = VulkanTexture::new_from_image(&images[0], TextureEncoding::Srgb, self.device.clone(), self.device_memory_properties);
+        self.device.set_object_name(tex.texture_image, "sprite atlas");
+        self.device.set_object_name(images[0].image_buffer, "sprite atlas staging");
+        submit_commandbuffer_to_load_image(self.device.clone(), &self.upload_context(), &tex, &images[0]);
+
+        let samplr = VulkanSampler::new(self.device.clone());
+        let texview = VulkanTextureView::new(self.device.clone(), &tex);
+
+        let descriptor_sizes = vec![make_ubo_pool_size(frames_in_flight as u32), make_image_sampler_pool_size(frames_in_flight as u32)];
+        let desc_layout_bindings = vec![
+            make_ubo_fragment_layout_binding(1, 1),
+            make_image_sampler_fragment_layout_binding(1, 1),
+        ];
+
+        let descriptor_pool = VulkanDescriptorPool::new(self.device.clone(), descriptor_sizes, frames_in_flight as u32);
+
+        let descriptor_set_layouts = VulkanDescriptorSetLayouts::new(self.device.clone(), desc_layout_bindings);
+        let descriptor_sets = descriptor_pool.allocate_descriptor_sets(&vec![descriptor_set_layouts.descriptor_set_layouts[0]; frames_in_flight]);
+
+        let uniform_color_buffer_descriptors: Vec<vk::DescriptorBufferInfo> = uniform_buffers
+            .iter()
+            .map(|uniform_buffer| uniform_buffer.get_descriptor_info(0))
+            .collect();
+
+        let tex_descriptor = texview.get_descriptor_info(&samplr);
+
+        let write_desc_sets = descriptor_sets
+            .iter()
+            .zip(uniform_color_buffer_descriptors.iter())
+            .flat_map(|(&descriptor_set, uniform_color_buffer_descriptor)| [
+                vk::WriteDescriptorSet {
+                    dst_set: descriptor_set,
+                    descriptor_count: 1,
+                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                    p_buffer_info: uniform_color_buffer_descriptor,
+                    ..Default::default()
+                },
+                vk::WriteDescriptorSet {
+                    dst_set: descriptor_set,
+                    dst_binding: 1,
+                    descriptor_count: 1,
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    p_image_info: &tex_descriptor,
+                    ..Default::default()
+                },
+            ])
+            .collect();
+
+        update_device_descriptor_sets(self.device.clone(), &write_desc_sets);
+
+        let vertex_shader = self.shader_library
+            .create_shader(self.device.clone(), &vertex_shader_key)
+            .expect("Failed to load vertex shader variant");
+        let fragment_shader = self.shader_library
+            .create_shader(self.device.clone(), &fragment_shader_key)
+            .expect("Failed to load fragment shader variant");
+
+        let pipeline_layout = VulkanPipelineLayout::new(self.device.clone(), &descriptor_set_layouts);
+
+        let shader_entry_name = ffi::CString::new("main").unwrap();
+        let shader_entry_name = shader_entry_name.as_c_str();
+        let shader_stage_create_infos = [
+            vk::PipelineShaderStageCreateInfo {
+                module: vertex_shader.shader_module,
+                p_name: shader_entry_name.as_ptr(),
+                stage: vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            },
+            vk::PipelineShaderStageCreateInfo {
+                module: fragment_shader.shader_module,
+                p_name: shader_entry_name.as_ptr(),
+                stage: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+
+        let vertex_input_binding_descriptions = [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: mem::size_of::<Vertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }];
+
+        let vertex_input_attribute_descriptions = Vertex::get_attribute_descriptions();
+        let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_attribute_descriptions(&vertex_input_attribute_descriptions)
+            .vertex_binding_descriptions(&vertex_input_binding_descriptions);
+
+        let vertex_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo {
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            ..Default::default()
+        };
+
+        let viewports = [vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: self.surface_resolution.width as f32,
+            height: self.surface_resolution.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+        let scissors = [self.surface_resolution.into()];
+        let viewport_state_info = vk::PipelineViewportStateCreateInfo::default()
+            .scissors(&scissors)
+            .viewports(&viewports);
+
+        let rasterization_info = vk::PipelineRasterizationStateCreateInfo {
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            line_width: 1.0,
+            polygon_mode: vk::PolygonMode::FILL,
+            ..Default::default()
+        };
+
+        let multisample_state_info = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(self.msaa_samples);
+
+        let render_pass = self.scene_render_pass();
+        let build_pipeline = |pipeline_config: PipelineConfig| {
+            let depth_state_info = pipeline_config.depth_stencil_state();
+
+            let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
+                blend_enable: 1,
+                src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+                dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                color_blend_op: vk::BlendOp::ADD,
+                src_alpha_blend_factor: vk::BlendFactor::ONE,
+                dst_alpha_blend_factor: vk::BlendFactor::ONE,
+                alpha_blend_op: vk::BlendOp::ADD,
+                color_write_mask: pipeline_config.color_write_mask,
+            }];
+            let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
+                .logic_op(vk::LogicOp::CLEAR)
+                .attachments(&color_blend_attachment_states);
+
+            let dynamic_state = pipeline_config.dynamic_states();
+            let dynamic_state_info =
+                vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_state);
+
+            let graphic_pipeline_infos = vk::GraphicsPipelineCreateInfo::default()
+                .stages(&shader_stage_create_infos)
+                .vertex_input_state(&vertex_input_state_info)
+                .input_assembly_state(&vertex_input_assembly_state_info)
+                .viewport_state(&viewport_state_info)
+                .rasterization_state(&rasterization_info)
+                .multisample_state(&multisample_state_info)
+                .depth_stencil_state(&depth_state_info)
+                .color_blend_state(&color_blend_state)
+                .dynamic_state(&dynamic_state_info)
+                .layout(pipeline_layout.pipeline_layout)
+                .render_pass(render_pass);
+
+            VulkanPipeline::new(self.device.clone(), self.pipeline_cache.as_ref().unwrap(), graphic_pipeline_infos)
+        };
+
+        let graphics_pipelines = build_pipeline(pipeline_config);
+        // mask pipelines are only useful when the content pipeline tests the stencil against the clip depth
+        let stencil_clip_pipelines = match pipeline_config.has_stencil() && pipeline_config.dynamic_stencil_reference {
+            true => Some(StencilClipPipelines {
+                push_mask: build_pipeline(PipelineConfig::stencil_clip_push()),
+                pop_mask: build_pipeline(PipelineConfig::stencil_clip_pop()),
+            }),
+            false => None,
+        };
+
+        self.pipeline_data = Some(PipelineData {
+            graphics_pipelines,
+            stencil_clip_pipelines,
+            pipeline_layout,
+            _vertex_shader: vertex_shader,
+            _fragment_shader: fragment_shader,
+            descriptor_sets,
+            descriptor_pool,
+            uniform_buffers,
+            descriptor_set_layouts,
+            sampler: samplr,
+            texture: tex,
+            texture_view: texview,
+            viewports,
+        });
+    }
+
+    pub fn recreate_pipeline(&mut self, ) {
+        if self.pipeline_data.is_none() {
+            return;
+        }
+
+        self.pipeline_data = None;
+        unsafe {
+            self.create_pipeline(
+                self.pipeline_extras.as_ref().unwrap().vertex_shader_key.clone(),
+                self.pipeline_extras.as_ref().unwrap().fragment_shader_key.clone(),
+                self.pipeline_extras.as_ref().unwrap().raw_ubo_data.clone(),
+                self.pipeline_extras.as_ref().unwrap().pipeline_config,
+            )
+        };
+    }
+
+    /// Marks the swapchain stale, it is rebuilt by `ensure_swapchain` before the next frame
+    pub fn request_swapchain_recreation(&mut self) {
+        self.needs_swapchain_recreation = true;
+    }
+
+    /// Rebuilds a stale swapchain, returns false while the surface has no area so the frame should be skipped
+    pub fn ensure_swapchain(&mut self, window_resolution: vk::Extent2D) -> bool {
+        if !self.needs_swapchain_recreation {
+            return true;
+        }
+
+        let resolution = self.surface.as_ref().unwrap().get_resolution(window_resolution, &self.pdevice);
+        if resolution.width == 0 || resolution.height == 0 {
+            return false;
+        }
+
+        log::info!("Recreating swapchain with resolution {:?}", resolution);
+        self.recreate_swapchain(resolution);
+        self.recreate_pipeline();
+        true
+    }
+
+    pub fn recreate_swapchain(&mut self, resolution: vk::Extent2D) {
+        let old_swapchain = self.swapchain;
+        // dropping retires them, framebuffers before the attachments they reference
+        drop((self.framebuffers.take(), self.renderpass.take(), self.depth_image.take(), self.msaa_color_image.take()));
+
+        // presentation isn't tracked by the timeline, the last present was queued before the submission after the
+        // last frame that used the swapchain, so its semaphores and views are free once that one completes
+        let presented = mem::take(&mut self.swapchain_last_use).ticket() + 1;
+        let old_present_image_views = mem::take(&mut self.present_image_views);
+        // an acquire that was never waited on leaves its semaphore signaled, fresh ones can't carry that over
+        let old_semaphores: Vec<vk::Semaphore> = self.present_complete_semaphores.drain(..).chain(self.rendering_complete_semaphores.drain(..)).collect();
+        self.device.retirement.retire_after(presented, move |device| unsafe {
+            for image_view in old_present_image_views {
+                device.destroy_image_view(image_view, None);
+            }
+            for semaphore in old_semaphores {
+                device.destroy_semaphore(semaphore, None);
+            }
+        });
+
+        self.surface_resolution = resolution;
+        let surface_format_preference = self.presentation_config.surface_format_preference(self.post_processing.is_some());
+        self.surface_format = self.surface.as_ref().unwrap().get_format(&self.pdevice, &surface_format_preference);
+
+        let swapchain = create_standard_swapchain(&self.pdevice, &self.surface.as_ref().unwrap(), self.surface_format, self.surface_resolution, &self.device.queue_families, &self.presentation_config, old_swapchain, &self.swapchain_device);
+        let swapchain_device = self.swapchain_device.clone();
+        self.device.retirement.retire_after(presented, move |_| unsafe {
+            swapchain_device.destroy_swapchain(old_swapchain, None);
+        });
+        let (present_images, present_image_views) = get_swapchain_image_views(self.device.clone(), &self.swapchain_device, swapchain, self.surface_format);
+
+        self.swapchain = swapchain;
+        self.present_complete_semaphores = create_semaphores(self.device.clone(), present_images.len());
+        self.rendering_complete_semaphores = create_semaphores(self.device.clone(), present_images.len());
+        *self.current_swapchain_image.borrow_mut() = 0;
+        self.present_images = present_images;
+        self.present_image_views = present_image_views;
+        self.needs_swapchain_recreation = false;
+
+        self.renderpass = Some(VulkanColorDepthRenderPass::new(self.device.clone(), self.surface_format.format, self.depth_format, self.msaa_samples));
+        self.depth_image = Some(VulkanDepthImage::new(self.surface_resolution, self.depth_format, self.msaa_samples, self.device.clone(), self.device_memory_properties));
+        self.msaa_color_image = make_multisampled_color_image(self.msaa_samples, self.surface_resolution, self.surface_format.format, self.device.clone(), self.device_memory_properties);
+
+        submit_commandbuffer_to_ensure_depth_image_format(
+                self.device.clone(),
+                &self.setup_command_buffer,
+                self.graphics_timeline(),
+                self.device.graphics_queue(),
+                &self.depth_image.as_ref().unwrap(),
+            );
+
+        log::info!("Recreating framebuffers with size {:?}", self.surface_resolution);
+        self.framebuffers = Some(VulkanFramebuffers::new(
+            self.device.clone(),
+            self.surface_resolution,
+            &self.renderpass.as_ref().unwrap(),
+            &self.depth_image.as_ref().unwrap(),
+            self.msaa_color_image.as_ref().map(|image| image.image_view),
+            &self.present_image_views,
+        ));
+
+        if let Some(post_processing) = self.post_processing.as_mut() {
+            let surface_format_changed = post_processing.surface_format != self.surface_format;
+            if surface_format_changed {
+                post_processing
+                    .set_surface_format(self.surface_format, &self.shader_library, self.pipeline_cache.as_ref().unwrap(), self.depth_image.as_ref().unwrap(), &self.present_image_views)
+                    .expect("Failed to rebuild post processing for the new surface format");
+            }
+            if post_processing.samples != self.msaa_samples {
+                post_processing.set_sample_count(self.msaa_samples, self.depth_image.as_ref().unwrap(), &self.present_image_views);
+            } else if !surface_format_changed {
+                post_processing.resize(self.surface_resolution, self.depth_image.as_ref().unwrap(), &self.present_image_views);
+            }
+        }
+    }
+
+    /// Applies vsync, surface format and image count changes by recreating the swapchain
+    pub fn set_presentation_config(&mut self, presentation_config: PresentationConfig) {
+        if presentation_config == self.presentation_config {
+            return;
+        }
+
+        self.presentation_config = presentation_config;
+        self.recreate_swapchain(self.surface_resolution);
+        self.recreate_pipeline();
+    }
+
+    pub fn set_vsync(&mut self, vsync: VsyncMode) {
+        self.set_presentation_config(PresentationConfig {
+            vsync,
+            ..self.presentation_config.clone()
+        });
+    }
+
+    /// Requested counts are clamped to what the device supports for both color and depth
+    pub fn set_msaa_samples(&mut self, requested_samples: u32) {
+        let samples = clamp_sample_count(requested_samples, self.max_msaa_samples);
+        if samples == self.msaa_samples {
+            return;
+        }
+
+        self.msaa_samples = samples;
+        self.recreate_swapchain(self.surface_resolution);
+        self.recreate_pipeline();
+    }
+}
+
+impl Drop for VulkanBase {
+    fn drop(&mut self) {
+        self.depth_image = None;
+        self.msaa_color_image = None;
+        self.framebuffers = None;
+        self.renderpass = None;
+
+        unsafe {
+            {
+                self.device.wait_idle();
+                let device = &self.device;
+
+                for semaphore in self.present_complete_semaphores.iter() {
+                    device.destroy_semaphore(*semaphore, None);
+                }
+
+                for semaphore in self.rendering_complete_semaphores.iter() {
+                    device.destroy_semaphore(*semaphore, None);
+                }
+
+                for &image_view in self.present_image_views.iter() {
+                    device.destroy_image_view(image_view, None);
+                }
+                self.swapchain_device
+                    .destroy_swapchain(self.swapchain, None);
+            }
+            self.pipeline_data = None;
+            self.post_processing = None;
+            if let Some(pipeline_cache) = self.pipeline_cache.take() {
+                if let Err(error) = pipeline_cache.save() {
+                    log::warn!("Failed to save pipeline cache: {}", error);
+                }
+            }
+            self.command_pool = None;
+            self.transfer_command_pool = None;
+            self.parallel_recorders.clear();
+            self.graphics_timeline = None;
+            self.transfer_timeline = None;
+            self.surface = None;
+            self.image_manager.clear();
+            // the device is idle, everything retired on the way here can go
+            self.device.destroy_retired(GpuTicket::MAX);
+            {
+                self.device.destroy_device(None);
+                self.debugger = None;
+                self.instance.destroy_instance(None);
+            }
+        }
+    }
+}
@@ -1,35 +1,69 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+
+use ash::{Instance, vk::{self, Extent2D}};
+
+use super::{device_context::DeviceContext, gpu_timeline::LastUse, memory::find_memorytype_index};
+
+/// Best first, stencil capable formats are preferred so stencil masking is available whenever possible
+pub const DEPTH_FORMAT_CANDIDATES: [vk::Format; 4] = [
+    vk::Format::D24_UNORM_S8_UINT,
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D32_SFLOAT,
+    vk::Format::D16_UNORM,
+];
+
+pub fn find_supported_depth_format(instance: &Instance, physical_device: vk::PhysicalDevice, candidates: &[vk::Format]) -> vk::Format {
+    candidates
+        .iter()
+        .cloned()
+        .find(|&format| {
+            let properties = unsafe { instance.get_physical_device_format_properties(physical_device, format) };
+            properties
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+        })
+        .expect("No supported depth format found")
+}
 
-use ash::{Device, vk::{self, Extent2D}};
+pub fn format_has_stencil(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT | vk::Format::S8_UINT
+    )
+}
 
-use super::memory::find_memorytype_index;
+pub fn depth_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format_has_stencil(format) {
+        true => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+        false => vk::ImageAspectFlags::DEPTH,
+    }
+}
 
 pub struct VulkanDepthImage {
-    pub device: Arc<Mutex<Device>>,
+    pub device: Arc<DeviceContext>,
     pub depth_image: vk::Image,
     pub depth_image_view: vk::ImageView,
     pub depth_image_memory: vk::DeviceMemory,
+    pub format: vk::Format,
     pub dropped: bool,
+    pub last_use: LastUse,
 }
 
 impl VulkanDepthImage {
-    pub fn new(surface_resolution: Extent2D, device: Arc<Mutex<Device>>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
-        let locked_device = device.clone();
-        let locked_device = locked_device.lock().unwrap();
-
+    pub fn new(surface_resolution: Extent2D, format: vk::Format, samples: vk::SampleCountFlags, device: Arc<DeviceContext>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
         let depth_image_create_info = vk::ImageCreateInfo::default()
             .image_type(vk::ImageType::TYPE_2D)
-            .format(vk::Format::D16_UNORM)
+            .format(format)
             .extent(surface_resolution.into())
             .mip_levels(1)
             .array_layers(1)
-            .samples(vk::SampleCountFlags::TYPE_1)
+            .samples(samples)
             .tiling(vk::ImageTiling::OPTIMAL)
             .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
 
-        let depth_image = unsafe { locked_device.create_image(&depth_image_create_info, None).unwrap() };
-        let depth_image_memory_req = unsafe { locked_device.get_image_memory_requirements(depth_image) };
+        let depth_image = unsafe { device.create_image(&depth_image_create_info, None).unwrap() };
+        let depth_image_memory_req = unsafe { device.get_image_memory_requirements(depth_image) };
         let depth_image_memory_index = find_memorytype_index(
             &depth_image_memory_req,
             &device_memory_properties,
@@ -41,18 +75,18 @@ impl VulkanDepthImage {
             .allocation_size(depth_image_memory_req.size)
             .memory_type_index(depth_image_memory_index);
 
-        let depth_image_memory = unsafe { locked_device
+        let depth_image_memory = unsafe { device
             .allocate_memory(&depth_image_allocate_info, None)
             .unwrap() };
 
-        unsafe { locked_device
+        unsafe { device
             .bind_image_memory(depth_image, depth_image_memory, 0)
             .expect("Unable to bind depth image memory") };
 
         let depth_image_view_info = vk::ImageViewCreateInfo::default()
             .subresource_range(
                 vk::ImageSubresourceRange::default()
-                    .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                    .aspect_mask(depth_aspect_mask(format))
                     .level_count(1)
                     .layer_count(1),
             )
@@ -60,7 +94,7 @@ impl VulkanDepthImage {
             .format(depth_image_create_info.format)
             .view_type(vk::ImageViewType::TYPE_2D);
 
-        let depth_image_view = unsafe { locked_device
+        let depth_image_view = unsafe { device
             .create_image_view(&depth_image_view_info, None)
             .unwrap() };
 
@@ -69,24 +103,32 @@ impl VulkanDepthImage {
             depth_image,
             depth_image_memory,
             depth_image_view,
+            format,
             dropped: false,
+            last_use: LastUse::default(),
         }
     }
 
+    pub fn has_stencil(&self) -> bool {
+        format_has_stencil(self.format)
+    }
+
+    pub fn aspect_mask(&self) -> vk::ImageAspectFlags {
+        depth_aspect_mask(self.format)
+    }
+
     pub fn intentionally_free(&mut self) {
         if self.dropped {
             return;
         }
 
-        unsafe {
-            let locked_device = self.device.clone();
-            let locked_device = locked_device.lock().unwrap();
-
-            locked_device.free_memory(self.depth_image_memory, None);
-            locked_device.destroy_image_view(self.depth_image_view, None);
-            locked_device.destroy_image(self.depth_image, None);
-            self.dropped = true;
-        }
+        let (depth_image_memory, depth_image_view, depth_image) = (self.depth_image_memory, self.depth_image_view, self.depth_image);
+        self.device.retire(&self.last_use, move |device| unsafe {
+            device.free_memory(depth_image_memory, None);
+            device.destroy_image_view(depth_image_view, None);
+            device.destroy_image(depth_image, None);
+        });
+        self.dropped = true;
     }
 }
 
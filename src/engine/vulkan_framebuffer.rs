@@ -1,52 +1,73 @@
-use std::sync::{Arc, Mutex};
+use std::{mem, sync::Arc};
 use ash::vk;
 
-use super::{vulkan_depth_image::VulkanDepthImage, vulkan_render_pass::VulkanColorDepthRenderPass};
+use super::{device_context::DeviceContext, gpu_timeline::LastUse, vulkan_depth_image::VulkanDepthImage, vulkan_render_pass::VulkanColorDepthRenderPass};
+
+/// Attachment order expected by `VulkanColorDepthRenderPass`, the color view becomes the resolve target when multisampling
+pub fn make_color_depth_framebuffer_attachments(color_view: vk::ImageView, depth_view: vk::ImageView, multisampled_color_view: Option<vk::ImageView>) -> Vec<vk::ImageView> {
+    match multisampled_color_view {
+        Some(multisampled_color_view) => vec![multisampled_color_view, depth_view, color_view],
+        None => vec![color_view, depth_view],
+    }
+}
 
 pub struct VulkanFramebuffers {
-    pub device: Arc<Mutex<ash::Device>>,
+    pub device: Arc<DeviceContext>,
     pub framebuffers: Vec<vk::Framebuffer>,
+    pub last_use: LastUse,
 }
 
 impl VulkanFramebuffers {
     pub fn new(
-        device: Arc<Mutex<ash::Device>>,
+        device: Arc<DeviceContext>,
         surface_resolution: vk::Extent2D,
         renderpass: &VulkanColorDepthRenderPass,
         depth_img: &VulkanDepthImage,
+        multisampled_color_view: Option<vk::ImageView>,
         present_image_views: &Vec<vk::ImageView>,
     ) -> Self {
-        let framebuffers: Vec<vk::Framebuffer> = present_image_views
+        let framebuffer_attachments = present_image_views
             .iter()
-            .map(|&present_image_view| {
-                let locked_device = device.clone();
-                let locked_device = locked_device.lock().unwrap();
+            .map(|&present_image_view| make_color_depth_framebuffer_attachments(present_image_view, depth_img.depth_image_view, multisampled_color_view))
+            .collect();
 
-                let framebuffer_attachments = [present_image_view, depth_img.depth_image_view];
+        Self::new_from_attachments(device, surface_resolution, renderpass.render_pass, framebuffer_attachments)
+    }
+
+    /// Creates one framebuffer per entry of `framebuffer_attachments`, each entry lists the views in attachment order
+    pub fn new_from_attachments(
+        device: Arc<DeviceContext>,
+        resolution: vk::Extent2D,
+        render_pass: vk::RenderPass,
+        framebuffer_attachments: Vec<Vec<vk::ImageView>>,
+    ) -> Self {
+        let framebuffers: Vec<vk::Framebuffer> = framebuffer_attachments
+            .iter()
+            .map(|attachments| {
                 let frame_buffer_create_info = vk::FramebufferCreateInfo::default()
-                    .render_pass(renderpass.render_pass)
-                    .attachments(&framebuffer_attachments)
-                    .width(surface_resolution.width)
-                    .height(surface_resolution.height)
+                    .render_pass(render_pass)
+                    .attachments(attachments)
+                    .width(resolution.width)
+                    .height(resolution.height)
                     .layers(1);
 
-                unsafe { locked_device
+                unsafe { device
                     .create_framebuffer(&frame_buffer_create_info, None)
                     .unwrap() }
             })
             .collect();
 
-        Self { framebuffers, device }
+        Self { framebuffers, device, last_use: LastUse::default() }
     }
 }
 
 impl Drop for VulkanFramebuffers {
     fn drop(&mut self) {
-        let locked_device = self.device.lock().unwrap();
-        for framebuffer in self.framebuffers.iter() {
-            unsafe {
-                locked_device.destroy_framebuffer(*framebuffer, None);
+        let framebuffers = mem::take(&mut self.framebuffers);
+        self.device.retire(&self.last_use, move |device| unsafe {
+            for framebuffer in framebuffers {
+                device.destroy_framebuffer(framebuffer, None);
             }
-        }
+        });
     }
 }
@@ -1,26 +1,30 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::mem;
 
 use ash::util::Align;
-use ash::{vk, Device};
-
-use super::{dimensions::Dimensions, memory::find_memorytype_index};
+use ash::vk;
 
+use super::{device_context::DeviceContext, dimensions::Dimensions, gpu_timeline::LastUse, memory::find_memorytype_index};
 
 pub struct VulkanImage {
   pub dimensions: Dimensions,
-  pub device: Arc<Mutex<Device>>,
+  pub device: Arc<DeviceContext>,
   pub image_buffer: vk::Buffer,
   pub image_buffer_memory: vk::DeviceMemory,
+  pub last_use: LastUse,
 }
 
 impl VulkanImage {
-  pub unsafe fn new_from_bytes(bytes: &'static [u8], device: Arc<Mutex<Device>>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
+  pub unsafe fn new_from_bytes(bytes: &'static [u8], device: Arc<DeviceContext>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
     let loaded_image = image::load_from_memory(bytes).unwrap().to_rgba8();
     let (width, height) = loaded_image.dimensions();
-    let image_dimensions = Dimensions::new(width, height, 0);
 
-    let image_data = loaded_image.into_raw();
+    Self::new_from_rgba(&loaded_image.into_raw(), width, height, device, device_memory_properties)
+  }
+
+  /// Stages tightly packed RGBA8 pixels, used for images generated at runtime rather than decoded from a file
+  pub unsafe fn new_from_rgba(image_data: &[u8], width: u32, height: u32, device: Arc<DeviceContext>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
+    let image_dimensions = Dimensions::new(width, height, 0);
 
     let image_buffer_info = vk::BufferCreateInfo {
         size: (mem::size_of::<u8>() * image_data.len()) as u64,
@@ -29,12 +33,8 @@ impl VulkanImage {
         ..Default::default()
     };
 
-
-    let locked_device = device.clone();
-    let locked_device = locked_device.lock().unwrap();
-
-    let image_buffer = locked_device.create_buffer(&image_buffer_info, None).unwrap();
-    let image_buffer_memory_req = locked_device.get_buffer_memory_requirements(image_buffer);
+    let image_buffer = device.create_buffer(&image_buffer_info, None).unwrap();
+    let image_buffer_memory_req = device.get_buffer_memory_requirements(image_buffer);
 
     let image_buffer_memory_index = find_memorytype_index(
         &image_buffer_memory_req,
@@ -49,10 +49,10 @@ impl VulkanImage {
         ..Default::default()
     };
 
-    let image_buffer_memory = locked_device
+    let image_buffer_memory = device
         .allocate_memory(&image_buffer_allocate_info, None)
         .unwrap();
-    let image_ptr = locked_device
+    let image_ptr = device
         .map_memory(
             image_buffer_memory,
             0,
@@ -65,9 +65,9 @@ impl VulkanImage {
         mem::align_of::<u8>() as u64,
         image_buffer_memory_req.size,
     );
-    image_slice.copy_from_slice(&image_data);
-    locked_device.unmap_memory(image_buffer_memory);
-    locked_device
+    image_slice.copy_from_slice(image_data);
+    device.unmap_memory(image_buffer_memory);
+    device
         .bind_buffer_memory(image_buffer, image_buffer_memory, 0)
         .unwrap();
 
@@ -76,6 +76,7 @@ impl VulkanImage {
       device: device.clone(),
       image_buffer,
       image_buffer_memory,
+      last_use: LastUse::default(),
     }
   }
 
@@ -86,10 +87,10 @@ impl VulkanImage {
 
 impl Drop for VulkanImage {
   fn drop(&mut self) {
-    let locked_device = self.device.lock().unwrap();
-    unsafe {
-      locked_device.free_memory(self.image_buffer_memory, None);
-      locked_device.destroy_buffer(self.image_buffer, None);
-    }
+    let (image_buffer_memory, image_buffer) = (self.image_buffer_memory, self.image_buffer);
+    self.device.retire(&self.last_use, move |device| unsafe {
+      device.free_memory(image_buffer_memory, None);
+      device.destroy_buffer(image_buffer, None);
+    });
   }
 }
@@ -11,7 +11,7 @@ use std::{
 };
 
 use ash::{
-    ext::debug_utils,
+    ext::{debug_utils, swapchain_colorspace},
     vk, Entry, Instance,
 };
 use winit::{
@@ -19,22 +19,62 @@ use winit::{
     window::Window,
 };
 
-pub unsafe fn make_vulkan_instance(app_name: &str, entry: &Entry, window: Arc<Mutex<Window>>) -> Result<Instance, Box<dyn Error>> {
+use super::vulkan_physical_device::REQUIRED_API_VERSION;
+
+pub const VALIDATION_LAYER_NAME: &ffi::CStr = c"VK_LAYER_KHRONOS_validation";
+
+/// Optional instance features that were actually enabled, anything requested but unavailable is `false`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InstanceFeatures {
+    pub validation_layer: bool,
+    /// Object names and command buffer labels, and the debug messenger while validating
+    pub debug_utils: bool,
+    /// HDR color spaces on surfaces
+    pub swapchain_colorspace: bool,
+}
+
+/// Validation is only enabled with `enable_validation`, debug utils whenever the loader offers them so object names
+/// and labels show up in capture tools too. Missing optional features are logged instead of failing instance creation,
+/// only the surface extensions are required.
+pub unsafe fn make_vulkan_instance(app_name: &str, entry: &Entry, window: Arc<Mutex<Window>>, enable_validation: bool) -> Result<(Instance, InstanceFeatures), Box<dyn Error>> {
     let app_name = ffi::CString::new(app_name).unwrap();
 
-    let layer_names = [ffi::CStr::from_bytes_with_nul_unchecked(
-        b"VK_LAYER_KHRONOS_validation\0",
-    )];
-    let layers_names_raw: Vec<*const c_char> = layer_names
-        .iter()
-        .map(|raw_name| raw_name.as_ptr())
-        .collect();
+    let available_layers = entry.enumerate_instance_layer_properties().unwrap_or_default();
+    let available_extensions = entry.enumerate_instance_extension_properties(None).unwrap_or_default();
+    let has_layer = |name: &ffi::CStr| available_layers.iter().any(|layer| layer.layer_name_as_c_str() == Ok(name));
+    let has_extension = |name: &ffi::CStr| available_extensions.iter().any(|extension| extension.extension_name_as_c_str() == Ok(name));
 
     let mut extension_names =
         ash_window::enumerate_required_extensions(window.lock().unwrap().display_handle()?.as_raw())
             .unwrap()
             .to_vec();
-    extension_names.push(debug_utils::NAME.as_ptr());
+    let missing_required: Vec<_> = extension_names
+        .iter()
+        .map(|name| ffi::CStr::from_ptr(*name))
+        .filter(|name| !has_extension(name))
+        .collect();
+    if !missing_required.is_empty() {
+        return Err(format!("The Vulkan loader lacks the surface extensions {:?}", missing_required).into());
+    }
+
+    let features = InstanceFeatures {
+        validation_layer: enable_validation && has_layer(VALIDATION_LAYER_NAME),
+        debug_utils: has_extension(debug_utils::NAME),
+        // exposes the HDR color spaces on surfaces, optional since not every loader ships it
+        swapchain_colorspace: has_extension(swapchain_colorspace::NAME),
+    };
+    report_skipped_features(enable_validation, &features);
+
+    let mut layers_names_raw: Vec<*const c_char> = Vec::new();
+    if features.validation_layer {
+        layers_names_raw.push(VALIDATION_LAYER_NAME.as_ptr());
+    }
+    if features.debug_utils {
+        extension_names.push(debug_utils::NAME.as_ptr());
+    }
+    if features.swapchain_colorspace {
+        extension_names.push(swapchain_colorspace::NAME.as_ptr());
+    }
 
     #[cfg(any(target_os = "macos", target_os = "ios"))]
     {
@@ -47,7 +87,7 @@ pub unsafe fn make_vulkan_instance(app_name: &str, entry: &Entry, window: Arc<Mu
         .application_version(0)
         .engine_name(app_name.as_c_str())
         .engine_version(0)
-        .api_version(vk::make_api_version(0, 1, 0, 0));
+        .api_version(REQUIRED_API_VERSION);
 
     let create_flags = if cfg!(any(target_os = "macos", target_os = "ios")) {
         vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR
@@ -63,7 +103,26 @@ pub unsafe fn make_vulkan_instance(app_name: &str, entry: &Entry, window: Arc<Mu
 
     let instance: Instance = entry
         .create_instance(&create_info, None)
-        .expect("Instance creation error");
+        .map_err(|error| format!("Instance creation error: {}", error))?;
 
-    Ok(instance)
+    Ok((instance, features))
+}
+
+fn report_skipped_features(enable_validation: bool, features: &InstanceFeatures) {
+    if enable_validation {
+        if features.validation_layer {
+            log::info!("Enabled {}", VALIDATION_LAYER_NAME.to_string_lossy());
+        } else {
+            log::warn!("Validation was requested but {} is not installed, install the Vulkan SDK to get it", VALIDATION_LAYER_NAME.to_string_lossy());
+        }
+        if !features.debug_utils {
+            log::warn!("{} is unavailable, continuing without a debug messenger", debug_utils::NAME.to_string_lossy());
+        }
+    }
+    if !features.debug_utils {
+        log::info!("{} is unavailable, objects and command buffers are not labeled", debug_utils::NAME.to_string_lossy());
+    }
+    if !features.swapchain_colorspace {
+        log::info!("{} is unavailable, HDR swapchains are disabled", swapchain_colorspace::NAME.to_string_lossy());
+    }
 }
@@ -1,4 +1,4 @@
-use std::sync::{Arc, Mutex};
+use std::{ffi::c_char, sync::Arc};
 
 use ash::{
     ext::debug_utils,
@@ -6,37 +6,42 @@ use ash::{
     vk, Device, Entry, Instance,
 };
 
-pub unsafe fn make_logical_device(instance: &Instance, pdevice: vk::PhysicalDevice, queue_family_index: u32) -> Arc<Mutex<Device>> {
-  let device_extension_names_raw = [
-      swapchain::NAME.as_ptr(),
-      #[cfg(any(target_os = "macos", target_os = "ios"))]
-      ash::khr::portability_subset::NAME.as_ptr(),
-  ];
+use super::{device_context::DeviceContext, vulkan_physical_device::{required_device_extensions, QueueFamilyIndices}};
+
+pub unsafe fn make_logical_device(instance: &Instance, pdevice: vk::PhysicalDevice, queue_families: &QueueFamilyIndices, debug_utils: bool) -> Arc<DeviceContext> {
+  let device_extension_names_raw: Vec<*const c_char> = required_device_extensions()
+      .iter()
+      .map(|extension| extension.as_ptr())
+      .collect();
   let features = vk::PhysicalDeviceFeatures {
       shader_clip_distance: 1,
       ..Default::default()
   };
+  let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features::default()
+      .timeline_semaphore(true);
   let priorities = [1.0];
 
-  let queue_info = vk::DeviceQueueCreateInfo::default()
-      .queue_family_index(queue_family_index)
-      .queue_priorities(&priorities);
+  let queue_infos: Vec<vk::DeviceQueueCreateInfo> = queue_families
+      .unique()
+      .into_iter()
+      .map(|queue_family_index| vk::DeviceQueueCreateInfo::default()
+          .queue_family_index(queue_family_index)
+          .queue_priorities(&priorities))
+      .collect();
 
   let device_create_info = vk::DeviceCreateInfo::default()
-      .queue_create_infos(std::slice::from_ref(&queue_info))
+      .queue_create_infos(&queue_infos)
       .enabled_extension_names(&device_extension_names_raw)
-      .enabled_features(&features);
+      .enabled_features(&features)
+      .push_next(&mut vulkan_12_features);
 
   let device: Device = instance
       .create_device(pdevice, &device_create_info, None)
       .unwrap();
 
-    Arc::new(Mutex::new(device))
+    Arc::new(DeviceContext::new(instance, pdevice, device, *queue_families, debug_utils))
 }
 
-pub fn make_swapchain_device(instance: &Instance, logical_device: Arc<Mutex<Device>>) -> swapchain::Device {
-    let locked_device = logical_device.clone();
-    let locked_device = locked_device.lock().unwrap();
-
-    swapchain::Device::new(instance, &locked_device)
+pub fn make_swapchain_device(logical_device: &DeviceContext) -> swapchain::Device {
+    swapchain::Device::new(&logical_device.instance, &logical_device.device)
 }
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use ash::vk;
+
+use super::{device_context::DeviceContext, gpu_timeline::LastUse, memory::find_memorytype_index};
+
+/// A device local color image that can be rendered into and then sampled by a later pass
+pub struct VulkanOffscreenTarget {
+    pub device: Arc<DeviceContext>,
+    pub image: vk::Image,
+    pub image_view: vk::ImageView,
+    pub image_memory: vk::DeviceMemory,
+    pub last_use: LastUse,
+}
+
+impl VulkanOffscreenTarget {
+    pub fn new(extent: vk::Extent2D, format: vk::Format, device: Arc<DeviceContext>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
+        Self::new_with_usage(
+            extent,
+            format,
+            vk::SampleCountFlags::TYPE_1,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            device,
+            device_memory_properties,
+        )
+    }
+
+    /// Transient multisampled color that only lives inside a render pass before being resolved
+    pub fn new_multisampled(extent: vk::Extent2D, format: vk::Format, samples: vk::SampleCountFlags, device: Arc<DeviceContext>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
+        Self::new_with_usage(
+            extent,
+            format,
+            samples,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+            device,
+            device_memory_properties,
+        )
+    }
+
+    fn new_with_usage(extent: vk::Extent2D, format: vk::Format, samples: vk::SampleCountFlags, usage: vk::ImageUsageFlags, device: Arc<DeviceContext>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
+        let image_create_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(extent.into())
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(samples)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let image = unsafe { device.create_image(&image_create_info, None).unwrap() };
+        let image_memory_req = unsafe { device.get_image_memory_requirements(image) };
+        // tile based gpus can keep transient attachments in on chip memory
+        let lazily_allocated_memory_index = match usage.contains(vk::ImageUsageFlags::TRANSIENT_ATTACHMENT) {
+            true => find_memorytype_index(
+                &image_memory_req,
+                &device_memory_properties,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::LAZILY_ALLOCATED,
+            ),
+            false => None,
+        };
+        let image_memory_index = lazily_allocated_memory_index.or_else(|| find_memorytype_index(
+            &image_memory_req,
+            &device_memory_properties,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        ))
+        .expect("Unable to find suitable memory index for offscreen target.");
+
+        let image_allocate_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(image_memory_req.size)
+            .memory_type_index(image_memory_index);
+
+        let image_memory = unsafe { device
+            .allocate_memory(&image_allocate_info, None)
+            .unwrap() };
+
+        unsafe { device
+            .bind_image_memory(image, image_memory, 0)
+            .expect("Unable to bind offscreen target memory") };
+
+        let image_view_info = vk::ImageViewCreateInfo::default()
+            .subresource_range(
+                vk::ImageSubresourceRange::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .level_count(1)
+                    .layer_count(1),
+            )
+            .image(image)
+            .format(format)
+            .view_type(vk::ImageViewType::TYPE_2D);
+
+        let image_view = unsafe { device
+            .create_image_view(&image_view_info, None)
+            .unwrap() };
+
+        Self {
+            device,
+            image,
+            image_view,
+            image_memory,
+            last_use: LastUse::default(),
+        }
+    }
+}
+
+impl Drop for VulkanOffscreenTarget {
+    fn drop(&mut self) {
+        let (image_view, image, image_memory) = (self.image_view, self.image, self.image_memory);
+        self.device.retire(&self.last_use, move |device| unsafe {
+            device.destroy_image_view(image_view, None);
+            device.destroy_image(image, None);
+            device.free_memory(image_memory, None);
+        });
+    }
+}
@@ -1,50 +1,352 @@
+use std::{error::Error, ffi::CStr, fmt};
+
 use ash::{
-    ext::debug_utils,
     khr::{surface, swapchain},
-    vk::{self, SurfaceKHR}, Device, Entry, Instance,
+    vk::{self, SurfaceKHR}, Instance,
 };
 
-pub unsafe fn get_physical_device_and_family_that_support(instance: &Instance, surface_loader: &surface::Instance, surface: SurfaceKHR) -> (vk::PhysicalDevice, u32) {
-  let pdevices = instance
-      .enumerate_physical_devices()
-      .expect("Physical device error");
-  let (pdevice, queue_family_index) = pdevices
-    .iter()
-    .find_map(|pdevice| {
-        instance
-            .get_physical_device_queue_family_properties(*pdevice)
-            .iter()
-            .enumerate()
-            .find_map(|(index, info)| {
-                let supports_graphic_and_surface =
-                    info.queue_flags.contains(vk::QueueFlags::GRAPHICS)
-                        && surface_loader
-                            .get_physical_device_surface_support(
-                                *pdevice,
-                                index as u32,
-                                surface,
-                            )
-                            .unwrap();
-                if supports_graphic_and_surface {
-                    Some((*pdevice, index))
-                } else {
-                    None
-                }
-            })
+pub fn required_device_extensions() -> Vec<&'static CStr> {
+    vec![
+        swapchain::NAME,
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        ash::khr::portability_subset::NAME,
+    ]
+}
+
+/// Picks a device by its position in `enumerate_physical_devices` or by a case insensitive name fragment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PhysicalDeviceSelector {
+    Index(usize),
+    Name(String),
+}
+
+impl PhysicalDeviceSelector {
+    pub fn parse(selector: &str) -> Self {
+        match selector.trim().parse() {
+            Ok(index) => Self::Index(index),
+            Err(_) => Self::Name(selector.trim().to_lowercase()),
+        }
+    }
+
+    pub fn matches(&self, info: &PhysicalDeviceInfo) -> bool {
+        match self {
+            Self::Index(index) => info.index == *index,
+            Self::Name(name) => info.name.to_lowercase().contains(name.as_str()),
+        }
+    }
+}
+
+/// Everything a device picker needs to show, `score` is `None` when the device cannot run the engine
+#[derive(Debug, Clone)]
+pub struct PhysicalDeviceInfo {
+    pub index: usize,
+    pub physical_device: vk::PhysicalDevice,
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub api_version: u32,
+    pub device_local_memory: u64,
+    pub queue_families: Option<QueueFamilyIndices>,
+    pub missing_extensions: Vec<String>,
+    pub score: Option<u64>,
+}
+
+impl fmt::Display for PhysicalDeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} ({:?}, {} MiB, Vulkan {}.{}.{})",
+            self.index,
+            self.name,
+            self.device_type,
+            self.device_local_memory / (1024 * 1024),
+            vk::api_version_major(self.api_version),
+            vk::api_version_minor(self.api_version),
+            vk::api_version_patch(self.api_version),
+        )
+    }
+}
+
+fn device_type_score(device_type: vk::PhysicalDeviceType) -> u64 {
+    match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 1_000_000,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 100_000,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 10_000,
+        vk::PhysicalDeviceType::CPU => 1_000,
+        _ => 0,
+    }
+}
+
+/// Device type dominates, device local memory in MiB breaks ties between devices of the same type
+pub fn score_physical_device(info: &PhysicalDeviceInfo, supports_required_features: bool) -> Option<u64> {
+    if info.queue_families.is_none() || !info.missing_extensions.is_empty() || !supports_required_features {
+        return None;
+    }
+
+    Some(device_type_score(info.device_type) + info.device_local_memory / (1024 * 1024))
+}
+
+/// Graphics, present and transfer may all be the same family, `transfer` is a dedicated copy family when the device has one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueFamilyIndices {
+    pub graphics: u32,
+    pub present: u32,
+    pub transfer: u32,
+}
+
+impl QueueFamilyIndices {
+    pub fn unique(&self) -> Vec<u32> {
+        let mut families = vec![self.graphics, self.present, self.transfer];
+        families.sort_unstable();
+        families.dedup();
+        families
+    }
+
+    pub fn has_separate_present(&self) -> bool {
+        self.graphics != self.present
+    }
+}
+
+/// # Safety
+///
+/// `physical_device` and `surface` must have been created from `instance`
+pub unsafe fn find_queue_families(instance: &Instance, physical_device: vk::PhysicalDevice, surface_loader: &surface::Instance, surface: SurfaceKHR) -> Option<QueueFamilyIndices> {
+    let families = instance.get_physical_device_queue_family_properties(physical_device);
+    let supports_present = |index: usize| surface_loader
+        .get_physical_device_surface_support(physical_device, index as u32, surface)
+        .unwrap_or(false);
+    let supports = |index: usize, flags: vk::QueueFlags| families[index].queue_count > 0 && families[index].queue_flags.contains(flags);
+
+    // a family that does both avoids sharing the swapchain images between queues
+    let shared_family = (0..families.len()).find(|&index| supports(index, vk::QueueFlags::GRAPHICS) && supports_present(index));
+    let (graphics, present) = match shared_family {
+        Some(index) => (index, index),
+        None => (
+            (0..families.len()).find(|&index| supports(index, vk::QueueFlags::GRAPHICS))?,
+            (0..families.len()).find(|&index| supports_present(index))?,
+        ),
+    };
+
+    let transfer = (0..families.len())
+        .find(|&index| {
+            supports(index, vk::QueueFlags::TRANSFER)
+                && !families[index].queue_flags.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+        })
+        .or_else(|| (0..families.len()).find(|&index| supports(index, vk::QueueFlags::TRANSFER) && !supports(index, vk::QueueFlags::GRAPHICS)))
+        .unwrap_or(graphics);
+
+    Some(QueueFamilyIndices {
+        graphics: graphics as u32,
+        present: present as u32,
+        transfer: transfer as u32,
     })
-    .expect("Couldn't find suitable device.");
+}
+
+unsafe fn get_missing_device_extensions(instance: &Instance, physical_device: vk::PhysicalDevice) -> Vec<String> {
+    let available_extensions = instance
+        .enumerate_device_extension_properties(physical_device)
+        .unwrap_or_default();
 
-    (pdevice, queue_family_index as u32)
+    required_device_extensions()
+        .into_iter()
+        .filter(|required| {
+            !available_extensions
+                .iter()
+                .any(|available| available.extension_name_as_c_str() == Ok(*required))
+        })
+        .map(|missing| missing.to_string_lossy().into_owned())
+        .collect()
 }
 
-pub fn get_mailbox_or_fifo_present_mode(physical_device: &vk::PhysicalDevice, surface_loader: &surface::Instance, surface: SurfaceKHR) -> vk::PresentModeKHR {
-    let present_modes = unsafe { surface_loader
-        .get_physical_device_surface_present_modes(*physical_device, surface)
-        .unwrap() };
+/// Timeline semaphores are core from here on
+pub const REQUIRED_API_VERSION: u32 = vk::API_VERSION_1_2;
+
+unsafe fn supports_timeline_semaphores(instance: &Instance, physical_device: vk::PhysicalDevice, api_version: u32) -> bool {
+    if api_version < REQUIRED_API_VERSION {
+        return false;
+    }
+
+    let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features::default();
+    let mut features = vk::PhysicalDeviceFeatures2::default().push_next(&mut vulkan_12_features);
+    instance.get_physical_device_features2(physical_device, &mut features);
+    vulkan_12_features.timeline_semaphore == vk::TRUE
+}
+
+/// # Safety
+///
+/// `surface` must have been created from `instance`, and `surface_loader` loaded for it
+pub unsafe fn enumerate_physical_devices(instance: &Instance, surface_loader: &surface::Instance, surface: SurfaceKHR) -> Vec<PhysicalDeviceInfo> {
+    let pdevices = instance
+        .enumerate_physical_devices()
+        .expect("Physical device error");
 
-    present_modes
+    pdevices
         .iter()
-        .cloned()
-        .find(|&mode| mode == vk::PresentModeKHR::MAILBOX)
-        .unwrap_or(vk::PresentModeKHR::FIFO)
+        .enumerate()
+        .map(|(index, &physical_device)| {
+            let properties = instance.get_physical_device_properties(physical_device);
+            let memory_properties = instance.get_physical_device_memory_properties(physical_device);
+            let features = instance.get_physical_device_features(physical_device);
+
+            let device_local_memory = memory_properties.memory_heaps_as_slice()
+                .iter()
+                .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+                .map(|heap| heap.size)
+                .sum();
+
+            let mut info = PhysicalDeviceInfo {
+                index,
+                physical_device,
+                name: properties
+                    .device_name_as_c_str()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                device_type: properties.device_type,
+                api_version: properties.api_version,
+                device_local_memory,
+                queue_families: find_queue_families(instance, physical_device, surface_loader, surface),
+                missing_extensions: get_missing_device_extensions(instance, physical_device),
+                score: None,
+            };
+            let supports_required_features = features.shader_clip_distance == vk::TRUE
+                && supports_timeline_semaphores(instance, physical_device, properties.api_version);
+            info.score = score_physical_device(&info, supports_required_features);
+            info
+        })
+        .collect()
+}
+
+/// Highest scoring usable device, or the device matching `selector` when one is given
+pub fn select_physical_device(devices: &[PhysicalDeviceInfo], selector: Option<&PhysicalDeviceSelector>) -> Result<PhysicalDeviceInfo, Box<dyn Error>> {
+    let available_devices = devices.iter().map(|device| device.to_string()).collect::<Vec<_>>().join(", ");
+
+    let selected = match selector {
+        Some(selector) => devices
+            .iter()
+            .find(|device| selector.matches(device))
+            .ok_or_else(|| format!("No physical device matches {:?}, available devices: {}", selector, available_devices))?,
+        None => devices
+            .iter()
+            .filter(|device| device.score.is_some())
+            .max_by_key(|device| device.score)
+            .ok_or_else(|| format!("Couldn't find suitable device, available devices: {}", available_devices))?,
+    };
+
+    if selected.score.is_none() {
+        return Err(format!(
+            "Physical device {} is not usable, queue families: {:?}, missing extensions: {:?}",
+            selected, selected.queue_families, selected.missing_extensions
+        ).into());
+    }
+
+    Ok(selected.clone())
+}
+
+pub fn get_surface_present_modes(physical_device: &vk::PhysicalDevice, surface_loader: &surface::Instance, surface: SurfaceKHR) -> Vec<vk::PresentModeKHR> {
+    unsafe { surface_loader
+        .get_physical_device_surface_present_modes(*physical_device, surface)
+        .unwrap() }
+}
+
+/// Highest sample count usable for both color and depth framebuffer attachments
+pub fn get_max_usable_sample_count(instance: &Instance, physical_device: vk::PhysicalDevice) -> vk::SampleCountFlags {
+    let limits = unsafe { instance.get_physical_device_properties(physical_device).limits };
+    let counts = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+    [
+        vk::SampleCountFlags::TYPE_64,
+        vk::SampleCountFlags::TYPE_32,
+        vk::SampleCountFlags::TYPE_16,
+        vk::SampleCountFlags::TYPE_8,
+        vk::SampleCountFlags::TYPE_4,
+        vk::SampleCountFlags::TYPE_2,
+    ]
+    .into_iter()
+    .find(|&count| counts.contains(count))
+    .unwrap_or(vk::SampleCountFlags::TYPE_1)
+}
+
+/// Rounds a requested sample count down to the nearest power of two the device supports
+pub fn clamp_sample_count(requested_samples: u32, max_usable_samples: vk::SampleCountFlags) -> vk::SampleCountFlags {
+    let requested = requested_samples.max(1);
+    let requested = 1 << (31 - requested.leading_zeros());
+    vk::SampleCountFlags::from_raw(requested.min(max_usable_samples.as_raw()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    fn device(index: usize, name: &str, device_type: vk::PhysicalDeviceType, device_local_memory: u64) -> PhysicalDeviceInfo {
+        let mut info = PhysicalDeviceInfo {
+            index,
+            physical_device: vk::PhysicalDevice::null(),
+            name: name.to_string(),
+            device_type,
+            api_version: REQUIRED_API_VERSION,
+            device_local_memory,
+            queue_families: Some(QueueFamilyIndices { graphics: 0, present: 0, transfer: 0 }),
+            missing_extensions: Vec::new(),
+            score: None,
+        };
+        info.score = score_physical_device(&info, true);
+        info
+    }
+
+    #[test]
+    fn device_type_outweighs_memory() {
+        let discrete = device(0, "Discrete", vk::PhysicalDeviceType::DISCRETE_GPU, 2048 * MIB);
+        let integrated = device(1, "Integrated", vk::PhysicalDeviceType::INTEGRATED_GPU, 16384 * MIB);
+        let larger_discrete = device(2, "Larger Discrete", vk::PhysicalDeviceType::DISCRETE_GPU, 8192 * MIB);
+
+        assert_eq!(discrete.score, Some(1_000_000 + 2048));
+        assert!(discrete.score > integrated.score);
+        assert!(larger_discrete.score > discrete.score);
+        assert!(device(3, "Cpu", vk::PhysicalDeviceType::CPU, 0).score > device(4, "Other", vk::PhysicalDeviceType::OTHER, 0).score);
+    }
+
+    #[test]
+    fn unusable_devices_have_no_score() {
+        let mut info = device(0, "Gpu", vk::PhysicalDeviceType::DISCRETE_GPU, MIB);
+        assert_eq!(score_physical_device(&info, false), None);
+
+        info.missing_extensions = vec!["VK_KHR_swapchain".to_string()];
+        assert_eq!(score_physical_device(&info, true), None);
+
+        info.missing_extensions.clear();
+        info.queue_families = None;
+        assert_eq!(score_physical_device(&info, true), None);
+    }
+
+    #[test]
+    fn selection_prefers_the_highest_score_unless_a_selector_is_given() {
+        let mut unusable = device(0, "Broken Discrete", vk::PhysicalDeviceType::DISCRETE_GPU, 4096 * MIB);
+        unusable.queue_families = None;
+        unusable.score = None;
+        let devices = [
+            unusable,
+            device(1, "Integrated", vk::PhysicalDeviceType::INTEGRATED_GPU, 1024 * MIB),
+            device(2, "Discrete", vk::PhysicalDeviceType::DISCRETE_GPU, 1024 * MIB),
+        ];
+
+        assert_eq!(select_physical_device(&devices, None).unwrap().index, 2);
+        assert_eq!(select_physical_device(&devices, Some(&PhysicalDeviceSelector::parse("1"))).unwrap().index, 1);
+        assert_eq!(select_physical_device(&devices, Some(&PhysicalDeviceSelector::parse(" INTEGRATED "))).unwrap().index, 1);
+        assert!(select_physical_device(&devices, Some(&PhysicalDeviceSelector::parse("broken"))).is_err());
+        assert!(select_physical_device(&devices, Some(&PhysicalDeviceSelector::Index(7))).is_err());
+        assert!(select_physical_device(&devices[..1], None).is_err());
+    }
+
+    #[test]
+    fn sample_counts_round_down_to_a_supported_power_of_two() {
+        let max = vk::SampleCountFlags::TYPE_8;
+        assert_eq!(clamp_sample_count(0, max), vk::SampleCountFlags::TYPE_1);
+        assert_eq!(clamp_sample_count(1, max), vk::SampleCountFlags::TYPE_1);
+        assert_eq!(clamp_sample_count(3, max), vk::SampleCountFlags::TYPE_2);
+        assert_eq!(clamp_sample_count(4, max), vk::SampleCountFlags::TYPE_4);
+        assert_eq!(clamp_sample_count(7, max), vk::SampleCountFlags::TYPE_4);
+        assert_eq!(clamp_sample_count(64, max), vk::SampleCountFlags::TYPE_8);
+        assert_eq!(clamp_sample_count(u32::MAX, vk::SampleCountFlags::TYPE_1), vk::SampleCountFlags::TYPE_1);
+    }
 }
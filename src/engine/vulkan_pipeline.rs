@@ -1,10 +1,10 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::mem;
 
 use ash::util::Align;
-use ash::{vk, Device};
+use ash::vk;
 
-use super::vulkan_descriptor::VulkanDescriptorSetLayouts;
+use super::{device_context::DeviceContext, gpu_timeline::LastUse, vulkan_descriptor::VulkanDescriptorSetLayouts, vulkan_pipeline_cache::VulkanPipelineCache};
 
 // let layout_create_info =
 //                 vk::PipelineLayoutCreateInfo::default().set_layouts(&descriptor_set_layouts.descriptor_set_layouts);
@@ -15,67 +15,217 @@ use super::vulkan_descriptor::VulkanDescriptorSetLayouts;
 //                 .unwrap();
 
 pub struct VulkanPipelineLayout {
-    pub device: Arc<Mutex<Device>>,
+    pub device: Arc<DeviceContext>,
     pub pipeline_layout: vk::PipelineLayout,
+    pub last_use: LastUse,
 }
 
 impl VulkanPipelineLayout {
-    pub fn new(device: Arc<Mutex<Device>>, descriptor_set_layouts: &VulkanDescriptorSetLayouts) -> Self {
-        let layout_create_info =
-            vk::PipelineLayoutCreateInfo::default().set_layouts(&descriptor_set_layouts.descriptor_set_layouts);
+    pub fn new(device: Arc<DeviceContext>, descriptor_set_layouts: &VulkanDescriptorSetLayouts) -> Self {
+        Self::new_with_push_constants(device, descriptor_set_layouts, &[])
+    }
 
-        let locked_device = device.clone();
-        let locked_device = locked_device.lock().unwrap();
+    pub fn new_with_push_constants(device: Arc<DeviceContext>, descriptor_set_layouts: &VulkanDescriptorSetLayouts, push_constant_ranges: &[vk::PushConstantRange]) -> Self {
+        let layout_create_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&descriptor_set_layouts.descriptor_set_layouts)
+            .push_constant_ranges(push_constant_ranges);
 
         let pipeline_layout = unsafe {
-            locked_device
+            device
                 .create_pipeline_layout(&layout_create_info, None)
                 .unwrap()
         };
 
-        Self { device, pipeline_layout }
+        Self { device, pipeline_layout, last_use: LastUse::default() }
     }
 }
 
 impl Drop for VulkanPipelineLayout {
     fn drop(&mut self) {
-        let locked_device = self.device.clone();
-        let locked_device = locked_device.lock().unwrap();
+        let pipeline_layout = self.pipeline_layout;
+        self.device.retire(&self.last_use, move |device| unsafe {
+            device.destroy_pipeline_layout(pipeline_layout, None);
+        });
+    }
+}
+
+/// Stencil operations applied to both faces, only honoured when the depth format carries a stencil aspect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StencilConfig {
+    pub compare_op: vk::CompareOp,
+    pub pass_op: vk::StencilOp,
+    pub fail_op: vk::StencilOp,
+    pub depth_fail_op: vk::StencilOp,
+    pub reference: u32,
+    pub compare_mask: u32,
+    pub write_mask: u32,
+}
+
+impl StencilConfig {
+    /// Only draws where the stored value equals `reference`, leaving the stencil untouched
+    pub fn test_equal(reference: u32) -> Self {
+        Self {
+            compare_op: vk::CompareOp::EQUAL,
+            pass_op: vk::StencilOp::KEEP,
+            fail_op: vk::StencilOp::KEEP,
+            depth_fail_op: vk::StencilOp::KEEP,
+            reference,
+            compare_mask: 0xff,
+            write_mask: 0,
+        }
+    }
+
+    /// Increments the stored value inside an existing clip of depth `reference`, nesting masks intersect
+    pub fn increment_where_equal(reference: u32) -> Self {
+        Self {
+            compare_op: vk::CompareOp::EQUAL,
+            pass_op: vk::StencilOp::INCREMENT_AND_CLAMP,
+            write_mask: 0xff,
+            ..Self::test_equal(reference)
+        }
+    }
 
-        unsafe {
-            locked_device.destroy_pipeline_layout(self.pipeline_layout, None);
+    /// Undoes `increment_where_equal`, `reference` being the depth the mask raised the stencil to
+    pub fn decrement_where_equal(reference: u32) -> Self {
+        Self {
+            pass_op: vk::StencilOp::DECREMENT_AND_CLAMP,
+            ..Self::increment_where_equal(reference)
+        }
+    }
+
+    pub fn op_state(&self) -> vk::StencilOpState {
+        vk::StencilOpState {
+            fail_op: self.fail_op,
+            pass_op: self.pass_op,
+            depth_fail_op: self.depth_fail_op,
+            compare_op: self.compare_op,
+            compare_mask: self.compare_mask,
+            write_mask: self.write_mask,
+            reference: self.reference,
         }
     }
 }
 
+/// Fixed function state that varies between otherwise identical graphics pipelines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineConfig {
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub color_write_mask: vk::ColorComponentFlags,
+    pub stencil: Option<StencilConfig>,
+    /// The stencil reference is set with `cmd_set_stencil_reference` instead of baked into the pipeline
+    pub dynamic_stencil_reference: bool,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            depth_test: true,
+            depth_write: true,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+            stencil: None,
+            dynamic_stencil_reference: false,
+        }
+    }
+}
+
+impl PipelineConfig {
+    /// Content drawn through a `ClipStack`, the reference is the current clip depth so zero means unclipped
+    pub fn stencil_clipped() -> Self {
+        Self {
+            stencil: Some(StencilConfig::test_equal(0)),
+            dynamic_stencil_reference: true,
+            ..Self::default()
+        }
+    }
+
+    /// Raises the stencil inside a non rectangular clip shape without touching color or depth
+    pub fn stencil_clip_push() -> Self {
+        Self {
+            depth_test: false,
+            depth_write: false,
+            color_write_mask: vk::ColorComponentFlags::empty(),
+            stencil: Some(StencilConfig::increment_where_equal(0)),
+            dynamic_stencil_reference: true,
+        }
+    }
+
+    /// Lowers the stencil again once a clip shape is popped
+    pub fn stencil_clip_pop() -> Self {
+        Self {
+            stencil: Some(StencilConfig::decrement_where_equal(0)),
+            ..Self::stencil_clip_push()
+        }
+    }
+
+    pub fn has_stencil(&self) -> bool {
+        self.stencil.is_some()
+    }
+
+    pub fn dynamic_states(&self) -> Vec<vk::DynamicState> {
+        let mut dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        if self.stencil.is_some() && self.dynamic_stencil_reference {
+            dynamic_states.push(vk::DynamicState::STENCIL_REFERENCE);
+        }
+        dynamic_states
+    }
+
+    /// Drops the stencil state when the depth attachment has no stencil aspect to test against
+    pub fn for_depth_format(self, has_stencil: bool) -> Self {
+        if self.stencil.is_some() && !has_stencil {
+            log::warn!("Depth format has no stencil aspect, ignoring pipeline stencil configuration");
+            return Self { stencil: None, dynamic_stencil_reference: false, ..self };
+        }
+
+        self
+    }
+
+    pub fn depth_stencil_state(&self) -> vk::PipelineDepthStencilStateCreateInfo<'static> {
+        let noop_stencil_state = vk::StencilOpState {
+            fail_op: vk::StencilOp::KEEP,
+            pass_op: vk::StencilOp::KEEP,
+            depth_fail_op: vk::StencilOp::KEEP,
+            compare_op: vk::CompareOp::ALWAYS,
+            ..Default::default()
+        };
+        let stencil_state = self.stencil.map(|stencil| stencil.op_state()).unwrap_or(noop_stencil_state);
+
+        vk::PipelineDepthStencilStateCreateInfo {
+            depth_test_enable: self.depth_test.into(),
+            depth_write_enable: self.depth_write.into(),
+            depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+            stencil_test_enable: self.stencil.is_some().into(),
+            front: stencil_state,
+            back: stencil_state,
+            max_depth_bounds: 1.0,
+            ..Default::default()
+        }
+    }
+}
 
 pub struct VulkanPipeline {
-    pub device: Arc<Mutex<Device>>,
+    pub device: Arc<DeviceContext>,
     pub pipeline: vk::Pipeline,
+    pub last_use: LastUse,
 }
 
 impl VulkanPipeline {
-    pub fn new(device: Arc<Mutex<Device>>, pipeline_create_info: vk::GraphicsPipelineCreateInfo) -> Self {
-        let locked_device = device.clone();
-        let locked_device = locked_device.lock().unwrap();
-
+    pub fn new(device: Arc<DeviceContext>, pipeline_cache: &VulkanPipelineCache, pipeline_create_info: vk::GraphicsPipelineCreateInfo) -> Self {
         let pipeline = unsafe {
-            locked_device
-                .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_create_info], None)
+            device
+                .create_graphics_pipelines(pipeline_cache.pipeline_cache, &[pipeline_create_info], None)
                 .expect("Failed to create graphics pipeline")[0]
         };
 
-        Self { device, pipeline }
+        Self { device, pipeline, last_use: LastUse::default() }
     }
 }
 
 impl Drop for VulkanPipeline {
     fn drop(&mut self) {
-        let locked_device = self.device.clone();
-        let locked_device = locked_device.lock().unwrap();
-
-        unsafe {
-            locked_device.destroy_pipeline(self.pipeline, None);
-        }
+        let pipeline = self.pipeline;
+        self.device.retire(&self.last_use, move |device| unsafe {
+            device.destroy_pipeline(pipeline, None);
+        });
     }
 }
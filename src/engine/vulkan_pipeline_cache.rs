@@ -0,0 +1,109 @@
+use std::{error::Error, fs, path::{Path, PathBuf}, sync::Arc};
+
+use ash::vk;
+
+use super::device_context::DeviceContext;
+
+// VkPipelineCacheHeaderVersionOne: header size, header version, vendor id, device id, pipeline cache uuid
+const PIPELINE_CACHE_HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+/// The platform's per user cache directory, the temp directory if it can't be determined
+pub fn user_cache_directory() -> PathBuf {
+    let from_env = |variable: &str| std::env::var_os(variable).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let directory = if cfg!(target_os = "windows") {
+        from_env("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        from_env("HOME").map(|home| home.join("Library/Caches"))
+    } else {
+        from_env("XDG_CACHE_HOME").or_else(|| from_env("HOME").map(|home| home.join(".cache")))
+    };
+    directory.unwrap_or_else(std::env::temp_dir).join("ash-base")
+}
+
+/// One file per device and driver build, so switching GPUs doesn't throw away the other one's cache
+pub fn default_pipeline_cache_path(physical_device_properties: &vk::PhysicalDeviceProperties) -> PathBuf {
+    let uuid: String = physical_device_properties.pipeline_cache_uuid.iter().map(|byte| format!("{:02x}", byte)).collect();
+    user_cache_directory().join(format!(
+        "pipeline-cache-{:04x}-{:04x}-{}.bin",
+        physical_device_properties.vendor_id, physical_device_properties.device_id, uuid
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Only hand the driver cache data written by this exact driver and device, anything else is discarded
+pub fn is_pipeline_cache_data_valid(data: &[u8], physical_device_properties: &vk::PhysicalDeviceProperties) -> bool {
+    if data.len() < PIPELINE_CACHE_HEADER_SIZE {
+        return false;
+    }
+
+    let header_size = read_u32(data, 0) as usize;
+    let header_version = read_u32(data, 4);
+    let vendor_id = read_u32(data, 8);
+    let device_id = read_u32(data, 12);
+    let pipeline_cache_uuid = &data[16..PIPELINE_CACHE_HEADER_SIZE];
+
+    header_size >= PIPELINE_CACHE_HEADER_SIZE
+        && header_size <= data.len()
+        && header_version == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && vendor_id == physical_device_properties.vendor_id
+        && device_id == physical_device_properties.device_id
+        && pipeline_cache_uuid == physical_device_properties.pipeline_cache_uuid
+}
+
+pub struct VulkanPipelineCache {
+    pub device: Arc<DeviceContext>,
+    pub pipeline_cache: vk::PipelineCache,
+    pub cache_path: PathBuf,
+}
+
+impl VulkanPipelineCache {
+    pub fn new(device: Arc<DeviceContext>, physical_device_properties: &vk::PhysicalDeviceProperties, cache_path: &Path) -> Self {
+        let initial_data = match fs::read(cache_path) {
+            Ok(data) if is_pipeline_cache_data_valid(&data, physical_device_properties) => data,
+            Ok(_) => {
+                log::info!("Discarding stale pipeline cache {}", cache_path.display());
+                Vec::new()
+            }
+            Err(_) => Vec::new(),
+        };
+
+        let pipeline_cache_info = vk::PipelineCacheCreateInfo::default().initial_data(&initial_data);
+        let pipeline_cache = unsafe {
+            device
+                .create_pipeline_cache(&pipeline_cache_info, None)
+                .or_else(|_| device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None))
+                .expect("Failed to create pipeline cache")
+        };
+
+        Self {
+            device,
+            pipeline_cache,
+            cache_path: cache_path.to_path_buf(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let data = unsafe { self.device.get_pipeline_cache_data(self.pipeline_cache)? };
+
+        if let Some(cache_directory) = self.cache_path.parent() {
+            fs::create_dir_all(cache_directory)?;
+        }
+        // write beside the real file first so an interrupted save never leaves a truncated cache behind
+        let temporary_path = self.cache_path.with_extension("tmp");
+        fs::write(&temporary_path, &data)?;
+        fs::rename(&temporary_path, &self.cache_path)?;
+
+        Ok(())
+    }
+}
+
+impl Drop for VulkanPipelineCache {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_pipeline_cache(self.pipeline_cache, None);
+        }
+    }
+}
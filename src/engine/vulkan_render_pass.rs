@@ -1,48 +1,115 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 use ash::vk;
 
-use super::vulkan_attachments::{make_color_attachment, make_color_subpass_dependency, make_depth_attachment, make_standard_depth_color_attachments};
+use super::vulkan_attachments::{make_color_attachment, make_color_subpass_dependency, make_depth_attachment, make_fullscreen_color_attachments, make_sampled_color_input_subpass_dependency, make_sampled_color_output_subpass_dependency, make_standard_depth_color_attachments};
+
+use super::device_context::DeviceContext;
+use super::gpu_timeline::LastUse;
 
 pub struct VulkanColorDepthRenderPass {
-    pub device: Arc<Mutex<ash::Device>>,
+    pub device: Arc<DeviceContext>,
     pub render_pass: vk::RenderPass,
+    pub last_use: LastUse,
 }
 
 impl VulkanColorDepthRenderPass {
-    pub fn new(device: Arc<Mutex<ash::Device>>, surface_format: vk::Format) -> Self {
-        let locked_device = device.clone();
-        let locked_device = locked_device.lock().unwrap();
+    pub fn new(device: Arc<DeviceContext>, surface_format: vk::Format, depth_format: vk::Format, samples: vk::SampleCountFlags) -> Self {
+        Self::new_with_final_layout(
+            device,
+            surface_format,
+            depth_format,
+            samples,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            &[make_color_subpass_dependency()],
+        )
+    }
 
-        let renderpass_attachments = make_standard_depth_color_attachments(surface_format);
+    /// Renders the scene into an offscreen target that post processing samples afterwards
+    pub fn new_offscreen(device: Arc<DeviceContext>, color_format: vk::Format, depth_format: vk::Format, samples: vk::SampleCountFlags) -> Self {
+        Self::new_with_final_layout(
+            device,
+            color_format,
+            depth_format,
+            samples,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            &[make_sampled_color_input_subpass_dependency(), make_sampled_color_output_subpass_dependency()],
+        )
+    }
+
+    fn new_with_final_layout(device: Arc<DeviceContext>, color_format: vk::Format, depth_format: vk::Format, samples: vk::SampleCountFlags, final_color_layout: vk::ImageLayout, dependencies: &[vk::SubpassDependency]) -> Self {
+        let renderpass_attachments = make_standard_depth_color_attachments(color_format, depth_format, final_color_layout, samples);
 
         let color_attachment_refs = [make_color_attachment(0)];
         let depth_attachment_ref = make_depth_attachment(1);
-        let dependencies = [make_color_subpass_dependency()];
+        let resolve_attachment_refs = [make_color_attachment(2)];
 
-        let subpass = vk::SubpassDescription::default()
+        let mut subpass = vk::SubpassDescription::default()
             .color_attachments(&color_attachment_refs)
             .depth_stencil_attachment(&depth_attachment_ref)
             .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
+        if samples != vk::SampleCountFlags::TYPE_1 {
+            subpass = subpass.resolve_attachments(&resolve_attachment_refs);
+        }
 
         let renderpass_create_info = vk::RenderPassCreateInfo::default()
             .attachments(&renderpass_attachments)
             .subpasses(std::slice::from_ref(&subpass))
-            .dependencies(&dependencies);
+            .dependencies(dependencies);
 
-        let render_pass = unsafe { locked_device
+        let render_pass = unsafe { device
             .create_render_pass(&renderpass_create_info, None)
             .unwrap() };
 
-        Self { render_pass, device }
+        Self { render_pass, device, last_use: LastUse::default() }
     }
 }
 
 impl Drop for VulkanColorDepthRenderPass {
     fn drop(&mut self) {
-        let locked_device = self.device.lock().unwrap();
-        unsafe {
-            locked_device.destroy_render_pass(self.render_pass, None);
-        }
+        let render_pass = self.render_pass;
+        self.device.retire(&self.last_use, move |device| unsafe {
+            device.destroy_render_pass(render_pass, None);
+        });
+    }
+}
+
+/// Color only render pass for full screen passes, the attachment is overwritten rather than cleared
+pub struct VulkanColorRenderPass {
+    pub device: Arc<DeviceContext>,
+    pub render_pass: vk::RenderPass,
+    pub last_use: LastUse,
+}
+
+impl VulkanColorRenderPass {
+    pub fn new(device: Arc<DeviceContext>, color_format: vk::Format, final_color_layout: vk::ImageLayout) -> Self {
+        let renderpass_attachments = make_fullscreen_color_attachments(color_format, final_color_layout);
+
+        let color_attachment_refs = [make_color_attachment(0)];
+        let dependencies = [make_sampled_color_input_subpass_dependency(), make_sampled_color_output_subpass_dependency()];
+
+        let subpass = vk::SubpassDescription::default()
+            .color_attachments(&color_attachment_refs)
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
+
+        let renderpass_create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&renderpass_attachments)
+            .subpasses(std::slice::from_ref(&subpass))
+            .dependencies(&dependencies);
+
+        let render_pass = unsafe { device
+            .create_render_pass(&renderpass_create_info, None)
+            .unwrap() };
+
+        Self { render_pass, device, last_use: LastUse::default() }
+    }
+}
+
+impl Drop for VulkanColorRenderPass {
+    fn drop(&mut self) {
+        let render_pass = self.render_pass;
+        self.device.retire(&self.last_use, move |device| unsafe {
+            device.destroy_render_pass(render_pass, None);
+        });
     }
 }
@@ -1,16 +1,19 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::mem;
 
 use ash::util::Align;
-use ash::{vk, Device};
+use ash::vk;
+
+use super::{device_context::DeviceContext, gpu_timeline::LastUse};
 
 pub struct VulkanSampler {
-  pub device: Arc<Mutex<Device>>,
+  pub device: Arc<DeviceContext>,
   pub sampler: vk::Sampler,
+  pub last_use: LastUse,
 }
 
 impl VulkanSampler {
-  pub fn new(device: Arc<Mutex<Device>>) -> Self {
+  pub fn new(device: Arc<DeviceContext>) -> Self {
     let sampler_info = vk::SamplerCreateInfo {
       mag_filter: vk::Filter::LINEAR,
       min_filter: vk::Filter::LINEAR,
@@ -24,20 +27,17 @@ impl VulkanSampler {
       ..Default::default()
     };
 
-    let locked_device = device.clone();
-    let locked_device = locked_device.lock().unwrap();
-
-    let sampler = unsafe { locked_device.create_sampler(&sampler_info, None).unwrap() };
+    let sampler = unsafe { device.create_sampler(&sampler_info, None).unwrap() };
 
-    Self { device, sampler }
+    Self { device, sampler, last_use: LastUse::default() }
   }
 }
 
 impl Drop for VulkanSampler {
   fn drop(&mut self) {
-    let locked_device = self.device.lock().unwrap();
-    unsafe {
-      locked_device.destroy_sampler(self.sampler, None);
-    }
+    let sampler = self.sampler;
+    self.device.retire(&self.last_use, move |device| unsafe {
+      device.destroy_sampler(sampler, None);
+    });
   }
 }
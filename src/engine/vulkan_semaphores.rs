@@ -1,15 +1,14 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
-use ash::{Device, vk};
+use ash::vk;
 
-pub fn create_semaphores(device: Arc<Mutex<Device>>, semaphore_count: usize) -> Vec<vk::Semaphore> {
-    let locked_device = device.clone();
-    let locked_device = locked_device.lock().unwrap();
+use super::device_context::DeviceContext;
 
+pub fn create_semaphores(device: Arc<DeviceContext>, semaphore_count: usize) -> Vec<vk::Semaphore> {
     let semaphore_create_info = vk::SemaphoreCreateInfo::default();
 
     (0..semaphore_count)
-        .map(|_| unsafe {locked_device
+        .map(|_| unsafe {device
             .create_semaphore(&semaphore_create_info, None)
             .unwrap() }
         ).collect()
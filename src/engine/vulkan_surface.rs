@@ -11,6 +11,8 @@ use winit::{
     window::Window,
 };
 
+use super::presentation::select_surface_format;
+
 pub fn get_surface_capabilities(physical_device: &vk::PhysicalDevice, surface_loader: &surface::Instance, surface: SurfaceKHR) -> vk::SurfaceCapabilitiesKHR {
     unsafe {
         surface_loader
@@ -19,14 +21,6 @@ pub fn get_surface_capabilities(physical_device: &vk::PhysicalDevice, surface_lo
     }
 }
 
-pub fn get_standard_surface_image_count(surface_capabilities: &vk::SurfaceCapabilitiesKHR) -> u32 {
-    let mut desired_image_count = surface_capabilities.min_image_count + 1;
-    if surface_capabilities.max_image_count > 0 && desired_image_count > surface_capabilities.max_image_count {
-        desired_image_count = surface_capabilities.max_image_count;
-    }
-    desired_image_count
-}
-
 pub fn get_surface_capabilities_pre_transform(surface_capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::SurfaceTransformFlagsKHR {
     if surface_capabilities
         .supported_transforms
@@ -65,27 +59,29 @@ impl VulkanSurface {
         }
     }
 
-    pub fn get_format(&self, physical_device: &vk::PhysicalDevice) -> vk::SurfaceFormatKHR {
+    pub fn get_formats(&self, physical_device: &vk::PhysicalDevice) -> Vec<vk::SurfaceFormatKHR> {
         unsafe {
             self.surface_loader
                 .get_physical_device_surface_formats(*physical_device, self.surface)
-                .unwrap()[0]
+                .unwrap()
         }
     }
 
-    pub fn get_resolution(&self, desired_resolution: vk::Extent2D, physical_device: &vk::PhysicalDevice) -> vk::Extent2D {
-        unsafe {
-            let surface_capabilities = get_surface_capabilities(physical_device, &self.surface_loader, self.surface);
+    pub fn get_format(&self, physical_device: &vk::PhysicalDevice, preferred_surface_formats: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR {
+        select_surface_format(&self.get_formats(physical_device), preferred_surface_formats)
+    }
 
-            let surface_resolution = match surface_capabilities.current_extent.width {
-                u32::MAX => desired_resolution,
-                _ => surface_capabilities.current_extent,
-            };
+    /// The surface decides the extent unless it reports the special value, then the window size is clamped into range,
+    /// a zero extent means the window is minimized and nothing can be presented
+    pub fn get_resolution(&self, desired_resolution: vk::Extent2D, physical_device: &vk::PhysicalDevice) -> vk::Extent2D {
+        let surface_capabilities = get_surface_capabilities(physical_device, &self.surface_loader, self.surface);
 
-            self.surface_loader
-                .get_physical_device_surface_capabilities(*physical_device, self.surface)
-                .unwrap()
-                .current_extent
+        match surface_capabilities.current_extent.width {
+            u32::MAX => vk::Extent2D {
+                width: desired_resolution.width.clamp(surface_capabilities.min_image_extent.width, surface_capabilities.max_image_extent.width),
+                height: desired_resolution.height.clamp(surface_capabilities.min_image_extent.height, surface_capabilities.max_image_extent.height),
+            },
+            _ => surface_capabilities.current_extent,
         }
     }
 }
@@ -1,4 +1,4 @@
-use std::sync::{Mutex, Arc};
+use std::sync::Arc;
 
 use ash::{
     ext::debug_utils,
@@ -11,16 +11,22 @@ use winit::{
     window::Window,
 };
 
-use super::{vulkan_physical_device::get_mailbox_or_fifo_present_mode, vulkan_surface::{get_standard_surface_image_count, get_surface_capabilities, get_surface_capabilities_pre_transform, VulkanSurface}};
-
-
-pub fn create_standard_swapchain(physical_device: &vk::PhysicalDevice, surface: &VulkanSurface, surface_format: SurfaceFormatKHR, dimensions: vk::Extent2D, swapchain_device: &swapchain::Device) -> vk::SwapchainKHR {
+use super::{device_context::DeviceContext, presentation::{select_image_count, select_present_mode, PresentationConfig}, vulkan_physical_device::{get_surface_present_modes, QueueFamilyIndices}, vulkan_surface::{get_surface_capabilities, get_surface_capabilities_pre_transform, VulkanSurface}};
 
+pub fn create_standard_swapchain(physical_device: &vk::PhysicalDevice, surface: &VulkanSurface, surface_format: SurfaceFormatKHR, dimensions: vk::Extent2D, queue_families: &QueueFamilyIndices, presentation_config: &PresentationConfig, old_swapchain: vk::SwapchainKHR, swapchain_device: &swapchain::Device) -> vk::SwapchainKHR {
     let surface_capabilities = get_surface_capabilities(&physical_device, &surface.surface_loader, surface.surface);
-    let desired_image_count = get_standard_surface_image_count(&surface_capabilities);
+    let desired_image_count = select_image_count(&surface_capabilities, presentation_config.image_count);
+    let present_mode = select_present_mode(&get_surface_present_modes(physical_device, &surface.surface_loader, surface.surface), presentation_config.vsync);
 
     let pre_transform = get_surface_capabilities_pre_transform(&surface_capabilities);
 
+    // images are rendered on the graphics family and handed to the present family without ownership transfers
+    let sharing_queue_families = [queue_families.graphics, queue_families.present];
+    let (sharing_mode, sharing_queue_families) = match queue_families.has_separate_present() {
+        true => (vk::SharingMode::CONCURRENT, &sharing_queue_families[..]),
+        false => (vk::SharingMode::EXCLUSIVE, &[][..]),
+    };
+
     let swapchain_create_info = vk::SwapchainCreateInfoKHR::default()
         .surface(surface.surface)
         .min_image_count(desired_image_count)
@@ -28,11 +34,14 @@ pub fn create_standard_swapchain(physical_device: &vk::PhysicalDevice, surface:
         .image_format(surface_format.format)
         .image_extent(dimensions)
         .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-        .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .image_sharing_mode(sharing_mode)
+        .queue_family_indices(sharing_queue_families)
         .pre_transform(pre_transform)
         .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-        .present_mode(get_mailbox_or_fifo_present_mode(physical_device, &surface.surface_loader, surface.surface))
+        .present_mode(present_mode)
         .clipped(true)
+        // lets the driver hand over resources from the swapchain being replaced, null on first creation
+        .old_swapchain(old_swapchain)
         .image_array_layers(1);
 
     unsafe {
@@ -42,16 +51,15 @@ pub fn create_standard_swapchain(physical_device: &vk::PhysicalDevice, surface:
     }
 }
 
-pub fn get_swapchain_image_views(logical_device: Arc<Mutex<ash::Device>>, swapchain_device: &swapchain::Device, swapchain: vk::SwapchainKHR, surface_format: SurfaceFormatKHR) -> (Vec<vk::Image>, Vec<vk::ImageView>) {
+pub fn get_swapchain_image_views(logical_device: Arc<DeviceContext>, swapchain_device: &swapchain::Device, swapchain: vk::SwapchainKHR, surface_format: SurfaceFormatKHR) -> (Vec<vk::Image>, Vec<vk::ImageView>) {
     unsafe {
-        let locked_device = logical_device.clone();
-        let locked_device = locked_device.lock().unwrap();
-
         let present_images = swapchain_device.get_swapchain_images(swapchain).unwrap();
 
         let views: Vec<vk::ImageView> = present_images
                 .iter()
-                .map(|&image| {
+                .enumerate()
+                .map(|(index, &image)| {
+                    logical_device.set_object_name(image, &format!("swapchain image {}", index));
                     let create_view_info = vk::ImageViewCreateInfo::default()
                         .view_type(vk::ImageViewType::TYPE_2D)
                         .format(surface_format.format)
@@ -69,7 +77,9 @@ pub fn get_swapchain_image_views(logical_device: Arc<Mutex<ash::Device>>, swapch
                             layer_count: 1,
                         })
                         .image(image);
-                    locked_device.create_image_view(&create_view_info, None).unwrap()
+                    let view = logical_device.create_image_view(&create_view_info, None).unwrap();
+                    logical_device.set_object_name(view, &format!("swapchain image view {}", index));
+                    view
                 })
                 .collect();
 
@@ -1,26 +1,42 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::mem;
 
 use ash::util::Align;
-use ash::{vk, Device};
+use ash::vk;
 
 use super::vulkan_image::VulkanImage;
 use super::vulkan_sampler::VulkanSampler;
-use super::{dimensions::Dimensions, memory::find_memorytype_index};
+use super::{device_context::DeviceContext, dimensions::Dimensions, gpu_timeline::LastUse, memory::find_memorytype_index};
 
+/// Color data is stored sRGB encoded so sampling returns linear values, data maps like luts and normals are sampled as stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureEncoding {
+  Srgb,
+  Linear,
+}
+
+impl TextureEncoding {
+  pub fn format(&self) -> vk::Format {
+    match self {
+      Self::Srgb => vk::Format::R8G8B8A8_SRGB,
+      Self::Linear => vk::Format::R8G8B8A8_UNORM,
+    }
+  }
+}
 
 pub struct VulkanTexture {
-  pub device: Arc<Mutex<Device>>,
+  pub device: Arc<DeviceContext>,
   pub texture_image: vk::Image,
   pub format: vk::Format,
   pub texture_memory: vk::DeviceMemory,
+  pub last_use: LastUse,
 }
 
 impl VulkanTexture {
-  pub unsafe fn new_from_image(vulkan_image: &VulkanImage, device: Arc<Mutex<Device>>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
+  pub unsafe fn new_from_image(vulkan_image: &VulkanImage, encoding: TextureEncoding, device: Arc<DeviceContext>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
     let texture_create_info = vk::ImageCreateInfo {
         image_type: vk::ImageType::TYPE_2D,
-        format: vk::Format::R8G8B8A8_UNORM,
+        format: encoding.format(),
         extent: vulkan_image.extent().into(),
         mip_levels: 1,
         array_layers: 1,
@@ -30,13 +46,11 @@ impl VulkanTexture {
         sharing_mode: vk::SharingMode::EXCLUSIVE,
         ..Default::default()
     };
-    let locked_device = device.clone();
-    let locked_device = locked_device.lock().unwrap();
 
-    let texture_image = locked_device
+    let texture_image = device
         .create_image(&texture_create_info, None)
         .unwrap();
-    let texture_memory_req = locked_device.get_image_memory_requirements(texture_image);
+    let texture_memory_req = device.get_image_memory_requirements(texture_image);
     let texture_memory_index = find_memorytype_index(
         &texture_memory_req,
         &device_memory_properties,
@@ -49,10 +63,10 @@ impl VulkanTexture {
         memory_type_index: texture_memory_index,
         ..Default::default()
     };
-    let texture_memory = locked_device
+    let texture_memory = device
         .allocate_memory(&texture_allocate_info, None)
         .unwrap();
-    locked_device
+    device
         .bind_image_memory(texture_image, texture_memory, 0)
         .expect("Unable to bind depth image memory");
 
@@ -61,17 +75,18 @@ impl VulkanTexture {
       texture_image,
       format: texture_create_info.format,
       texture_memory,
+      last_use: LastUse::default(),
     }
   }
 }
 
 impl Drop for VulkanTexture {
   fn drop(&mut self) {
-    let locked_device = self.device.lock().unwrap();
-    unsafe {
-        locked_device.free_memory(self.texture_memory, None);
-        locked_device.destroy_image(self.texture_image, None);
-    }
+    let (texture_memory, texture_image) = (self.texture_memory, self.texture_image);
+    self.device.retire(&self.last_use, move |device| unsafe {
+        device.free_memory(texture_memory, None);
+        device.destroy_image(texture_image, None);
+    });
   }
 }
 
@@ -99,12 +114,13 @@ impl Drop for VulkanTexture {
 //     .unwrap();
 
 pub struct VulkanTextureView {
-  pub device: Arc<Mutex<Device>>,
+  pub device: Arc<DeviceContext>,
   pub texture_image_view: vk::ImageView,
+  pub last_use: LastUse,
 }
 
 impl VulkanTextureView {
-  pub fn new(device: Arc<Mutex<Device>>, texture: &VulkanTexture) -> Self {
+  pub fn new(device: Arc<DeviceContext>, texture: &VulkanTexture) -> Self {
     let tex_image_view_info = vk::ImageViewCreateInfo {
         view_type: vk::ImageViewType::TYPE_2D,
         format: texture.format,
@@ -123,16 +139,15 @@ impl VulkanTextureView {
         image: texture.texture_image,
         ..Default::default()
     };
-    let locked_device = device.clone();
-    let locked_device = locked_device.lock().unwrap();
 
-    let texture_image_view = unsafe { locked_device
+    let texture_image_view = unsafe { device
         .create_image_view(&tex_image_view_info, None)
         .unwrap() };
 
     Self {
       device,
       texture_image_view,
+      last_use: LastUse::default(),
     }
   }
 
@@ -147,9 +162,9 @@ impl VulkanTextureView {
 
 impl Drop for VulkanTextureView {
   fn drop(&mut self) {
-    let locked_device = self.device.lock().unwrap();
-    unsafe {
-        locked_device.destroy_image_view(self.texture_image_view, None);
-    }
+    let texture_image_view = self.texture_image_view;
+    self.device.retire(&self.last_use, move |device| unsafe {
+        device.destroy_image_view(texture_image_view, None);
+    });
   }
 }
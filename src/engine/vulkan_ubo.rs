@@ -1,22 +1,23 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::mem;
 
 use ash::util::Align;
-use ash::{vk, Device};
+use ash::vk;
 
 use super::vec3::Vector3;
 use super::vulkan_image::VulkanImage;
-use super::{dimensions::Dimensions, memory::find_memorytype_index};
+use super::{device_context::DeviceContext, dimensions::Dimensions, gpu_timeline::LastUse, memory::find_memorytype_index};
 
 pub struct VulkanUniformBufferObject {
-  pub device: Arc<Mutex<Device>>,
+  pub device: Arc<DeviceContext>,
   pub uniform_color_buffer: vk::Buffer,
   pub uniform_color_buffer_memory: vk::DeviceMemory,
   pub color_vector: Vector3,
+  pub last_use: LastUse,
 }
 
 impl VulkanUniformBufferObject {
-  pub unsafe fn new_from_vec3(uniform_color_buffer_data: Vector3, device: Arc<Mutex<Device>>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
+  pub unsafe fn new_from_vec3(uniform_color_buffer_data: Vector3, device: Arc<DeviceContext>, device_memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
     let uniform_color_buffer_info = vk::BufferCreateInfo {
         size: mem::size_of_val(&uniform_color_buffer_data) as u64,
         usage: vk::BufferUsageFlags::UNIFORM_BUFFER,
@@ -24,13 +25,10 @@ impl VulkanUniformBufferObject {
         ..Default::default()
     };
 
-    let locked_device = device.clone();
-    let locked_device = locked_device.lock().unwrap();
-
-    let uniform_color_buffer = locked_device
+    let uniform_color_buffer = device
         .create_buffer(&uniform_color_buffer_info, None)
         .unwrap();
-    let uniform_color_buffer_memory_req = locked_device
+    let uniform_color_buffer_memory_req = device
         .get_buffer_memory_requirements(uniform_color_buffer);
     let uniform_color_buffer_memory_index = find_memorytype_index(
         &uniform_color_buffer_memory_req,
@@ -44,10 +42,10 @@ impl VulkanUniformBufferObject {
         memory_type_index: uniform_color_buffer_memory_index,
         ..Default::default()
     };
-    let uniform_color_buffer_memory = locked_device
+    let uniform_color_buffer_memory = device
         .allocate_memory(&uniform_color_buffer_allocate_info, None)
         .unwrap();
-    let uniform_ptr = locked_device
+    let uniform_ptr = device
         .map_memory(
             uniform_color_buffer_memory,
             0,
@@ -61,8 +59,8 @@ impl VulkanUniformBufferObject {
         uniform_color_buffer_memory_req.size,
     );
     uniform_aligned_slice.copy_from_slice(&[uniform_color_buffer_data]);
-    locked_device.unmap_memory(uniform_color_buffer_memory);
-    locked_device
+    device.unmap_memory(uniform_color_buffer_memory);
+    device
         .bind_buffer_memory(uniform_color_buffer, uniform_color_buffer_memory, 0)
         .unwrap();
 
@@ -71,6 +69,7 @@ impl VulkanUniformBufferObject {
         device: device.clone(),
         uniform_color_buffer,
         uniform_color_buffer_memory,
+        last_use: LastUse::default(),
     }
   }
 
@@ -86,10 +85,10 @@ impl VulkanUniformBufferObject {
 
 impl Drop for VulkanUniformBufferObject {
   fn drop(&mut self) {
-    let locked_device = self.device.lock().unwrap();
-    unsafe {
-        locked_device.free_memory(self.uniform_color_buffer_memory, None);
-        locked_device.destroy_buffer(self.uniform_color_buffer, None);
-    }
+    let (uniform_color_buffer_memory, uniform_color_buffer) = (self.uniform_color_buffer_memory, self.uniform_color_buffer);
+    self.device.retire(&self.last_use, move |device| unsafe {
+        device.free_memory(uniform_color_buffer_memory, None);
+        device.destroy_buffer(uniform_color_buffer, None);
+    });
   }
 }
@@ -8,736 +8,402 @@
 )]
 
 mod engine;
-use engine::{coherent_quads::CoherentQuads, commandbuffer::{record_submit_commandbuffer, submit_commandbuffer_to_ensure_depth_image_format, submit_commandbuffer_to_load_image}, debugging::VulkanDebugger, image_manager::ImageManager, vec3::Vector3, vertex::Vertex, vertex_generation::make_quad_vertices, vulkan_bindings::{make_image_sampler_fragment_layout_binding, make_ubo_fragment_layout_binding}, vulkan_commands::{create_command_buffers, get_device_presentation_queue, VulkanCommandPool}, vulkan_depth_image::VulkanDepthImage, vulkan_descriptor::{make_image_sampler_pool_size, make_ubo_pool_size, update_device_descriptor_sets, VulkanDescriptorPool, VulkanDescriptorSetLayouts}, vulkan_fences::create_standard_fences, vulkan_framebuffer::VulkanFramebuffers, vulkan_image::VulkanImage, vulkan_instance::make_vulkan_instance, vulkan_logical_device::{make_logical_device, make_swapchain_device}, vulkan_physical_device::get_physical_device_and_family_that_support, vulkan_pipeline::{VulkanPipeline, VulkanPipelineLayout}, vulkan_render_pass::VulkanColorDepthRenderPass, vulkan_sampler::VulkanSampler, vulkan_semaphores::create_semaphores, vulkan_shaders::VulkanShader, vulkan_surface::VulkanSurface, vulkan_swapchain::{create_standard_swapchain, get_swapchain_image_views}, vulkan_texture::{VulkanTexture, VulkanTextureView}, vulkan_ubo::VulkanUniformBufferObject, winit_window::{get_window_resolution, make_winit_window}};
-
-use std::{
-    cell::RefCell, default::Default, error::Error, ffi, ops::Drop, sync::{mpsc, Arc, Mutex}
-};
-
-use ash::{
-    khr::swapchain,
-    vk, Device, Entry, Instance,
-};
-use winit::{
-    event::{ElementState, Event, KeyEvent, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    keyboard::{Key, NamedKey},
-    platform::run_on_demand::EventLoopExtRunOnDemand,
-    window::Window,
-};
-
-use std::io::Cursor;
-use std::mem;
-
-use crate::engine::input_state::{InputState, InputStateEvent};
-
-pub fn render_loop<F: FnMut(bool)>(event_loop: RefCell<EventLoop<()>>, event_sender: mpsc::Sender<InputStateEvent>, mut render: F) -> Result<(), impl Error> {
-    event_loop.borrow_mut().run_on_demand(|event, elwp| {
-        elwp.set_control_flow(ControlFlow::Poll);
-        match event {
-            Event::WindowEvent {
-                event:
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        event:
-                            KeyEvent {
-                                state: ElementState::Pressed,
-                                logical_key: Key::Named(NamedKey::Escape),
-                                ..
-                            },
-                        ..
-                    },
-                ..
-            } => {
-                elwp.exit();
-            }
-            Event::WindowEvent { event: WindowEvent::CursorMoved { device_id, position }, .. } => {
-                event_sender.send(InputStateEvent::CursorMoved((position.x, position.y))).unwrap();
-            }
-            Event::WindowEvent { event: WindowEvent::MouseInput { device_id, state, button }, .. } => {
-                let button_index = match button {
-                    winit::event::MouseButton::Left => 0,
-                    winit::event::MouseButton::Right => 1,
-                    winit::event::MouseButton::Middle => 2,
-                    _ => return,
-                };
-
-                match state {
-                    ElementState::Pressed => {
-                        event_sender.send(InputStateEvent::MouseButtonPressed(button_index)).unwrap();
-                    }
-                    ElementState::Released => {
-                        event_sender.send(InputStateEvent::MouseButtonReleased(button_index)).unwrap();
-                    }
-                }
-            }
-            Event::WindowEvent {
-                event: WindowEvent::Resized(_),
-                ..
-            } => {
-                render(true);
-            }
-            Event::AboutToWait => render(false),
-            _ => (),
-        }
-    })
+use engine::{app::{run_app, App, AppContext, Frame}, clip_stack::record_clip_commands, coherent_quads::CoherentQuads, input_actions::{AxisBinding, Binding, WheelDirection}, keyboard::{Key, Modifiers}, mouse::MouseButton, post_processing::PostProcessEffect, presentation::VsyncMode, shader_manifest::ShaderVariantKey, vec3::Vector3, vertex_generation::make_quad_vertices, vulkan_image::VulkanImage, vulkan_pipeline::PipelineConfig};
+
+use std::error::Error;
+
+use ash::vk;
+
+const QUAD_QUANTITY: u32 = 100;
+/// Frame rate the fps limit toggle caps to
+const DEMO_FPS_LIMIT: f32 = 30.0;
+/// Vignette intensity change per second while its keys are held
+const VIGNETTE_INTENSITY_SPEED: f32 = 1.0;
+const CONTROL: Modifiers = Modifiers { shift: false, control: true, alt: false, super_key: false };
+
+/// Every action and axis the demo binds with what it does, F1 logs them with the current bindings
+const CONTROLS: [(&str, &str); 13] = [
+    ("pause", "pause the animation"),
+    ("follow_cursor", "move the quads to the cursor"),
+    ("grayscale", "grayscale while held"),
+    ("clip", "clip the quads to the first one"),
+    ("vignette", "toggle the vignette"),
+    ("vignette_intensity", "vignette intensity"),
+    ("post_processing", "toggle post processing"),
+    ("vsync", "cycle vsync modes"),
+    ("more_samples", "double the msaa samples"),
+    ("fewer_samples", "halve the msaa samples"),
+    ("fps_limit", "toggle the fps limit"),
+    ("save_bindings", "save the bindings"),
+    ("quit", "quit"),
+];
+
+/// Where a quad is after a fixed update, drawn interpolated between the last two
+#[derive(Clone, Copy)]
+struct QuadState {
+    x: f32,
+    y: f32,
+    size: f32,
 }
 
-pub struct PipelineData {
-    pub texture: VulkanTexture,
-    pub sampler: VulkanSampler,
-    pub texture_view: VulkanTextureView,
-    pub descriptor_pool: VulkanDescriptorPool,
-    pub descriptor_set_layouts: VulkanDescriptorSetLayouts,
-    pub vertex_shader: VulkanShader,
-    pub fragment_shader: VulkanShader,
-    pub pipeline_layout: VulkanPipelineLayout,
-    pub viewports: [vk::Viewport; 1],
-    pub scissors: [vk::Rect2D; 1],
-    pub graphics_pipelines: VulkanPipeline, // must be last for automatic drop to be last, https://github.com/rust-lang/rfcs/blob/246ff86b320a72f98ed2df92805e8e3d48b402d6/text/1857-stabilize-drop-order.md
-}
-
-pub struct PipelineExtras {
-    vertex_bytes: Vec<u8>,
-    frag_bytes: Vec<u8>,
-    raw_ubo_data: Vec<Vector3>,
+impl QuadState {
+    fn lerp(self, next: Self, alpha: f32) -> Self {
+        Self {
+            x: self.x + (next.x - self.x) * alpha,
+            y: self.y + (next.y - self.y) * alpha,
+            size: self.size + (next.size - self.size) * alpha,
+        }
+    }
 }
 
-pub struct VulkanBase {
-    pub entry: Entry,
-    pub instance: Instance,
-    pub device: Arc<Mutex<Device>>,
-    pub swapchain_device: swapchain::Device,
-    pub window: Arc<Mutex<Window>>,
-    pub depth_image: Option<VulkanDepthImage>,
-    pub debugger: Option<VulkanDebugger>,
-    pub command_pool: Option<VulkanCommandPool>,
-    pub surface: Option<VulkanSurface>,
-    pub renderpass: Option<VulkanColorDepthRenderPass>,
-    pub framebuffers: Option<VulkanFramebuffers>,
-
-    pub pdevice: vk::PhysicalDevice,
-    pub device_memory_properties: vk::PhysicalDeviceMemoryProperties,
-    pub queue_family_index: u32,
-    pub present_queue: vk::Queue,
-
-    pub surface_format: vk::SurfaceFormatKHR,
-    pub surface_resolution: vk::Extent2D,
-
-    pub swapchain: vk::SwapchainKHR,
-    pub present_images: Vec<vk::Image>,
-    pub present_image_views: Vec<vk::ImageView>,
-
-    pub draw_command_buffer: vk::CommandBuffer,
-    pub setup_command_buffer: vk::CommandBuffer,
-
-    pub present_complete_semaphores: Vec<vk::Semaphore>,
-    pub rendering_complete_semaphores: Vec<vk::Semaphore>,
-
-    pub draw_commands_reuse_fence: vk::Fence,
-    pub setup_commands_reuse_fence: vk::Fence,
-
-    pub pipeline_data: Option<PipelineData>,
-    pub pipeline_extras: Option<PipelineExtras>,
-
-    pub current_swapchain_image: RefCell<usize>,
-    pub frame: RefCell<usize>,
-    pub image_manager: ImageManager,
+struct QuadsDemo {
+    /// One copy per frame in flight, a frame rewrites its own while the GPU may still draw the others
+    quads: Vec<CoherentQuads>,
+    previous_states: Vec<QuadState>,
+    states: Vec<QuadState>,
+    /// Fixed updates so far, the animation advances per fixed step so it runs at the same speed at any frame rate
+    ticks: u64,
+    paused: bool,
+    clipped: bool,
+    /// Pixels the quads are clipped to, the middle of the window until one is dragged out
+    clip_rect: Option<vk::Rect2D>,
+    drag_start: Option<(f64, f64)>,
+    /// Frame count and time when F4 went down
+    measure_start: Option<(u64, f64)>,
+    vignette_intensity: f32,
 }
 
-impl VulkanBase {
-    pub fn shared_device(&self) -> Arc<Mutex<Device>> {
-        self.device.clone()
-    }
-
-    pub fn get_next_swapchain_image_index(&self) -> usize {
-        let current_swapchain_image = *self.current_swapchain_image.borrow();
-        *self.current_swapchain_image.borrow_mut() = (current_swapchain_image + 1)
-            % self.present_images.len();
-        current_swapchain_image
-    }
-
-    pub fn increment_frame(&self) -> usize {
-        let frame = *self.frame.borrow();
-        *self.frame.borrow_mut() = frame + 1;
-        frame
-    }
-
-    pub fn new(window: Arc<Mutex<Window>>) -> Result<Self, Box<dyn Error>> {
+impl App for QuadsDemo {
+    fn init(context: &mut AppContext) -> Result<Self, Box<dyn Error>> {
         unsafe {
-            let entry = Entry::linked();
-            let title = {
-                let locked_window = window.clone();
-                let locked_window = locked_window.lock().unwrap();
-                locked_window.title()
-            };
-
-            let instance = make_vulkan_instance(title.as_str(), &entry, window.clone())?;
-
-
-            let debugger = match option_env!("VULKAN_DEBUG") {
-                Some("1") => Some(VulkanDebugger::new(&entry, &instance)),
-                _ => None,
+            let base = &mut context.base;
+            let vertices = make_quad_vertices(0.0, 0.0, 0.5, 0.5, 0.0);
+
+            let quads: Vec<CoherentQuads> = (0..base.frames_in_flight())
+                .map(|slot| {
+                    let mut quads = CoherentQuads::new(QUAD_QUANTITY, base.shared_device(), base.device_memory_properties);
+                    for _ in 0..QUAD_QUANTITY {
+                        quads.add_quad(vertices.clone());
+                    }
+                    quads.remap_data();
+                    base.device.set_object_name(quads.device_vertex_buffer, &format!("quad vertices {}", slot));
+                    base.device.set_object_name(quads.device_index_buffer, &format!("quad indices {}", slot));
+                    quads
+                })
+                .collect();
+
+            let uniform_color_buffer_data = Vector3 {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+                _pad: 0.0,
             };
-            let surf = VulkanSurface::new(&entry, &instance, window.clone());
-            let (pdevice, queue_family_index) = get_physical_device_and_family_that_support(&instance, &surf.surface_loader, surf.surface);
-            let device = make_logical_device(&instance, pdevice, queue_family_index);
-            let present_queue = get_device_presentation_queue(device.clone(), queue_family_index);
-            let surface_format = surf.get_format(&pdevice);
-            let surface_resolution = surf.get_resolution(get_window_resolution(window.clone()), &pdevice);
-            let swapchain_device = make_swapchain_device(&instance, device.clone());
-            let swapchain = create_standard_swapchain(&pdevice, &surf, surface_format, surface_resolution, &swapchain_device);
-            let (present_images, present_image_views) = get_swapchain_image_views(device.clone(), &swapchain_device, swapchain, surface_format);
-
-            let command_pool = VulkanCommandPool::new(device.clone(), queue_family_index);
-            let (setup_command_buffer, draw_command_buffer) = create_command_buffers(&command_pool, device.clone());
-            let device_memory_properties = instance.get_physical_device_memory_properties(pdevice);
-            let depth_img = VulkanDepthImage::new(surface_resolution, device.clone(), device_memory_properties);
-
-            let fences = create_standard_fences(device.clone(), 2);
-            let (draw_commands_reuse_fence, setup_commands_reuse_fence) = (fences[0], fences[1]);
-
-
-            submit_commandbuffer_to_ensure_depth_image_format(
-                device.clone(),
-                setup_command_buffer,
-                setup_commands_reuse_fence,
-                present_queue,
-                &depth_img,
-            );
-
-            let present_complete_semaphores = create_semaphores(device.clone(), present_images.len());
-            let rendering_complete_semaphores = create_semaphores(device.clone(), present_images.len());
 
-            let renderpass = VulkanColorDepthRenderPass::new(device.clone(), surface_format.format);
+            let raw_ubo_data = vec![uniform_color_buffer_data];
 
-            let framebuffers = VulkanFramebuffers::new(
-                device.clone(),
-                surface_resolution,
-                &renderpass,
-                &depth_img,
-                &present_image_views,
-            );
+            let vertex_shader_key = ShaderVariantKey::default_variant("texture/texture.vert");
+            let fragment_shader_key = ShaderVariantKey::default_variant("texture/texture.frag");
 
+            base.add_image("sprite", VulkanImage::new_from_bytes(include_bytes!("../assets/rust.png"), base.shared_device(), base.device_memory_properties));
+            match base.enable_post_processing(&[PostProcessEffect::Vignette]) {
+                Ok(()) => log::info!("Post processing for a {:?} display", base.post_processing.as_ref().unwrap().display_encoding()),
+                Err(error) => log::warn!("Running without post processing: {}", error),
+            }
+            base.create_pipeline(vertex_shader_key, fragment_shader_key, raw_ubo_data, PipelineConfig::stencil_clipped());
+
+            log::info!("Finished pipeline creation");
+
+            let actions = &mut context.actions;
+            actions.bind("pause", Binding::key(Key::Space));
+            actions.bind("follow_cursor", Binding::mouse(MouseButton::Left));
+            actions.bind("grayscale", Binding::mouse(MouseButton::Right));
+            actions.bind("clip", Binding::key(Key::C));
+            actions.bind("vignette", Binding::key(Key::V));
+            actions.bind_axis("vignette_intensity", AxisBinding::buttons(Binding::key(Key::Minus), Binding::key(Key::Equal)));
+            actions.bind("post_processing", Binding::key(Key::P));
+            actions.bind("vsync", Binding::key(Key::F2));
+            actions.bind("more_samples", Binding::wheel(WheelDirection::Up));
+            actions.bind("fewer_samples", Binding::wheel(WheelDirection::Down));
+            actions.bind("fps_limit", Binding::key(Key::F3));
+            actions.bind("save_bindings", Binding::key(Key::S).with_modifiers(CONTROL));
+            actions.bind("quit", Binding::key(Key::Q).with_modifiers(CONTROL));
+            log::info!("Press F1 to list the controls");
+
+            let state = QuadState { x: 0.0, y: 0.0, size: 0.5 };
             Ok(Self {
-                entry,
-                instance,
-                device,
-                queue_family_index,
-                pdevice,
-                device_memory_properties,
-                window,
-                surface_format,
-                present_queue,
-                surface_resolution,
-                swapchain_device,
-                swapchain,
-                present_images,
-                present_image_views,
-                draw_command_buffer,
-                setup_command_buffer,
-                present_complete_semaphores,
-                rendering_complete_semaphores,
-                draw_commands_reuse_fence,
-                setup_commands_reuse_fence,
-                current_swapchain_image: RefCell::new(0),
-                frame: RefCell::new(0),
-                depth_image: Some(depth_img),
-                debugger: debugger,
-                command_pool: Some(command_pool),
-                surface: Some(surf),
-                renderpass: Some(renderpass),
-                framebuffers: Some(framebuffers),
-                pipeline_data: None,
-                pipeline_extras: None,
-                image_manager: ImageManager::new(),
+                quads,
+                previous_states: vec![state; QUAD_QUANTITY as usize],
+                states: vec![state; QUAD_QUANTITY as usize],
+                ticks: 0,
+                paused: false,
+                clipped: false,
+                clip_rect: None,
+                drag_start: None,
+                measure_start: None,
+                vignette_intensity: 1.0,
             })
         }
     }
 
-    pub fn add_image(&mut self, name: &'static str, image: VulkanImage) {
-        self.image_manager.add_image(name, image);
-    }
-
-    pub unsafe fn create_pipeline(&mut self, vertex_bytes: Vec<u8>, frag_bytes: Vec<u8>, ubo: Vec<Vector3>) {
-        self.pipeline_extras = Some(PipelineExtras {
-            vertex_bytes: vertex_bytes.clone(),
-            frag_bytes: frag_bytes.clone(),
-            raw_ubo_data: ubo.clone(),
-        });
-
-        let ubo = VulkanUniformBufferObject::new_from_vec3(ubo[0], self.shared_device(), self.device_memory_properties);
-        let img = self.image_manager.get_image("sprite");
-        let images = vec![img];
-
-        let tex = VulkanTexture::new_from_image(&images[0], self.device.clone(), self.device_memory_properties);
-        submit_commandbuffer_to_load_image(self.device.clone(), self.setup_command_buffer, self.setup_commands_reuse_fence, self.present_queue, &tex, &images[0]);
-
-        let samplr = VulkanSampler::new(self.device.clone());
-        let texview = VulkanTextureView::new(self.device.clone(), &tex);
-
-        let descriptor_sizes = vec![make_ubo_pool_size(1), make_image_sampler_pool_size(1)];
-        let desc_layout_bindings = vec![
-            make_ubo_fragment_layout_binding(1, 1),
-            make_image_sampler_fragment_layout_binding(1, 1),
-        ];
-
-        let mut descriptor_pool = VulkanDescriptorPool::new(self.device.clone(), descriptor_sizes);
-
-        let descriptor_set_layouts = VulkanDescriptorSetLayouts::new(self.device.clone(), desc_layout_bindings);
-        descriptor_pool.create_source_descriptor_sets_releasing_old(&descriptor_set_layouts);
-
-        let uniform_color_buffer_descriptor = ubo.get_descriptor_info(0);
-
-        let tex_descriptor = texview.get_descriptor_info(&samplr);
-
-        let write_desc_sets = vec![
-            vk::WriteDescriptorSet {
-                dst_set: descriptor_pool.source_descriptor_sets[0],
-                descriptor_count: 1,
-                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                p_buffer_info: &uniform_color_buffer_descriptor,
-                ..Default::default()
-            },
-            vk::WriteDescriptorSet {
-                dst_set: descriptor_pool.source_descriptor_sets[0],
-                dst_binding: 1,
-                descriptor_count: 1,
-                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                p_image_info: &tex_descriptor,
-                ..Default::default()
-            },
-        ];
-
-        update_device_descriptor_sets(self.device.clone(), &write_desc_sets);
-
-        let vertex_spv_file = Cursor::new(vertex_bytes.as_slice());
-        let frag_spv_file = Cursor::new(frag_bytes.as_slice());
-        let vertex_shader = VulkanShader::new(self.device.clone(), vertex_spv_file);
-        let fragment_shader = VulkanShader::new(self.device.clone(), frag_spv_file);
-
-        let pipeline_layout = VulkanPipelineLayout::new(self.device.clone(), &descriptor_set_layouts);
-
-        let shader_entry_name = ffi::CString::new("main").unwrap();
-        let shader_entry_name = shader_entry_name.as_c_str();
-        let shader_stage_create_infos = [
-            vk::PipelineShaderStageCreateInfo {
-                module: vertex_shader.shader_module,
-                p_name: shader_entry_name.as_ptr(),
-                stage: vk::ShaderStageFlags::VERTEX,
-                ..Default::default()
-            },
-            vk::PipelineShaderStageCreateInfo {
-                module: fragment_shader.shader_module,
-                p_name: shader_entry_name.as_ptr(),
-                stage: vk::ShaderStageFlags::FRAGMENT,
-                ..Default::default()
-            },
-        ];
-
-        let vertex_input_binding_descriptions = [vk::VertexInputBindingDescription {
-            binding: 0,
-            stride: mem::size_of::<Vertex>() as u32,
-            input_rate: vk::VertexInputRate::VERTEX,
-        }];
-
-        let vertex_input_attribute_descriptions = Vertex::get_attribute_descriptions();
-        let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo::default()
-            .vertex_attribute_descriptions(&vertex_input_attribute_descriptions)
-            .vertex_binding_descriptions(&vertex_input_binding_descriptions);
-
-        let vertex_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo {
-            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-            ..Default::default()
-        };
-
-        let viewports = [vk::Viewport {
-            x: 0.0,
-            y: 0.0,
-            width: self.surface_resolution.width as f32,
-            height: self.surface_resolution.height as f32,
-            min_depth: 0.0,
-            max_depth: 1.0,
-        }];
-        let scissors = [self.surface_resolution.into()];
-        let viewport_state_info = vk::PipelineViewportStateCreateInfo::default()
-            .scissors(&scissors)
-            .viewports(&viewports);
-
-        let rasterization_info = vk::PipelineRasterizationStateCreateInfo {
-            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
-            line_width: 1.0,
-            polygon_mode: vk::PolygonMode::FILL,
-            ..Default::default()
-        };
+    fn update(&mut self, context: &mut AppContext, dt: f32) {
+        if context.input.just_pressed(Key::F1) {
+            log_controls(context);
+        }
+        if context.actions.just_activated("quit") {
+            context.exit();
+        }
+        if context.actions.just_activated("save_bindings") {
+            match context.actions.save(&context.config.input_bindings) {
+                Ok(()) => log::info!("Saved the bindings to {}", context.config.input_bindings.display()),
+                Err(error) => log::warn!("{}", error),
+            }
+        }
+        if context.actions.just_activated("pause") {
+            self.paused = !self.paused;
+        }
+        if context.actions.just_activated("clip") {
+            self.clipped = !self.clipped;
+        }
+        let input = &context.input;
+        if input.mouse_just_pressed(MouseButton::Middle) {
+            self.drag_start = Some(input.cursor_position);
+        }
+        if let (true, Some(start)) = (input.mouse_just_released(MouseButton::Middle), self.drag_start.take()) {
+            self.clip_rect = Some(drag_rect(start, input.cursor_position));
+            self.clipped = true;
+        }
+        if input.just_pressed(Key::F4) {
+            self.measure_start = Some((context.clock.frame_count(), context.clock.total()));
+        }
+        if let (true, Some((start_frame, start_time))) = (input.just_released(Key::F4), self.measure_start.take()) {
+            let frames = context.clock.frame_count() - start_frame;
+            log::info!("{} frames in {:.2}s", frames, context.clock.total() - start_time);
+            log_statistics(context);
+        }
 
-        let multisample_state_info = vk::PipelineMultisampleStateCreateInfo::default()
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let actions = &context.actions;
+        if actions.just_activated("post_processing") {
+            if context.base.post_processing.is_some() {
+                context.base.disable_post_processing();
+            } else if let Err(error) = unsafe { context.base.enable_post_processing(&[PostProcessEffect::Vignette]) } {
+                log::warn!("Failed to enable post processing: {}", error);
+            }
+        }
+        if let Some(post_processing) = context.base.post_processing.as_mut() {
+            if actions.just_activated("grayscale") {
+                post_processing.set_enabled(PostProcessEffect::Grayscale, true);
+            } else if actions.just_deactivated("grayscale") {
+                post_processing.set_enabled(PostProcessEffect::Grayscale, false);
+            }
+            if actions.just_activated("vignette") {
+                post_processing.toggle(PostProcessEffect::Vignette);
+                log::info!("Vignette {}", if post_processing.is_enabled(PostProcessEffect::Vignette) { "on" } else { "off" });
+            }
+            self.vignette_intensity = (self.vignette_intensity + actions.axis("vignette_intensity") * VIGNETTE_INTENSITY_SPEED * dt).clamp(0.0, 2.0);
+            post_processing.set_intensity(PostProcessEffect::Vignette, self.vignette_intensity);
+        }
 
-        let noop_stencil_state = vk::StencilOpState {
-            fail_op: vk::StencilOp::KEEP,
-            pass_op: vk::StencilOp::KEEP,
-            depth_fail_op: vk::StencilOp::KEEP,
-            compare_op: vk::CompareOp::ALWAYS,
-            ..Default::default()
-        };
-        let depth_state_info = vk::PipelineDepthStencilStateCreateInfo {
-            depth_test_enable: 1,
-            depth_write_enable: 1,
-            depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
-            front: noop_stencil_state,
-            back: noop_stencil_state,
-            max_depth_bounds: 1.0,
-            ..Default::default()
+        if actions.just_activated("vsync") {
+            let vsync = match context.base.presentation_config.vsync {
+                VsyncMode::On => VsyncMode::Adaptive,
+                VsyncMode::Adaptive => VsyncMode::Off,
+                VsyncMode::Off => VsyncMode::On,
+            };
+            context.base.set_vsync(vsync);
+            log::info!("Vsync {:?}", vsync);
+        }
+        let samples = context.base.msaa_samples.as_raw();
+        let requested_samples = match (actions.just_activated("more_samples"), actions.just_activated("fewer_samples")) {
+            (true, false) => Some(samples * 2),
+            (false, true) => Some((samples / 2).max(1)),
+            _ => None,
         };
-
-        let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
-            blend_enable: 1,
-            src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
-            dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
-            color_blend_op: vk::BlendOp::ADD,
-            src_alpha_blend_factor: vk::BlendFactor::ONE,
-            dst_alpha_blend_factor: vk::BlendFactor::ONE,
-            alpha_blend_op: vk::BlendOp::ADD,
-            color_write_mask: vk::ColorComponentFlags::RGBA,
-        }];
-        let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
-            .logic_op(vk::LogicOp::CLEAR)
-            .attachments(&color_blend_attachment_states);
-
-        let dynamic_state = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
-        let dynamic_state_info =
-            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_state);
-
-        let graphic_pipeline_infos = vk::GraphicsPipelineCreateInfo::default()
-            .stages(&shader_stage_create_infos)
-            .vertex_input_state(&vertex_input_state_info)
-            .input_assembly_state(&vertex_input_assembly_state_info)
-            .viewport_state(&viewport_state_info)
-            .rasterization_state(&rasterization_info)
-            .multisample_state(&multisample_state_info)
-            .depth_stencil_state(&depth_state_info)
-            .color_blend_state(&color_blend_state)
-            .dynamic_state(&dynamic_state_info)
-            .layout(pipeline_layout.pipeline_layout)
-            .render_pass(self.renderpass.as_ref().unwrap().render_pass);
-
-        let graphics_pipelines = VulkanPipeline::new(self.device.clone(), graphic_pipeline_infos);
-
-        self.pipeline_data = Some(PipelineData {
-            graphics_pipelines,
-            pipeline_layout,
-            vertex_shader,
-            fragment_shader,
-            descriptor_pool,
-            descriptor_set_layouts,
-            sampler: samplr,
-            texture: tex,
-            texture_view: texview,
-            viewports,
-            scissors,
-        });
+        if let Some(requested_samples) = requested_samples {
+            context.base.set_msaa_samples(requested_samples);
+            log::info!("{} msaa samples", context.base.msaa_samples.as_raw());
+        }
+        if actions.just_activated("fps_limit") {
+            let fps_limit = match context.clock.fps_limit() {
+                Some(_) => None,
+                None => Some(DEMO_FPS_LIMIT),
+            };
+            context.clock.set_fps_limit(fps_limit);
+            log::info!("Fps limit {:?}", fps_limit);
+        }
     }
 
-    pub fn recreate_pipeline(&mut self, ) {
-        if self.pipeline_data.is_none() {
+    fn fixed_update(&mut self, context: &mut AppContext, _dt: f32) {
+        self.previous_states.copy_from_slice(&self.states);
+        if self.paused {
             return;
         }
+        let frame = self.ticks;
+        self.ticks += 1;
+        let input = &context.input;
+        let follow_cursor = context.actions.is_active("follow_cursor");
+
+        for (quad_id, state) in self.states.iter_mut().enumerate() {
+            let movement_factor: f32 = (frame as f32 + quad_id as f32 * 20.0) / 100.0;
+            let mv: (f32, f32) = (movement_factor.sin() / 10000.0, movement_factor.cos() / 10000.0);
+
+            let distance_from_zero = (frame as f32 / ((quad_id as f32 + 1.0) * 43.0)).sin() / 2.0 + 0.5;
+            let size = distance_from_zero * 2.0;
+            let x_position = match follow_cursor {
+                true => input.cursor_position.0 as f32 / context.base.surface_resolution.width as f32 * 2.0 - 1.0 - size / 2.0,
+                false => state.x + mv.0,
+            };
+            let y_position = match follow_cursor {
+                true => input.cursor_position.1 as f32 / context.base.surface_resolution.height as f32 * 2.0 - 1.0 - size / 2.0,
+                false => state.y + mv.1,
+            };
+            // let y_position = (frame as f32 / 43.0).sin() / 2.0 - 1.0;
 
-        self.pipeline_data = None;
-        unsafe {
-            self.create_pipeline(
-                self.pipeline_extras.as_ref().unwrap().vertex_bytes.clone(),
-                self.pipeline_extras.as_ref().unwrap().frag_bytes.clone(),
-                self.pipeline_extras.as_ref().unwrap().raw_ubo_data.clone(),
-            )
-        };
+            *state = QuadState { x: x_position, y: y_position, size };
+        }
     }
 
-    pub fn recreate_swapchain(&mut self, resolution: vk::Extent2D) {
-        unsafe {
-            self.renderpass = None;
-            self.framebuffers = None;
-            self.depth_image = None;
-            let device = self.device.lock().unwrap();
-            device.device_wait_idle().unwrap();
-            for &image_view in self.present_image_views.iter() {
-                device.destroy_image_view(image_view, None);
+    fn render(&mut self, frame: &mut Frame<'_>) {
+        let quads = &mut self.quads[frame.frame_slot];
+        frame.mark_used(&quads.last_use);
+        {
+            let _scope = frame.profiler.cpu_scope("remap_data");
+            for (quad_id, (previous, current)) in self.previous_states.iter().zip(self.states.iter()).enumerate() {
+                let state = previous.lerp(*current, frame.alpha);
+                quads.modify_quad(quad_id, make_quad_vertices(state.x, state.y, state.size, state.size, 0.0));
             }
-            self.swapchain_device
-                    .destroy_swapchain(self.swapchain, None);
+            quads.remap_data();
         }
 
-        self.surface_resolution = resolution;
-
-        let swapchain = create_standard_swapchain(&self.pdevice, &self.surface.as_ref().unwrap(), self.surface_format, self.surface_resolution, &self.swapchain_device);
-        let (present_images, present_image_views) = get_swapchain_image_views(self.device.clone(), &self.swapchain_device, swapchain, self.surface_format);
-
-        self.swapchain = swapchain;
-        self.present_images = present_images;
-        self.present_image_views = present_image_views;
-
-        self.renderpass = Some(VulkanColorDepthRenderPass::new(self.device.clone(), self.surface_format.format));
-        self.depth_image = Some(VulkanDepthImage::new(self.surface_resolution, self.device.clone(), self.device_memory_properties));
-
-        submit_commandbuffer_to_ensure_depth_image_format(
-                self.device.clone(),
-                self.setup_command_buffer,
-                self.setup_commands_reuse_fence,
-                self.present_queue,
-                &self.depth_image.as_ref().unwrap(),
+        // one draw per recording thread, a single draw would leave all but one worker idle
+        let recording_threads = frame.base.parallel_recorder(frame.frame_slot).thread_count() as u32;
+        let quad_quantity = quads.quad_quantity() as u32;
+        let mut clip_stack = frame.base.clip_stack();
+        if self.clipped {
+            // the first quad masks the rest inside the middle of the window, without stencil only its bounds clip
+            let resolution = frame.base.surface_resolution;
+            let mask = self.previous_states[0].lerp(self.states[0], frame.alpha);
+            clip_stack.draw(0..1);
+            clip_stack.push_rect(self.clip_rect.unwrap_or(vk::Rect2D {
+                offset: vk::Offset2D { x: resolution.width as i32 / 4, y: resolution.height as i32 / 4 },
+                extent: vk::Extent2D { width: resolution.width / 2, height: resolution.height / 2 },
+            }));
+            clip_stack.push_mask(ndc_rect(mask, resolution), 0..1);
+            clip_stack.draw_batches(1..quad_quantity, recording_threads);
+            clip_stack.pop();
+            clip_stack.pop();
+        } else {
+            clip_stack.draw_batches(0..quad_quantity, recording_threads);
+        }
+        let clip_commands = clip_stack.finish();
+        let clip_pipelines = frame.base.clip_pipelines();
+
+        // secondaries don't inherit bound state, every worker binds everything again for its share of the draws
+        let pipeline_data = frame.base.pipeline_data.as_ref().unwrap();
+        let pipeline_layout = pipeline_data.pipeline_layout.pipeline_layout;
+        let descriptor_set = pipeline_data.descriptor_sets[frame.frame_slot];
+        let viewports = pipeline_data.viewports;
+        let (vertex_buffer, index_buffer) = (quads.device_vertex_buffer, quads.device_index_buffer);
+        let profiler = frame.profiler;
+        let device_context = &frame.base.device;
+        frame.record_scene(&clip_commands, |device, command_buffer, clip_commands| unsafe {
+            device_context.begin_label(command_buffer, "quads", [0.4, 1.0, 0.4, 1.0]);
+            let scope = profiler.begin_gpu_scope(device, command_buffer, "quads");
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
             );
-
-        println!("Recreating framebuffers with size {:?}", self.surface_resolution);
-        self.framebuffers = Some(VulkanFramebuffers::new(
-            self.device.clone(),
-            self.surface_resolution,
-            &self.renderpass.as_ref().unwrap(),
-            &self.depth_image.as_ref().unwrap(),
-            &self.present_image_views,
-        ));
+            device.cmd_set_viewport(command_buffer, 0, &viewports);
+            device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[vertex_buffer],
+                &[0],
+            );
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+            record_clip_commands(device, command_buffer, clip_commands, &clip_pipelines);
+            // Or draw without the index buffer
+            // device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            profiler.end_gpu_scope(device, command_buffer, scope);
+            device_context.end_label(command_buffer);
+        });
     }
 }
 
-impl Drop for VulkanBase {
-    fn drop(&mut self) {
-        self.depth_image = None;
-        self.framebuffers = None;
-        self.renderpass = None;
-
-        unsafe {
-            {
-                let device = self.device.lock().unwrap();
-                device.device_wait_idle().unwrap();
-
-                for semaphore in self.present_complete_semaphores.iter() {
-                    device.destroy_semaphore(*semaphore, None);
-                }
-
-                for semaphore in self.rendering_complete_semaphores.iter() {
-                    device.destroy_semaphore(*semaphore, None);
-                }
-
-                device
-                    .destroy_fence(self.draw_commands_reuse_fence, None);
-                device
-                    .destroy_fence(self.setup_commands_reuse_fence, None);
-                for &image_view in self.present_image_views.iter() {
-                    device.destroy_image_view(image_view, None);
-                }
-                self.swapchain_device
-                    .destroy_swapchain(self.swapchain, None);
-            }
-            self.pipeline_data = None;
-            self.command_pool = None;
-            self.surface = None;
-            self.image_manager.clear();
-            {
-                let device = self.device.lock().unwrap();
-                device.destroy_device(None);
-                self.debugger = None;
-                self.instance.destroy_instance(None);
-            }
-        }
+fn drag_rect(start: (f64, f64), end: (f64, f64)) -> vk::Rect2D {
+    let (left, top) = (start.0.min(end.0).max(0.0), start.1.min(end.1).max(0.0));
+    vk::Rect2D {
+        offset: vk::Offset2D { x: left as i32, y: top as i32 },
+        extent: vk::Extent2D { width: (start.0.max(end.0) - left) as u32, height: (start.1.max(end.1) - top) as u32 },
     }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    unsafe {
-        let app_name = "Ash Base";
-
-        let (event_loop, window) = make_winit_window(app_name);
-
-        let mut base = VulkanBase::new(window.clone())?;
-
-        let vertices = make_quad_vertices(0.0, 0.0, 0.5, 0.5, 0.0);
-
-        let quad_quantity = 100;
-        let mut quads = CoherentQuads::new(quad_quantity, base.shared_device(), base.device_memory_properties);
-        for _ in 0..quad_quantity {
-            quads.add_quad(vertices.clone());
-        }
-        quads.remap_data();
-
-        let uniform_color_buffer_data = Vector3 {
-            x: 1.0,
-            y: 1.0,
-            z: 1.0,
-            _pad: 0.0,
-        };
-
-        let raw_ubo_data = vec![uniform_color_buffer_data];
-
-        let vertex_bytes = Vec::from(include_bytes!("../shader/texture/vert.spv"));
-        let frag_bytes = Vec::from(include_bytes!("../shader/texture/frag.spv"));
-
-        base.add_image("sprite", VulkanImage::new_from_bytes(include_bytes!("../assets/rust.png"), base.shared_device(), base.device_memory_properties));
-        base.create_pipeline(vertex_bytes, frag_bytes, raw_ubo_data);
-
-        println!("finished pipeline creation");
-
-        let inputstate = RefCell::new(InputState::new());
-        let event_sender = inputstate.borrow_mut().sender_clone();
-
-        let _ = render_loop(event_loop, event_sender, |recreate_swapchain| {
-            let frame = base.increment_frame();
-            inputstate.borrow_mut().consume_channel_events();
-            if recreate_swapchain && frame > 10 {
-                println!("Should recreate swapchain");
-                base.shared_device().lock().unwrap().device_wait_idle().unwrap();
-                let resolution = get_window_resolution(window.clone());
-                println!("Recreating swapchain with resolution {:?}", resolution);
-                base.recreate_swapchain(resolution);
-                base.recreate_pipeline();
-                return;
-            }
-            let current_swapchain_image = base.get_next_swapchain_image_index();
-
-            for quad_id in 0..quads.quad_quantity() {
-                let quad_current = quads.get_quad(quad_id);
-
-                let movement_factor: f32 = (frame as f32 + quad_id as f32 * 20.0) / 100.0;
-                let mv: (f32, f32) = (movement_factor.sin() / 10000.0, movement_factor.cos() / 10000.0);
-
-                let distance_from_zero = (frame as f32 / ((quad_id as f32 + 1.0) * 43.0)).sin() / 2.0 + 0.5;
-                let size = distance_from_zero * 2.0;
-                let x_position = match inputstate.borrow().mouse_buttons[0] {
-                    true => inputstate.borrow().cursor_position.0 as f32 / base.surface_resolution.width as f32 * 2.0 - 1.0 - size / 2.0,
-                    false => quad_current[0].pos[0] + mv.0,
-                };
-                let y_position = match inputstate.borrow().mouse_buttons[0] {
-                    true => inputstate.borrow().cursor_position.1 as f32 / base.surface_resolution.height as f32 * 2.0 - 1.0 - size / 2.0,
-                    false => quad_current[0].pos[1] + mv.1,
-                };
-                // let y_position = (frame as f32 / 43.0).sin() / 2.0 - 1.0;
-                let rotation = (frame as f32 / 43.0).cos() / 2.0;
-
-                quads.modify_quad(quad_id, make_quad_vertices(x_position, y_position, size, size, 0.0));
-            }
-
-            quads.remap_data();
+/// Pixel bounds of a quad given in normalized device coordinates
+fn ndc_rect(state: QuadState, resolution: vk::Extent2D) -> vk::Rect2D {
+    let to_pixels = |ndc: f32, pixels: u32| ((ndc + 1.0) / 2.0 * pixels as f32).clamp(0.0, pixels as f32);
+    let (left, right) = (to_pixels(state.x, resolution.width), to_pixels(state.x + state.size, resolution.width));
+    let (top, bottom) = (to_pixels(state.y, resolution.height), to_pixels(state.y + state.size, resolution.height));
+    vk::Rect2D {
+        offset: vk::Offset2D { x: left as i32, y: top as i32 },
+        extent: vk::Extent2D { width: (right - left).ceil() as u32, height: (bottom - top).ceil() as u32 },
+    }
+}
 
-            let acquisition_result = base
-                .swapchain_device
-                .acquire_next_image(
-                    base.swapchain,
-                    u64::MAX,
-                    base.present_complete_semaphores[current_swapchain_image],
-                    vk::Fence::null(),
-                );
-            let present_index = match acquisition_result {
-                Ok((present_index, _)) => {
-                    present_index
-                }
-                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                    println!("Swapchain out of date");
-                    return;
-                }
-                Err(e) => {
-                    panic!("Failed to acquire next image: {:?}", e);
-                }
-            };
-            let clear_values = [
-                vk::ClearValue {
-                    color: vk::ClearColorValue {
-                        float32: [0.0, 0.0, 0.0, 0.0],
-                    },
-                },
-                vk::ClearValue {
-                    depth_stencil: vk::ClearDepthStencilValue {
-                        depth: 1.0,
-                        stencil: 0,
-                    },
-                },
-            ];
-
-            let render_pass_begin_info = vk::RenderPassBeginInfo::default()
-                .render_pass(base.renderpass.as_ref().unwrap().render_pass)
-                .framebuffer(base.framebuffers.as_ref().unwrap().framebuffers[present_index as usize])
-                .render_area(base.surface_resolution.into())
-                .clear_values(&clear_values);
-
-            record_submit_commandbuffer(
-                &base.shared_device().lock().unwrap(),
-                base.draw_command_buffer,
-                base.draw_commands_reuse_fence,
-                base.present_queue,
-                &[vk::PipelineStageFlags::BOTTOM_OF_PIPE],
-                &[base.present_complete_semaphores[current_swapchain_image]],
-                &[base.rendering_complete_semaphores[current_swapchain_image]],
-                |device, draw_command_buffer| {
-                    device.cmd_begin_render_pass(
-                        draw_command_buffer,
-                        &render_pass_begin_info,
-                        vk::SubpassContents::INLINE,
-                    );
-                    device.cmd_bind_descriptor_sets(
-                        draw_command_buffer,
-                        vk::PipelineBindPoint::GRAPHICS,
-                        base.pipeline_data.as_ref().unwrap().pipeline_layout.pipeline_layout,
-                        0,
-                        &&base.pipeline_data.as_ref().unwrap().descriptor_pool.source_descriptor_sets[..],
-                        &[],
-                    );
-                    device.cmd_bind_pipeline(
-                        draw_command_buffer,
-                        vk::PipelineBindPoint::GRAPHICS,
-                        base.pipeline_data.as_ref().unwrap().graphics_pipelines.pipeline,
-                    );
-                    device.cmd_set_viewport(draw_command_buffer, 0, &base.pipeline_data.as_ref().unwrap().viewports);
-                    device.cmd_set_scissor(draw_command_buffer, 0, &base.pipeline_data.as_ref().unwrap().scissors);
-                    device.cmd_bind_vertex_buffers(
-                        draw_command_buffer,
-                        0,
-                        &[quads.device_vertex_buffer],
-                        &[0],
-                    );
-                    device.cmd_bind_index_buffer(
-                        draw_command_buffer,
-                        quads.device_index_buffer,
-                        0,
-                        vk::IndexType::UINT32,
-                    );
-                    device.cmd_draw_indexed(
-                        draw_command_buffer,
-                        quads.index_quantity() as u32,
-                        quads.quad_quantity() as u32,
-                        0,
-                        0,
-                        1,
-                    );
-                    // Or draw without the index buffer
-                    // device.cmd_draw(draw_command_buffer, 3, 1, 0, 0);
-                    device.cmd_end_render_pass(draw_command_buffer);
-                },
-            );
-            let present_info = vk::PresentInfoKHR {
-                wait_semaphore_count: 1,
-                p_wait_semaphores: &base.rendering_complete_semaphores[current_swapchain_image],
-                swapchain_count: 1,
-                p_swapchains: &base.swapchain,
-                p_image_indices: &present_index,
-                ..Default::default()
-            };
-            let presentation_result = base.swapchain_device
-                .queue_present(base.present_queue, &present_info);
-            match presentation_result {
-                Ok(_) => {}
-                Err(vk::Result::SUBOPTIMAL_KHR) => {
-                    println!("Swapchain suboptimal");
-                    return;
-                }
-                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-                    println!("Swapchain out of date");
-                    return;
-                }
-                Err(e) => {
-                    panic!("Failed to present: {:?}", e);
-                }
-            }
-        });
-        base.shared_device().lock().unwrap().device_wait_idle().unwrap();
+fn log_controls(context: &AppContext) {
+    let actions = &context.actions;
+    for (action, description) in CONTROLS {
+        let bindings: Vec<String> = actions.bindings(action).iter().map(ToString::to_string)
+            .chain(actions.axis_bindings(action).iter().map(ToString::to_string))
+            .collect();
+        log::info!("{}: {}", bindings.join(", "), description);
+    }
+    log::info!("MouseMiddle drag: pick the clip rectangle");
+    log::info!("F4 held: measure the frame rate until released");
+}
 
-        Ok(())
+/// Rolling frame times from the clock and the latest frame the profiler finished reading back
+fn log_statistics(context: &AppContext) {
+    let statistics = context.clock.statistics();
+    log::info!(
+        "{:.1} fps, frame times in ms min {:.2} average {:.2} p50 {:.2} p95 {:.2} p99 {:.2} max {:.2}",
+        statistics.average_fps(),
+        statistics.min * 1000.0,
+        statistics.average * 1000.0,
+        statistics.p50 * 1000.0,
+        statistics.p95 * 1000.0,
+        statistics.p99 * 1000.0,
+        statistics.max * 1000.0,
+    );
+
+    let Some(profiled_frame) = context.profiler.latest_frame() else {
+        return;
+    };
+    log::info!(
+        "Frame {} CPU in us update {:.0} render {:.0} submit {:.0}",
+        profiled_frame.frame_index,
+        profiled_frame.cpu_time_us("update"),
+        profiled_frame.cpu_time_us("render"),
+        profiled_frame.cpu_time_us("submit"),
+    );
+    if context.profiler.gpu_enabled() {
+        log::info!(
+            "Frame {} GPU in us total {:.0} scene {:.0} post processing {:.0}",
+            profiled_frame.frame_index,
+            profiled_frame.gpu_frame_time_us(),
+            profiled_frame.gpu_time_us("scene pass"),
+            profiled_frame.gpu_time_us("post processing"),
+        );
     }
 }
+
+fn main() -> Result<(), Box<dyn Error>> {
+    run_app::<QuadsDemo>("Ash Base")
+}